@@ -0,0 +1,14 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use lsys::lsystem::LSystem;
+
+// `cargo +nightly fuzz run parse`
+fuzz_target!(|data: &[u8]| {
+    if let Ok(input) = std::str::from_utf8(data) {
+        if let Ok(mut lsys) = LSystem::from_str(input) {
+            let _ = lsys.to_string();
+            let _ = lsys.nth(1);
+        };
+    }
+});
@@ -0,0 +1,118 @@
+use crate::geometry::Recorder;
+use crate::graphics::TurtleConfig;
+use crate::lsystem::LSystem;
+use crate::render::fit;
+
+/// Picks how many times to rewrite a system from the size it will be drawn at: generations are
+/// derived until the median line, once the figure is scaled to fit the output, is no longer than
+/// `target`. Sizes are in whatever unit the output uses, pixels or millimetres.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdaptiveDepth {
+    target: f32,
+    max_iterations: usize,
+    max_segments: usize,
+}
+
+impl Default for AdaptiveDepth {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AdaptiveDepth {
+    pub fn new() -> Self {
+        Self {
+            target: 1.0,
+            max_iterations: 20,
+            max_segments: 4_000_000,
+        }
+    }
+
+    pub fn target(self, target: f32) -> Self {
+        Self { target, ..self }
+    }
+
+    /// The deepest generation to derive even if lines are still too long.
+    pub fn max_iterations(self, max_iterations: usize) -> Self {
+        Self {
+            max_iterations,
+            ..self
+        }
+    }
+
+    /// A generation with more lines than this isn't used; the one before it is.
+    pub fn max_segments(self, max_segments: usize) -> Self {
+        Self {
+            max_segments,
+            ..self
+        }
+    }
+
+    /// The number of iterations chosen for a `width` by `height` output and the figure drawn at
+    /// that depth, unscaled.
+    pub fn expand(
+        &self,
+        lsystem: LSystem,
+        turtle: &TurtleConfig,
+        width: f32,
+        height: f32,
+    ) -> (usize, Recorder) {
+        let mut chosen = (0, Recorder::new());
+
+        for (iterations, word) in lsystem.take(self.max_iterations + 1).enumerate() {
+            let mut recorder = Recorder::new();
+            turtle.create_turtle().draw(&mut recorder, word).unwrap();
+            if iterations > 0 && recorder.segments().len() > self.max_segments {
+                break;
+            }
+
+            let fine = match median_length(&recorder, width, height) {
+                Some(length) => length <= self.target,
+                None => false,
+            };
+            chosen = (iterations, recorder);
+            if fine {
+                break;
+            }
+        }
+
+        chosen
+    }
+}
+
+fn median_length(recorder: &Recorder, width: f32, height: f32) -> Option<f32> {
+    let (_, scale) = fit(recorder, width, height)?;
+    let mut lengths: Vec<f32> = recorder
+        .segments()
+        .iter()
+        .map(|s| f32::hypot(s.end.0 - s.start.0, s.end.1 - s.start.1))
+        .collect();
+    lengths.sort_by(f32::total_cmp);
+    Some(lengths[lengths.len() / 2] * scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn depth_follows_output_size() {
+        // every generation of the Koch curve has lines a third as long, relative to the figure
+        let koch = LSystem::from_str("F; F -> F+F--F+F;").unwrap();
+        let turtle = TurtleConfig::default().delta_ang(std::f32::consts::FRAC_PI_3);
+        let depth = |size: f32| {
+            AdaptiveDepth::new()
+                .expand(koch.clone(), &turtle, size, size)
+                .0
+        };
+
+        assert_eq!(depth(1.0), 0);
+        assert_eq!(depth(100.0), 5);
+        assert_eq!(depth(300.0), 6);
+
+        let (iterations, recorder) = AdaptiveDepth::new()
+            .max_segments(100)
+            .expand(koch, &turtle, 1000.0, 1000.0);
+        assert_eq!((iterations, recorder.segments().len()), (3, 64));
+    }
+}
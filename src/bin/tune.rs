@@ -0,0 +1,138 @@
+use eframe::egui;
+
+use lsys::geometry::Recorder;
+use lsys::graphics::TurtleConfig;
+use lsys::lsystem::LSystem;
+use lsys::preset;
+
+const MAX_SEGMENTS: usize = 200_000;
+
+#[derive(Clone, PartialEq)]
+struct Params {
+    grammar: String,
+    draw_forward: String,
+    iterations: usize,
+    angle: f32,
+    step_decay: f32,
+    jitter: f32,
+    seed: u64,
+}
+
+struct Tuner {
+    params: Params,
+    rendered: Option<Params>,
+    recorder: Recorder,
+    error: Option<String>,
+}
+
+impl Tuner {
+    fn new() -> Self {
+        let preset = preset::TREE;
+        Self {
+            params: Params {
+                grammar: preset.grammar.into(),
+                draw_forward: preset.draw_forward.into(),
+                iterations: 5,
+                angle: preset.angle,
+                step_decay: 1.0,
+                jitter: 0.0,
+                seed: 0,
+            },
+            rendered: None,
+            recorder: Recorder::new(),
+            error: None,
+        }
+    }
+
+    fn render(&mut self) {
+        if self.rendered.as_ref() == Some(&self.params) {
+            return;
+        }
+        self.rendered = Some(self.params.clone());
+
+        let params = &self.params;
+        let lsystem = match LSystem::from_str(&params.grammar) {
+            Ok(lsystem) => lsystem,
+            Err(e) => {
+                self.error = Some(e.to_string());
+                return;
+            }
+        };
+        let word = lsystem.into_iter().nth(params.iterations).unwrap();
+
+        let config = TurtleConfig::default()
+            .delta_ang(params.angle.to_radians())
+            .draw_forward(&params.draw_forward)
+            .step_decay(params.step_decay)
+            .jitter(params.jitter.to_radians(), params.seed);
+        let mut recorder = Recorder::new();
+        config.create_turtle().draw(&mut recorder, word).unwrap();
+
+        if recorder.segments().len() > MAX_SEGMENTS {
+            self.error = Some(format!(
+                "{} segments is too many to draw",
+                recorder.segments().len()
+            ));
+            return;
+        }
+        self.error = None;
+        self.recorder = recorder;
+    }
+
+    fn controls(&mut self, ui: &mut egui::Ui) {
+        let params = &mut self.params;
+        ui.label("Grammar");
+        ui.text_edit_multiline(&mut params.grammar);
+        ui.label("Draw symbols");
+        ui.text_edit_singleline(&mut params.draw_forward);
+        ui.add(egui::Slider::new(&mut params.iterations, 0..=12).text("iterations"));
+        ui.add(egui::Slider::new(&mut params.angle, 0.0..=180.0).text("angle"));
+        ui.add(egui::Slider::new(&mut params.step_decay, 0.1..=1.5).text("step decay"));
+        ui.add(egui::Slider::new(&mut params.jitter, 0.0..=30.0).text("jitter"));
+        ui.add(egui::Slider::new(&mut params.seed, 0..=1000).text("seed"));
+        if let Some(error) = &self.error {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+    }
+
+    fn canvas(&self, ui: &mut egui::Ui) {
+        let (response, painter) = ui.allocate_painter(ui.available_size(), egui::Sense::hover());
+        let rect = response.rect.shrink(10.0);
+
+        let Some(bounds) = self.recorder.bounds() else {
+            return;
+        };
+        let scale = f32::min(
+            rect.width() / bounds.width().max(f32::EPSILON),
+            rect.height() / bounds.height().max(f32::EPSILON),
+        );
+        let (cx, cy) = bounds.center();
+        let to_screen =
+            |(x, y): (f32, f32)| rect.center() + egui::vec2((x - cx) * scale, (cy - y) * scale);
+
+        let stroke = egui::Stroke::new(1.0, ui.visuals().text_color());
+        for segment in self.recorder.segments() {
+            let stroke = match segment.color {
+                Some(c) => egui::Stroke::new(1.0, egui::Color32::from_rgb(c.r, c.g, c.b)),
+                None => stroke,
+            };
+            painter.line_segment([to_screen(segment.start), to_screen(segment.end)], stroke);
+        }
+    }
+}
+
+impl eframe::App for Tuner {
+    fn ui(&mut self, ui: &mut egui::Ui, _frame: &mut eframe::Frame) {
+        egui::Panel::left("controls").show(ui, |ui| self.controls(ui));
+        self.render();
+        egui::CentralPanel::default().show(ui, |ui| self.canvas(ui));
+    }
+}
+
+fn main() -> eframe::Result {
+    eframe::run_native(
+        "lsys-tune",
+        eframe::NativeOptions::default(),
+        Box::new(|_| Ok(Box::new(Tuner::new()))),
+    )
+}
@@ -0,0 +1,118 @@
+use std::error::Error;
+
+use crate::lsystem::{Instruction, Instructions, LSystem, Rule, RuleSelection, Word};
+use crate::symbol::Symbol;
+
+/// Puts a system together in code instead of writing out a grammar, e.g.
+/// `LSystemBuilder::axiom("F").rule('F', "F+F--F+F").build()`. Words given as text are parsed
+/// like an axiom; the first one that can't be is the error `build` returns.
+#[derive(Debug, Clone)]
+pub struct LSystemBuilder {
+    axiom: Instructions,
+    rules: Vec<Rule>,
+    selection: Option<RuleSelection>,
+    seed: u64,
+    error: Option<String>,
+}
+
+impl LSystemBuilder {
+    pub fn axiom(axiom: &str) -> Self {
+        Self::from_word(vec![]).parse(axiom, |builder, axiom| Self { axiom, ..builder })
+    }
+
+    /// Starts from an axiom that is already parsed.
+    pub fn from_word(axiom: impl Into<Word>) -> Self {
+        Self {
+            axiom: axiom.into().into_instructions(),
+            rules: vec![],
+            selection: None,
+            seed: 0,
+            error: None,
+        }
+    }
+
+    /// Adds a rule rewriting `from` to `to`, after the rules already added.
+    pub fn rule(self, from: impl Into<Symbol>, to: &str) -> Self {
+        let from = Instruction::Symbol(from.into());
+        self.parse(to, |builder, to| builder.push(Rule::new(from, to)))
+    }
+
+    /// Adds a rule with an already parsed successor.
+    pub fn rule_to(self, from: impl Into<Symbol>, to: impl Into<Word>) -> Self {
+        let rule = Rule::new(
+            Instruction::Symbol(from.into()),
+            to.into().into_instructions(),
+        );
+        self.push(rule)
+    }
+
+    /// Adds a rule as it is, e.g. one with a weight or from `Rule::parse_all`.
+    pub fn push(mut self, rule: Rule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Defaults to `RuleSelection::Weighted` if a rule's weight was changed, to
+    /// `RuleSelection::First` otherwise, like in a grammar.
+    pub fn selection(self, selection: RuleSelection) -> Self {
+        Self {
+            selection: Some(selection),
+            ..self
+        }
+    }
+
+    pub fn seed(self, seed: u64) -> Self {
+        Self { seed, ..self }
+    }
+
+    /// Weighted rules are checked like a grammar's, see `LSystem::check_weights`.
+    pub fn build(self) -> Result<LSystem, Box<dyn Error>> {
+        if let Some(error) = self.error {
+            return Err(error.into());
+        }
+        let weighted = self.rules.iter().any(|rule| rule.weight() != 1.0);
+        let selection = self.selection.unwrap_or(match weighted {
+            true => RuleSelection::Weighted,
+            false => RuleSelection::default(),
+        });
+        let mut lsystem = LSystem::new(self.axiom, self.rules);
+        lsystem.set_selection(selection);
+        lsystem.set_seed(self.seed);
+        lsystem.check_weights()?;
+        Ok(lsystem)
+    }
+
+    fn parse(self, input: &str, then: impl FnOnce(Self, Instructions) -> Self) -> Self {
+        if self.error.is_some() {
+            return self;
+        }
+        match Word::parse(input) {
+            Ok(word) => then(self, word.into_instructions()),
+            Err(e) => {
+                let error = Some(format!("'{}': {}", input, e));
+                Self { error, ..self }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_what_the_grammar_parses() {
+        let built = LSystemBuilder::axiom("F")
+            .rule('F', "F+F--F+F")
+            .rule_to('G', Word::parse("G[F]").unwrap())
+            .build()
+            .unwrap();
+        let parsed = LSystem::from_str("F; F -> F+F--F+F; G -> G[F];").unwrap();
+        assert_eq!(built, parsed);
+
+        let error = LSystemBuilder::axiom("F").rule('F', "F]").build();
+        assert!(error.is_err());
+        let error = LSystemBuilder::axiom("[F").rule('F', "F").build();
+        assert!(error.is_err());
+    }
+}
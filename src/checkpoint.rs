@@ -0,0 +1,223 @@
+use std::error::Error;
+use std::io::Write;
+use std::path::Path;
+
+use rand_chacha::ChaCha12Rng;
+
+use crate::lsystem::Word;
+
+const HEADER: &str = "lsys-checkpoint 1";
+
+/// An expansion in progress: the current word, where the random rule selection is and, where
+/// the system keeps them, the ages of its symbols and how often each was rewritten past the
+/// maximum depth, so that the same grammar can carry on from here later or on another machine.
+/// The word alone is enough to draw it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checkpoint {
+    pub(crate) generation: usize,
+    pub(crate) word: Word,
+    pub(crate) rng: ChaCha12Rng,
+    pub(crate) births: Option<Vec<usize>>,
+    pub(crate) deep_rewrites: Option<Vec<usize>>,
+}
+
+fn write_counts(
+    mut out: impl Write,
+    name: &str,
+    counts: &Option<Vec<usize>>,
+) -> std::io::Result<()> {
+    match counts {
+        Some(counts) => {
+            let counts: Vec<_> = counts.iter().map(usize::to_string).collect();
+            writeln!(out, "{} {}", name, counts.join(" "))
+        }
+        None => Ok(()),
+    }
+}
+
+impl Checkpoint {
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+
+    pub fn word(&self) -> &Word {
+        &self.word
+    }
+
+    pub fn into_word(self) -> Word {
+        self.word
+    }
+
+    /// A small text header followed by the word on one line, and the ages and deep rewrites of
+    /// its symbols on a line each where there are any.
+    pub fn write(&self, mut out: impl Write) -> std::io::Result<()> {
+        let seed: String = self
+            .rng
+            .get_seed()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        writeln!(out, "{}", HEADER)?;
+        writeln!(out, "generation {}", self.generation)?;
+        writeln!(
+            out,
+            "rng {} {} {}",
+            seed,
+            self.rng.get_stream(),
+            self.rng.get_word_pos()
+        )?;
+        writeln!(out, "{}", self.word)?;
+        write_counts(&mut out, "births", &self.births)?;
+        write_counts(&mut out, "deep", &self.deep_rewrites)
+    }
+
+    pub fn read(input: &str) -> Result<Self, Box<dyn Error>> {
+        let mut lines = input.lines();
+        let mut line = |name: &str| {
+            lines
+                .next()
+                .ok_or_else(|| format!("checkpoint ends before its {}", name))
+        };
+
+        if line("header")? != HEADER {
+            return Err("not a checkpoint".into());
+        }
+        let generation = line("generation")?
+            .strip_prefix("generation ")
+            .ok_or("expected the generation")?
+            .parse()?;
+
+        let rng = line("random state")?;
+        let fields: Vec<_> = rng
+            .strip_prefix("rng ")
+            .ok_or("expected the random state")?
+            .split(' ')
+            .collect();
+        let (seed_hex, stream, word_pos) = match fields[..] {
+            [seed, stream, word_pos] if seed.len() == 64 && seed.is_ascii() => {
+                (seed, stream.parse()?, word_pos.parse()?)
+            }
+            _ => return Err(format!("'{}' is not a random state", rng).into()),
+        };
+        let mut seed = [0u8; 32];
+        for (i, byte) in seed.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&seed_hex[2 * i..2 * i + 2], 16)?;
+        }
+        let mut rng = <ChaCha12Rng as rand::SeedableRng>::from_seed(seed);
+        rng.set_stream(stream);
+        rng.set_word_pos(word_pos);
+
+        let word = Word::parse(line("word")?)?;
+
+        let (mut births, mut deep_rewrites) = (None, None);
+        for line in lines {
+            let (counts, name) = match line.split_once(' ') {
+                Some(("births", counts)) => (counts, &mut births),
+                Some(("deep", counts)) => (counts, &mut deep_rewrites),
+                _ => return Err(format!("'{}' is not part of a checkpoint", line).into()),
+            };
+            let counts = counts
+                .split(' ')
+                .map(str::parse)
+                .collect::<Result<Vec<usize>, _>>()?;
+            if counts.len() != word.symbols().count() {
+                return Err(format!("'{}' doesn't count every symbol of the word", line).into());
+            }
+            *name = Some(counts);
+        }
+
+        Ok(Self {
+            generation,
+            word,
+            rng,
+            births,
+            deep_rewrites,
+        })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        self.write(std::io::BufWriter::new(std::fs::File::create(path)?))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        Self::read(&std::fs::read_to_string(path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lsystem::{LSystem, RuleSelection};
+
+    #[test]
+    fn resumed_expansions_carry_on_the_same() {
+        let mut original = LSystem::from_str("F; F -> F+F; F -> F[-F]; F -> FF;").unwrap();
+        original.set_selection(RuleSelection::Uniform);
+        original.set_seed(9);
+        original.nth(2);
+
+        let mut text = vec![];
+        original.checkpoint().write(&mut text).unwrap();
+        let checkpoint = Checkpoint::read(std::str::from_utf8(&text).unwrap()).unwrap();
+        assert_eq!(checkpoint, original.checkpoint());
+        assert_eq!(checkpoint.generation(), 3);
+
+        let mut resumed = LSystem::from_str("F; F -> F+F; F -> F[-F]; F -> FF;").unwrap();
+        resumed.set_selection(RuleSelection::Uniform);
+        resumed.set_seed(9);
+        resumed.resume(checkpoint);
+        assert_eq!(
+            resumed.take(3).collect::<Vec<_>>(),
+            original.take(3).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn resumed_systems_remember_ages_and_depths() {
+        let carry_on = |grammar: &str, setup: fn(&mut LSystem), at: usize| {
+            let mut original = LSystem::from_str(grammar).unwrap();
+            setup(&mut original);
+            original.nth(at);
+            let mut text = vec![];
+            original.checkpoint().write(&mut text).unwrap();
+            let checkpoint = Checkpoint::read(std::str::from_utf8(&text).unwrap()).unwrap();
+            assert_eq!(checkpoint, original.checkpoint());
+
+            let mut resumed = LSystem::from_str(grammar).unwrap();
+            setup(&mut resumed);
+            resumed.resume(checkpoint);
+            let words = |lsystem: LSystem| -> Vec<_> {
+                lsystem.take(5).map(|w| Word::from(w).to_string()).collect()
+            };
+            (words(original), words(resumed))
+        };
+
+        let (original, resumed) = carry_on("A; A@2 -> B[A]A;", |_| {}, 1);
+        assert_eq!(original, ["A", "B[A]A", "B[A]A", "B[A]A", "B[B[A]A]B[A]A"]);
+        assert_eq!(resumed, original);
+
+        let (original, resumed) = carry_on(
+            "X; X -> F[X]X; F -> FF;",
+            |lsystem: &mut LSystem| lsystem.set_max_depth(Some(1), 1),
+            3,
+        );
+        assert_eq!(resumed, original);
+    }
+
+    #[test]
+    fn damaged_checkpoints_are_errors() {
+        assert!(Checkpoint::read("").is_err());
+        assert!(Checkpoint::read("lsys-checkpoint 1\ngeneration x\n").is_err());
+        assert!(Checkpoint::read("lsys-checkpoint 1\ngeneration 1\nrng 00 0 0\nF\n").is_err());
+        let mut text = vec![];
+        LSystem::from_str("FF; F -> F;")
+            .unwrap()
+            .checkpoint()
+            .write(&mut text)
+            .unwrap();
+        let text = String::from_utf8(text).unwrap();
+        assert!(Checkpoint::read(&text).is_ok());
+        assert!(Checkpoint::read(&format!("{}births 0\n", text)).is_err());
+        assert!(Checkpoint::read(&format!("{}ages 0 0\n", text)).is_err());
+    }
+}
@@ -0,0 +1,412 @@
+//! A compact binary form of words. Runs of one symbol are stored once with a count, and a branch
+//! that appeared before is stored as a reference to it, so words produced by rewriting, which
+//! repeat themselves at every scale, shrink by orders of magnitude.
+//!
+//! The format is a magic number, a table of the symbols used, and a stream of operations:
+//! a run of a symbol, a module with the bits of its values, the start or end of a branch, or a
+//! reference to the n-th branch that ended so far. All numbers are LEB128 varints.
+
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+
+use crate::lsystem::{symbols_in, Instruction, Instructions, Params, Symbol};
+
+const MAGIC: &[u8; 4] = b"LSZ1";
+
+const RUN: u8 = 0;
+const BEGIN: u8 = 1;
+const END: u8 = 2;
+const REFERENCE: u8 = 3;
+const MODULE: u8 = 4;
+
+/// One step of a word read in order, without building it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    Symbol(Symbol),
+    Module(Symbol, Params),
+    /// A branch starts.
+    Push,
+    /// The innermost branch ends.
+    Pop,
+}
+
+fn write_varint(out: &mut impl Write, mut value: u64) -> io::Result<()> {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            return out.write_all(&[byte]);
+        }
+        out.write_all(&[byte | 0x80])?;
+    }
+}
+
+fn read_byte(input: &mut impl Read) -> io::Result<Option<u8>> {
+    let mut byte = [0];
+    match input.read(&mut byte)? {
+        0 => Ok(None),
+        _ => Ok(Some(byte[0])),
+    }
+}
+
+fn read_varint(input: &mut impl Read) -> io::Result<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = read_byte(input)?.ok_or(io::ErrorKind::UnexpectedEof)?;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(invalid("varint is too long"))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+struct Encoder<'w, W> {
+    out: &'w mut W,
+    symbols: HashMap<&'w Symbol, u64>,
+    branches: HashMap<&'w [Instruction], u64>,
+}
+
+impl<'w, W: Write> Encoder<'w, W> {
+    fn sequence(&mut self, instructions: &'w [Instruction]) -> io::Result<()> {
+        let mut rest = instructions;
+        while let Some(first) = rest.first() {
+            match first {
+                Instruction::Symbol(symbol) => {
+                    let run = rest.iter().take_while(|i| *i == first).count();
+                    self.out.write_all(&[RUN])?;
+                    write_varint(self.out, self.symbols[symbol])?;
+                    write_varint(self.out, run as u64)?;
+                    rest = &rest[run..];
+                }
+                Instruction::Module(symbol, values) => {
+                    self.out.write_all(&[MODULE])?;
+                    write_varint(self.out, self.symbols[symbol])?;
+                    write_varint(self.out, values.0.len() as u64)?;
+                    for value in &values.0 {
+                        write_varint(self.out, u64::from(value.to_bits()))?;
+                    }
+                    rest = &rest[1..];
+                }
+                Instruction::Branch(inner) => {
+                    match self.branches.get(&inner[..]) {
+                        Some(&index) => {
+                            self.out.write_all(&[REFERENCE])?;
+                            write_varint(self.out, index)?;
+                        }
+                        None => {
+                            self.out.write_all(&[BEGIN])?;
+                            self.sequence(inner)?;
+                            self.out.write_all(&[END])?;
+                            let index = self.branches.len() as u64;
+                            self.branches.insert(inner, index);
+                        }
+                    }
+                    rest = &rest[1..];
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes `word` in the compressed form.
+pub fn compress(word: &[Instruction], mut out: impl Write) -> io::Result<()> {
+    let mut symbols: Vec<&Symbol> = vec![];
+    for s in symbols_in(word) {
+        if !symbols.contains(&s) {
+            symbols.push(s);
+        }
+    }
+
+    out.write_all(MAGIC)?;
+    write_varint(&mut out, symbols.len() as u64)?;
+    for symbol in &symbols {
+        let text = symbol.to_string();
+        write_varint(&mut out, text.len() as u64)?;
+        out.write_all(text.as_bytes())?;
+    }
+
+    Encoder {
+        out: &mut out,
+        symbols: symbols.into_iter().zip(0..).collect(),
+        branches: HashMap::new(),
+    }
+    .sequence(word)
+}
+
+#[derive(Debug, Clone)]
+enum Op {
+    Run(usize, u64),
+    Module(usize, Vec<f32>),
+    Reference(usize),
+}
+
+/// Reads a compressed word as a stream of events. Only the compressed form of earlier branches
+/// is kept, to replay references from. Reading stops at the first error, which `finish` returns.
+pub struct Decoder<R> {
+    input: R,
+    symbols: Vec<Symbol>,
+    branches: Vec<Vec<Op>>,
+    // branches still being read from the input
+    open: Vec<Vec<Op>>,
+    // references being replayed, with the next op of each
+    replaying: Vec<(usize, usize)>,
+    run: Option<(usize, u64)>,
+    error: Option<io::Error>,
+}
+
+impl<R: Read> Decoder<R> {
+    pub fn new(mut input: R) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a compressed word"));
+        }
+
+        let count = read_varint(&mut input)?;
+        let mut symbols = vec![];
+        for _ in 0..count {
+            let len = read_varint(&mut input)?;
+            let mut text = String::new();
+            (&mut input).take(len).read_to_string(&mut text)?;
+            if text.len() as u64 != len || text.is_empty() {
+                return Err(invalid("symbol table is cut short"));
+            }
+            symbols.push(Symbol::new(&text));
+        }
+
+        Ok(Self {
+            input,
+            symbols,
+            branches: vec![],
+            open: vec![],
+            replaying: vec![],
+            run: None,
+            error: None,
+        })
+    }
+
+    /// Whether the whole word was read.
+    pub fn finish(self) -> io::Result<()> {
+        match self.error {
+            Some(error) => Err(error),
+            None if !self.open.is_empty() => Err(invalid("a branch is never closed")),
+            None => Ok(()),
+        }
+    }
+
+    fn symbol(&self, index: usize) -> io::Result<usize> {
+        match index < self.symbols.len() {
+            true => Ok(index),
+            false => Err(invalid("unknown symbol")),
+        }
+    }
+
+    fn branch(&self, index: usize) -> io::Result<usize> {
+        match index < self.branches.len() {
+            true => Ok(index),
+            false => Err(invalid("reference to a branch that hasn't ended")),
+        }
+    }
+
+    fn read_event(&mut self) -> io::Result<Option<Event>> {
+        loop {
+            if let Some((symbol, left)) = &mut self.run {
+                let symbol = *symbol;
+                *left -= 1;
+                if *left == 0 {
+                    self.run = None;
+                }
+                return Ok(Some(Event::Symbol(self.symbols[symbol].clone())));
+            }
+
+            if let Some((branch, next)) = self.replaying.last_mut() {
+                match self.branches[*branch].get(*next) {
+                    None => {
+                        self.replaying.pop();
+                        return Ok(Some(Event::Pop));
+                    }
+                    Some(op) => {
+                        *next += 1;
+                        match *op {
+                            Op::Run(symbol, count) if count > 0 => self.run = Some((symbol, count)),
+                            Op::Run(..) => {}
+                            Op::Module(symbol, ref values) => {
+                                let symbol = self.symbols[symbol].clone();
+                                return Ok(Some(Event::Module(symbol, Params(values.clone()))));
+                            }
+                            Op::Reference(branch) => {
+                                self.replaying.push((branch, 0));
+                                return Ok(Some(Event::Push));
+                            }
+                        }
+                        continue;
+                    }
+                }
+            }
+
+            let op = match read_byte(&mut self.input)? {
+                None => return Ok(None),
+                Some(RUN) => {
+                    let symbol = read_varint(&mut self.input)? as usize;
+                    let symbol = self.symbol(symbol)?;
+                    let count = read_varint(&mut self.input)?;
+                    Op::Run(symbol, count)
+                }
+                Some(MODULE) => {
+                    let symbol = read_varint(&mut self.input)? as usize;
+                    let symbol = self.symbol(symbol)?;
+                    let count = read_varint(&mut self.input)?;
+                    let mut values = vec![];
+                    for _ in 0..count {
+                        let bits = u32::try_from(read_varint(&mut self.input)?)
+                            .map_err(|_| invalid("value is too long"))?;
+                        values.push(f32::from_bits(bits));
+                    }
+                    Op::Module(symbol, values)
+                }
+                Some(REFERENCE) => {
+                    let branch = read_varint(&mut self.input)? as usize;
+                    Op::Reference(self.branch(branch)?)
+                }
+                Some(BEGIN) => {
+                    self.open.push(vec![]);
+                    return Ok(Some(Event::Push));
+                }
+                Some(END) => {
+                    let branch = self.open.pop().ok_or_else(|| invalid("unmatched end"))?;
+                    self.branches.push(branch);
+                    if let Some(parent) = self.open.last_mut() {
+                        parent.push(Op::Reference(self.branches.len() - 1));
+                    }
+                    return Ok(Some(Event::Pop));
+                }
+                Some(_) => return Err(invalid("unknown operation")),
+            };
+
+            if let Some(parent) = self.open.last_mut() {
+                parent.push(op.clone());
+            }
+            match op {
+                Op::Run(symbol, count) if count > 0 => self.run = Some((symbol, count)),
+                Op::Run(..) => {}
+                Op::Module(symbol, values) => {
+                    let symbol = self.symbols[symbol].clone();
+                    return Ok(Some(Event::Module(symbol, Params(values))));
+                }
+                Op::Reference(branch) => {
+                    self.replaying.push((branch, 0));
+                    return Ok(Some(Event::Push));
+                }
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for Decoder<R> {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Event> {
+        if self.error.is_some() {
+            return None;
+        }
+        match self.read_event() {
+            Ok(event) => event,
+            Err(error) => {
+                self.error = Some(error);
+                None
+            }
+        }
+    }
+}
+
+/// Reads a whole compressed word back into memory.
+pub fn decompress(input: impl Read) -> io::Result<Instructions> {
+    let mut decoder = Decoder::new(input)?;
+    let word = assemble(&mut decoder);
+    decoder.finish()?;
+    Ok(word)
+}
+
+// builds the word a stream of events walks through; branches left open are closed at the end
+pub(crate) fn assemble(events: impl IntoIterator<Item = Event>) -> Instructions {
+    let mut open = vec![vec![]];
+    for event in events {
+        match event {
+            Event::Symbol(s) => open.last_mut().unwrap().push(Instruction::Symbol(s)),
+            Event::Module(s, values) => open
+                .last_mut()
+                .unwrap()
+                .push(Instruction::Module(s, values)),
+            Event::Push => open.push(vec![]),
+            Event::Pop if open.len() > 1 => {
+                let branch = open.pop().unwrap();
+                open.last_mut().unwrap().push(Instruction::Branch(branch));
+            }
+            Event::Pop => {}
+        }
+    }
+    while open.len() > 1 {
+        let branch = open.pop().unwrap();
+        open.last_mut().unwrap().push(Instruction::Branch(branch));
+    }
+    open.pop().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lsystem::LSystem;
+
+    #[test]
+    fn words_survive_compression() {
+        let word = LSystem::from_str("X; X -> F[+X][-X]FX; F -> FF;")
+            .unwrap()
+            .nth(7)
+            .unwrap();
+        let mut compressed = vec![];
+        compress(&word, &mut compressed).unwrap();
+
+        let text_len = crate::lsystem::Word::from(word.clone()).to_string().len();
+        assert!(compressed.len() * 20 < text_len);
+        assert_eq!(decompress(&compressed[..]).unwrap(), word);
+    }
+
+    #[test]
+    fn unicode_symbols_and_empty_branches() {
+        let word = LSystem::from_str("🌱[][F[]]αα[F[]];")
+            .unwrap()
+            .word()
+            .clone();
+        let mut compressed = vec![];
+        compress(&word, &mut compressed).unwrap();
+        assert_eq!(decompress(&compressed[..]).unwrap(), word);
+    }
+
+    #[test]
+    fn modules_keep_their_values() {
+        let word = LSystem::from_str("F(1); F(x) -> F(x*0.7)[+(30)F(x/3)]F(x);")
+            .unwrap()
+            .nth(3)
+            .unwrap();
+        let mut compressed = vec![];
+        compress(&word, &mut compressed).unwrap();
+        assert_eq!(decompress(&compressed[..]).unwrap(), word);
+    }
+
+    #[test]
+    fn broken_input_is_an_error() {
+        let mut compressed = vec![];
+        let word = LSystem::from_str("F[F]F;").unwrap().word().clone();
+        compress(&word, &mut compressed).unwrap();
+        for len in 0..compressed.len() {
+            assert_ne!(decompress(&compressed[..len]).ok(), Some(word.clone()));
+        }
+        assert!(decompress(&b"LSZ1\x01\x01F\x03\x00"[..]).is_err());
+    }
+}
@@ -0,0 +1,136 @@
+use std::path::{Path, PathBuf};
+
+use crate::compress::compress;
+use crate::geometry::Recorder;
+use crate::graphics::TurtleConfig;
+use crate::lsystem::{LSystem, Word};
+use crate::render::RenderConfig;
+
+/// Writes every generation of a system to its own files, deriving each generation from the one
+/// before it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dump {
+    prefix: String,
+    words: bool,
+    compressed: bool,
+    renders: bool,
+    size: (f32, f32),
+}
+
+impl Default for Dump {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Dump {
+    pub fn new() -> Self {
+        Self {
+            prefix: String::from("generation-"),
+            words: true,
+            compressed: false,
+            renders: true,
+            size: (300.0, 300.0),
+        }
+    }
+
+    /// Files are named the prefix followed by the zero-padded generation.
+    pub fn prefix(self, prefix: &str) -> Self {
+        Self {
+            prefix: prefix.to_string(),
+            ..self
+        }
+    }
+
+    /// Whether to write each word as text.
+    pub fn words(self, words: bool) -> Self {
+        Self { words, ..self }
+    }
+
+    /// Writes words in the compressed form of `compress`, as `.lsz` files, instead of as text.
+    pub fn compressed(self, compressed: bool) -> Self {
+        Self { compressed, ..self }
+    }
+
+    /// Whether to draw each word to an SVG.
+    pub fn renders(self, renders: bool) -> Self {
+        Self { renders, ..self }
+    }
+
+    /// Every render has this size, with the figure scaled to fit.
+    pub fn size(self, width: f32, height: f32) -> Self {
+        Self {
+            size: (width, height),
+            ..self
+        }
+    }
+
+    /// Writes generations 0 to `generations` into `directory`, creating it if needed, and
+    /// returns the written paths in order.
+    pub fn write(
+        &self,
+        lsystem: LSystem,
+        generations: usize,
+        turtle: &TurtleConfig,
+        render: &RenderConfig,
+        directory: impl AsRef<Path>,
+    ) -> std::io::Result<Vec<PathBuf>> {
+        let directory = directory.as_ref();
+        std::fs::create_dir_all(directory)?;
+        let digits = generations.to_string().len();
+        let mut written = vec![];
+
+        for (generation, word) in lsystem.take(generations + 1).enumerate() {
+            let name = format!("{}{:0digits$}", self.prefix, generation, digits = digits);
+
+            if self.renders {
+                let mut recorder = Recorder::new();
+                turtle.create_turtle().draw(&mut recorder, &word).unwrap();
+                let path = directory.join(format!("{}.svg", name));
+                render
+                    .fit_into(&recorder, self.size.0, self.size.1)
+                    .save_svg(&path.to_string_lossy())?;
+                written.push(path);
+            }
+
+            if self.words && self.compressed {
+                let path = directory.join(format!("{}.lsz", name));
+                let file = std::io::BufWriter::new(std::fs::File::create(&path)?);
+                compress(&word, file)?;
+                written.push(path);
+            } else if self.words {
+                let path = directory.join(format!("{}.txt", name));
+                std::fs::write(&path, Word::from(word).to_string())?;
+                written.push(path);
+            }
+        }
+
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_generation_gets_its_files() {
+        let directory = std::env::temp_dir().join(format!("lsys-dump-{}", std::process::id()));
+        let lsystem = LSystem::from_str("F; F -> F+F;").unwrap();
+        let written = Dump::new()
+            .renders(false)
+            .write(
+                lsystem,
+                10,
+                &TurtleConfig::default(),
+                &RenderConfig::default(),
+                &directory,
+            )
+            .unwrap();
+
+        assert_eq!(written.len(), 11);
+        assert_eq!(written[3], directory.join("generation-03.txt"));
+        assert_eq!(std::fs::read_to_string(&written[2]).unwrap(), "F+F+F+F");
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
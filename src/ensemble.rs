@@ -0,0 +1,143 @@
+use crate::explore::grid;
+use crate::geometry::Recorder;
+use crate::graphics::TurtleConfig;
+use crate::lsystem::{Instructions, LSystem};
+use crate::render::{RenderConfig, Surface};
+
+/// Many independent derivations of one stochastic grammar, to see the range of forms it grows
+/// rather than a single one. Sample `i` is derived with seed `seed + i`.
+pub struct EnsembleConfig {
+    count: usize,
+    seed: u64,
+    iterations: usize,
+    columns: usize,
+    cell_size: f32,
+    opacity: f32,
+}
+
+impl Default for EnsembleConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EnsembleConfig {
+    pub fn new() -> Self {
+        Self {
+            count: 16,
+            seed: 0,
+            iterations: 5,
+            columns: 4,
+            cell_size: 200.0,
+            opacity: 0.1,
+        }
+    }
+
+    pub fn count(self, count: usize) -> Self {
+        Self { count, ..self }
+    }
+
+    pub fn seed(self, seed: u64) -> Self {
+        Self { seed, ..self }
+    }
+
+    pub fn iterations(self, iterations: usize) -> Self {
+        Self { iterations, ..self }
+    }
+
+    pub fn columns(self, columns: usize) -> Self {
+        Self {
+            columns: columns.max(1),
+            ..self
+        }
+    }
+
+    pub fn cell_size(self, cell_size: f32) -> Self {
+        Self { cell_size, ..self }
+    }
+
+    /// The alpha of every line in `overlay`.
+    pub fn opacity(self, opacity: f32) -> Self {
+        Self {
+            opacity: opacity.clamp(0.0, 1.0),
+            ..self
+        }
+    }
+
+    pub fn samples(&self, lsystem: &LSystem) -> Vec<Instructions> {
+        (0..self.count as u64)
+            .map(|i| {
+                let mut sample = lsystem.clone();
+                sample.set_seed(self.seed.wrapping_add(i));
+                sample.nth(self.iterations).unwrap()
+            })
+            .collect()
+    }
+
+    /// Every sample in its own cell of a grid, scaled to fit.
+    pub fn grid(&self, lsystem: &LSystem, turtle: &TurtleConfig, render: &RenderConfig) -> Surface {
+        let recorders: Vec<_> = self
+            .samples(lsystem)
+            .iter()
+            .map(|word| {
+                let mut recorder = Recorder::new();
+                turtle.create_turtle().draw(&mut recorder, word).unwrap();
+                recorder
+            })
+            .collect();
+        grid(render, self.columns, self.cell_size, &recorders)
+    }
+
+    /// Every sample drawn on top of each other with faint lines, so where the forms agree comes
+    /// out dark. Only raster targets show the opacity.
+    pub fn overlay(&self, lsystem: &LSystem, turtle: &TurtleConfig) -> Recorder {
+        let turtle = turtle.clone().opacity(self.opacity);
+        let mut recorder = Recorder::new();
+        for word in self.samples(lsystem) {
+            turtle.create_turtle().draw(&mut recorder, word).unwrap();
+        }
+        recorder
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lsystem::RuleSelection;
+
+    #[test]
+    fn samples_differ_and_repeat() {
+        let mut lsystem = LSystem::from_str("F; F -> F[+F]F; F -> F[-F]F; F -> FF;").unwrap();
+        lsystem.set_selection(RuleSelection::Uniform);
+        let config = EnsembleConfig::new().count(6).iterations(3).seed(5);
+
+        let samples = config.samples(&lsystem);
+        assert_eq!(samples, config.samples(&lsystem));
+        assert!(samples.iter().any(|sample| *sample != samples[0]));
+
+        let overlay = config.overlay(&lsystem, &TurtleConfig::default());
+        let lines: usize = samples
+            .iter()
+            .map(|sample| {
+                let mut recorder = Recorder::new();
+                TurtleConfig::default()
+                    .create_turtle()
+                    .draw(&mut recorder, sample)
+                    .unwrap();
+                recorder.segments().len()
+            })
+            .sum();
+        assert_eq!(overlay.segments().len(), lines);
+        assert!(overlay.segments().iter().all(|s| s.alpha == 0.1));
+
+        let surface = config.columns(4).cell_size(50.0).grid(
+            &lsystem,
+            &TurtleConfig::default(),
+            &RenderConfig::default(),
+        );
+        assert_eq!(
+            (surface.canvas().width, surface.canvas().height),
+            (200, 100)
+        );
+    }
+}
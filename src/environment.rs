@@ -0,0 +1,174 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::symbol::Symbol;
+
+/// Space shared between the turtle and the rewriter: the turtle marks the cells its lines pass
+/// through, and answers each query symbol with whether the cell one step ahead is taken. The
+/// next `LSystem::step_in` keeps the rest of a branch after a blocked query unchanged.
+///
+/// Symbols given a response are answered with values instead, which `step_in` makes the
+/// values of their modules before rules see them, e.g. `P(x, y)` with the turtle's position.
+///
+/// The cell size should be smaller than the turtle's step, or the turtle will find its own last
+/// line ahead of it.
+#[derive(Debug, Clone)]
+pub struct Environment {
+    cell_size: f32,
+    query: Symbol,
+    occupied: HashSet<(i64, i64)>,
+    queries: Vec<bool>,
+    responders: Vec<Responder>,
+    responses: Vec<Vec<f32>>,
+}
+
+/// Where the turtle is when it meets a symbol the environment responds to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TurtleState {
+    pub position: (f32, f32),
+    /// In radians, like turns.
+    pub heading: f32,
+}
+
+type Response = Rc<dyn Fn(&TurtleState) -> Vec<f32>>;
+
+#[derive(Clone)]
+struct Responder {
+    symbol: Symbol,
+    response: Response,
+}
+
+impl fmt::Debug for Responder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Responder")
+            .field("symbol", &self.symbol)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Environment {
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size,
+            query: '?'.into(),
+            occupied: HashSet::new(),
+            queries: vec![],
+            responders: vec![],
+            responses: vec![],
+        }
+    }
+
+    pub fn query(self, query: impl Into<Symbol>) -> Self {
+        Self {
+            query: query.into(),
+            ..self
+        }
+    }
+
+    pub fn query_symbol(&self) -> &Symbol {
+        &self.query
+    }
+
+    /// Answers `symbol` with what `response` makes of the turtle's state, e.g. how much light
+    /// reaches it. A later response for the same symbol replaces the earlier one.
+    pub fn respond(
+        mut self,
+        symbol: impl Into<Symbol>,
+        response: impl Fn(&TurtleState) -> Vec<f32> + 'static,
+    ) -> Self {
+        let symbol = symbol.into();
+        self.responders
+            .retain(|responder| responder.symbol != symbol);
+        self.responders.push(Responder {
+            symbol,
+            response: Rc::new(response),
+        });
+        self
+    }
+
+    /// Answers `symbol` with the turtle's position, like `?P` in the literature.
+    pub fn position_query(self, symbol: impl Into<Symbol>) -> Self {
+        self.respond(symbol, |turtle| vec![turtle.position.0, turtle.position.1])
+    }
+
+    /// Answers `symbol` with the turtle's heading as a unit vector, like `?H`.
+    pub fn heading_query(self, symbol: impl Into<Symbol>) -> Self {
+        self.respond(symbol, |turtle| {
+            vec![turtle.heading.cos(), turtle.heading.sin()]
+        })
+    }
+
+    pub fn responds_to(&self, symbol: &Symbol) -> bool {
+        self.responders.iter().any(|r| r.symbol == *symbol)
+    }
+
+    /// Forgets all lines and query answers, ready for the next interpretation.
+    pub fn clear(&mut self) {
+        self.occupied.clear();
+        self.queries.clear();
+        self.responses.clear();
+    }
+
+    // cells are centered on multiples of the cell size, so axis aligned figures don't sit on
+    // cell edges and flip between cells with rounding noise
+    fn cell(&self, (x, y): (f32, f32)) -> (i64, i64) {
+        (
+            (x / self.cell_size).round() as i64,
+            (y / self.cell_size).round() as i64,
+        )
+    }
+
+    pub fn is_occupied(&self, point: (f32, f32)) -> bool {
+        self.occupied.contains(&self.cell(point))
+    }
+
+    pub fn mark(&mut self, c_0: (f32, f32), c_1: (f32, f32)) {
+        let length = ((c_1.0 - c_0.0).powi(2) + (c_1.1 - c_0.1).powi(2)).sqrt();
+        let samples = (2.0 * length / self.cell_size).ceil().max(1.0) as usize;
+        for i in 0..=samples {
+            let t = i as f32 / samples as f32;
+            let point = (c_0.0 + (c_1.0 - c_0.0) * t, c_0.1 + (c_1.1 - c_0.1) * t);
+            self.occupied.insert(self.cell(point));
+        }
+    }
+
+    pub(crate) fn answer(&mut self, ahead: (f32, f32)) {
+        let blocked = self.is_occupied(ahead);
+        self.queries.push(blocked);
+    }
+
+    /// Whether each query symbol was blocked, in the order the turtle met them.
+    pub fn queries(&self) -> &[bool] {
+        &self.queries
+    }
+
+    pub(crate) fn record(&mut self, symbol: &Symbol, turtle: &TurtleState) {
+        if let Some(responder) = self.responders.iter().find(|r| r.symbol == *symbol) {
+            let response = (responder.response)(turtle);
+            self.responses.push(response);
+        }
+    }
+
+    /// The values of every symbol responded to, in the order the turtle met them.
+    pub fn responses(&self) -> &[Vec<f32>] {
+        &self.responses
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn marked_lines_occupy_cells() {
+        let mut env = Environment::new(1.0);
+        env.mark((0.0, 0.0), (4.0, 0.0));
+        assert!(env.is_occupied((2.2, 0.4)));
+        assert!(env.is_occupied((4.4, -0.1)));
+        assert!(!env.is_occupied((2.2, 0.6)));
+
+        env.clear();
+        assert!(!env.is_occupied((2.2, 0.4)));
+    }
+}
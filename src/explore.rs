@@ -0,0 +1,183 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::geometry::Recorder;
+use crate::graphics::TurtleConfig;
+use crate::lsystem::{LSystem, MutationConfig};
+use crate::render::{RenderConfig, Surface};
+
+/// A mutated grammar together with the turning angle it should be drawn with.
+#[derive(Debug, Clone)]
+pub struct Variant {
+    pub lsystem: LSystem,
+    pub delta_ang: f32,
+}
+
+pub struct ExploreConfig {
+    count: usize,
+    seed: u64,
+    edits: usize,
+    angle_jitter: f32,
+    iterations: usize,
+    columns: usize,
+    cell_size: f32,
+}
+
+impl Default for ExploreConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExploreConfig {
+    pub fn new() -> Self {
+        Self {
+            count: 9,
+            seed: 0,
+            edits: 2,
+            angle_jitter: std::f32::consts::PI / 36.0,
+            iterations: 5,
+            columns: 3,
+            cell_size: 200.0,
+        }
+    }
+
+    pub fn count(self, count: usize) -> Self {
+        Self { count, ..self }
+    }
+
+    pub fn seed(self, seed: u64) -> Self {
+        Self { seed, ..self }
+    }
+
+    pub fn edits(self, edits: usize) -> Self {
+        Self { edits, ..self }
+    }
+
+    pub fn angle_jitter(self, angle_jitter: f32) -> Self {
+        Self {
+            angle_jitter,
+            ..self
+        }
+    }
+
+    pub fn iterations(self, iterations: usize) -> Self {
+        Self { iterations, ..self }
+    }
+
+    pub fn columns(self, columns: usize) -> Self {
+        Self {
+            columns: columns.max(1),
+            ..self
+        }
+    }
+
+    pub fn cell_size(self, cell_size: f32) -> Self {
+        Self { cell_size, ..self }
+    }
+
+    /// Produces `count` variants of `base`, each a few rule edits and an angle nudge away from it.
+    /// The same seed always yields the same variants.
+    pub fn variants(&self, base: &LSystem, delta_ang: f32) -> Vec<Variant> {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let mutation = MutationConfig::new().edits(self.edits);
+
+        (0..self.count)
+            .map(|_| {
+                let jitter = if self.angle_jitter > 0.0 {
+                    rng.gen_range(-self.angle_jitter..=self.angle_jitter)
+                } else {
+                    0.0
+                };
+
+                Variant {
+                    lsystem: base.mutate(&mut rng, &mutation),
+                    delta_ang: delta_ang + jitter,
+                }
+            })
+            .collect()
+    }
+
+    /// Draws every variant after `iterations` steps into its own cell of a grid, scaled to fit.
+    /// The turtle's angle is replaced by each variant's own.
+    pub fn thumbnail_grid(
+        &self,
+        variants: &[Variant],
+        turtle: &TurtleConfig,
+        render: &RenderConfig,
+    ) -> Surface {
+        let recorders: Vec<_> = variants
+            .iter()
+            .map(|variant| {
+                let word = variant.lsystem.clone().nth(self.iterations).unwrap();
+                let turtle = turtle.clone().delta_ang(variant.delta_ang);
+                let mut recorder = Recorder::new();
+                turtle.create_turtle().draw(&mut recorder, word).unwrap();
+                recorder
+            })
+            .collect();
+        grid(render, self.columns, self.cell_size, &recorders)
+    }
+}
+
+/// Draws every recording into its own square cell, rows of `columns` of them, scaled to fit.
+pub(crate) fn grid(
+    render: &RenderConfig,
+    columns: usize,
+    cell_size: f32,
+    recorders: &[Recorder],
+) -> Surface {
+    let rows = recorders.len().div_ceil(columns).max(1);
+    let mut surface = render.surface(columns as f32 * cell_size, rows as f32 * cell_size);
+
+    for (i, recorder) in recorders.iter().enumerate() {
+        let bounds = match recorder.bounds() {
+            Some(bounds) => bounds,
+            None => continue,
+        };
+
+        let margin = cell_size * 0.05;
+        let extent = bounds.width().max(bounds.height()).max(f32::EPSILON);
+        let scale = (cell_size - 2.0 * margin) / extent;
+        let (cx, cy) = bounds.center();
+        let cell_x = (i % columns) as f32 * cell_size + cell_size / 2.0;
+        let cell_y = (i / columns) as f32 * cell_size + cell_size / 2.0;
+
+        surface.place((cell_x - cx * scale, cell_y - cy * scale), scale);
+        recorder.replay(&mut surface).unwrap();
+    }
+
+    surface
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_variants() {
+        let base = LSystem::from_str("F; F->F[+F]F[-F]F;").unwrap();
+        let config = ExploreConfig::new().count(4).seed(42);
+
+        let a = config.variants(&base, 0.4);
+        let b = config.variants(&base, 0.4);
+        assert_eq!(a.len(), 4);
+        for (a, b) in a.iter().zip(&b) {
+            assert_eq!(a.lsystem, b.lsystem);
+            assert_eq!(a.delta_ang, b.delta_ang);
+        }
+    }
+
+    #[test]
+    fn grid_fits_all_variants() {
+        let base = LSystem::from_str("F; F->F[+F]F[-F]F;").unwrap();
+        let config = ExploreConfig::new().count(5).columns(2).cell_size(100.0);
+        let surface = config.thumbnail_grid(
+            &config.variants(&base, 0.4),
+            &TurtleConfig::default(),
+            &RenderConfig::default(),
+        );
+
+        let canvas = surface.canvas();
+        assert_eq!((canvas.width, canvas.height), (200, 300));
+    }
+}
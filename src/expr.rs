@@ -0,0 +1,458 @@
+//! Arithmetic on the parameters of modules, as written in the successors and guards of
+//! parametric rules: numbers, the rule's parameter names, `+ - * / ^`, negation and
+//! parentheses, and for guards the comparisons `< <= > >= == !=` and `&& || !`. Comparisons
+//! and logic give 1 for true and 0 for false, and any value but 0 counts as true.
+
+use std::fmt;
+
+use crate::lsystem::{Expected, Parsed, MAX_NESTING};
+use nom::{
+    branch::alt,
+    bytes::complete::{tag, take_while, take_while1},
+    character::complete::multispace0,
+    combinator::{map, not, recognize},
+    number::complete::float,
+    sequence::{pair, preceded, tuple},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+    And,
+    Or,
+}
+
+const COMPARISONS: [Op; 6] = [
+    Op::LessEqual,
+    Op::GreaterEqual,
+    Op::Equal,
+    Op::NotEqual,
+    Op::Less,
+    Op::Greater,
+];
+
+// negation and `!` bind between products and powers
+const PREFIX: u8 = 6;
+
+impl Op {
+    fn precedence(self) -> u8 {
+        match self {
+            Op::Or => 1,
+            Op::And => 2,
+            Op::Less
+            | Op::LessEqual
+            | Op::Greater
+            | Op::GreaterEqual
+            | Op::Equal
+            | Op::NotEqual => 3,
+            Op::Add | Op::Sub => 4,
+            Op::Mul | Op::Div => 5,
+            Op::Pow => 7,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            Op::Add => "+",
+            Op::Sub => "-",
+            Op::Mul => "*",
+            Op::Div => "/",
+            Op::Pow => "^",
+            Op::Less => "<",
+            Op::LessEqual => "<=",
+            Op::Greater => ">",
+            Op::GreaterEqual => ">=",
+            Op::Equal => "==",
+            Op::NotEqual => "!=",
+            Op::And => "&&",
+            Op::Or => "||",
+        }
+    }
+}
+
+fn truth(value: bool) -> f32 {
+    match value {
+        true => 1.0,
+        false => 0.0,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Expr {
+    Number(f32),
+    /// The parameter at this position of the rule's head.
+    Parameter(usize),
+    Neg(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    /// The value with `values` for the rule's parameters. Parameters that weren't given are 0.
+    pub fn eval(&self, values: &[f32]) -> f32 {
+        match self {
+            Expr::Number(n) => *n,
+            Expr::Parameter(i) => values.get(*i).copied().unwrap_or(0.0),
+            Expr::Neg(e) => -e.eval(values),
+            Expr::Not(e) => truth(!e.holds(values)),
+            Expr::Binary(Op::And, a, b) => truth(a.holds(values) && b.holds(values)),
+            Expr::Binary(Op::Or, a, b) => truth(a.holds(values) || b.holds(values)),
+            Expr::Binary(op, a, b) => {
+                let (a, b) = (a.eval(values), b.eval(values));
+                match op {
+                    Op::Add => a + b,
+                    Op::Sub => a - b,
+                    Op::Mul => a * b,
+                    Op::Div => a / b,
+                    Op::Pow => a.powf(b),
+                    Op::Less => truth(a < b),
+                    Op::LessEqual => truth(a <= b),
+                    Op::Greater => truth(a > b),
+                    Op::GreaterEqual => truth(a >= b),
+                    Op::Equal => truth(a == b),
+                    Op::NotEqual => truth(a != b),
+                    Op::And | Op::Or => unreachable!(),
+                }
+            }
+        }
+    }
+
+    /// Whether the expression is true, as a guard: not 0 (nor NaN).
+    pub fn holds(&self, values: &[f32]) -> bool {
+        let value = self.eval(values);
+        value != 0.0 && !value.is_nan()
+    }
+
+    /// Writes the expression back with the parameter names it was parsed with.
+    pub fn display<'a>(&'a self, names: &'a [String]) -> impl fmt::Display + 'a {
+        Named { expr: self, names }
+    }
+}
+
+struct Named<'a> {
+    expr: &'a Expr,
+    names: &'a [String],
+}
+
+impl Named<'_> {
+    fn child<'a>(&'a self, expr: &'a Expr) -> Named<'a> {
+        Named {
+            expr,
+            names: self.names,
+        }
+    }
+}
+
+fn precedence(expr: &Expr) -> u8 {
+    match expr {
+        Expr::Binary(op, ..) => op.precedence(),
+        Expr::Neg(_) | Expr::Not(_) => PREFIX,
+        _ => 8,
+    }
+}
+
+impl fmt::Display for Named<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.expr {
+            Expr::Number(n) => write!(f, "{}", n),
+            Expr::Parameter(i) => match self.names.get(*i) {
+                Some(name) => write!(f, "{}", name),
+                None => write!(f, "${}", i),
+            },
+            Expr::Neg(e) if precedence(e) < PREFIX => write!(f, "-({})", self.child(e)),
+            Expr::Neg(e) => write!(f, "-{}", self.child(e)),
+            Expr::Not(e) if precedence(e) < PREFIX => write!(f, "!({})", self.child(e)),
+            Expr::Not(e) => write!(f, "!{}", self.child(e)),
+            Expr::Binary(op, a, b) => {
+                // `^` groups to the right, the others to the left
+                let p = op.precedence();
+                let (left, right) = match op {
+                    Op::Pow => (precedence(a) <= p, precedence(b) < p),
+                    _ => (precedence(a) < p, precedence(b) <= p),
+                };
+                let spaced = p <= 3;
+                let symbol = match spaced {
+                    true => format!(" {} ", op.symbol()),
+                    false => op.symbol().to_string(),
+                };
+                match left {
+                    true => write!(f, "({})", self.child(a))?,
+                    false => write!(f, "{}", self.child(a))?,
+                }
+                write!(f, "{}", symbol)?;
+                match right {
+                    true => write!(f, "({})", self.child(b)),
+                    false => write!(f, "{}", self.child(b)),
+                }
+            }
+        }
+    }
+}
+
+pub(crate) fn identifier(input: &str) -> Parsed<'_, &str> {
+    recognize(pair(
+        take_while1(|c: char| c.is_ascii_alphabetic() || c == '_'),
+        take_while(|c: char| c.is_ascii_alphanumeric() || c == '_'),
+    ))(input)
+}
+
+fn token<'i>(t: &'static str) -> impl Fn(&'i str) -> Parsed<'i, &'i str> {
+    move |input| preceded(multispace0, tag(t))(input)
+}
+
+// `-`, but not the start of the arrow after a guard, `->` or `-0.4->`
+fn minus(input: &str) -> Parsed<'_, &str> {
+    let (input, _) = multispace0(input)?;
+    let arrow = alt((tag("->"), recognize(tuple((tag("-"), float, tag("->"))))));
+    let (input, ()) = not(arrow)(input)?;
+    tag("-")(input)
+}
+
+/// Parses an expression over the parameters `names`; any other name is an error, and so is a
+/// tree of operators nested more than `MAX_NESTING` deep, since evaluating, printing and
+/// dropping it recurses once per level.
+pub(crate) fn parse<'i>(input: &'i str, names: &[String]) -> Parsed<'i, Expr> {
+    map(|input| disjunction(input, names), |(expr, _)| expr)(input)
+}
+
+// an expression and how deep its tree is
+type Tree = (Expr, usize);
+
+type Level = for<'i> fn(&'i str, &[String]) -> Parsed<'i, Tree>;
+
+fn too_deep(input: &str) -> nom::Err<Expected<'_>> {
+    nom::Err::Failure(Expected::new(
+        input,
+        format!("operators nested at most {} deep", MAX_NESTING),
+    ))
+}
+
+// operands of `next` joined by any of `ops`, grouped to the left
+fn chain<'i>(input: &'i str, names: &[String], ops: &[Op], next: Level) -> Parsed<'i, Tree> {
+    let (mut input, (mut expr, mut depth)) = next(input, names)?;
+    loop {
+        let op = ops.iter().find_map(|&op| {
+            let matched = match op {
+                Op::Sub => minus(input),
+                op => token(op.symbol())(input),
+            };
+            matched.ok().map(|(after, _)| (after, op))
+        });
+        match op {
+            Some((after, op)) => {
+                let (after, (e, d)) = next(after, names)?;
+                depth = 1 + depth.max(d);
+                if depth > MAX_NESTING {
+                    return Err(too_deep(input));
+                }
+                expr = Expr::Binary(op, Box::new(expr), Box::new(e));
+                input = after;
+            }
+            None => return Ok((input, (expr, depth))),
+        }
+    }
+}
+
+fn disjunction<'i>(input: &'i str, names: &[String]) -> Parsed<'i, Tree> {
+    chain(input, names, &[Op::Or], conjunction)
+}
+
+fn conjunction<'i>(input: &'i str, names: &[String]) -> Parsed<'i, Tree> {
+    chain(input, names, &[Op::And], comparison)
+}
+
+fn comparison<'i>(input: &'i str, names: &[String]) -> Parsed<'i, Tree> {
+    chain(input, names, &COMPARISONS, sum)
+}
+
+fn sum<'i>(input: &'i str, names: &[String]) -> Parsed<'i, Tree> {
+    chain(input, names, &[Op::Add, Op::Sub], product)
+}
+
+fn product<'i>(input: &'i str, names: &[String]) -> Parsed<'i, Tree> {
+    chain(input, names, &[Op::Mul, Op::Div], negation)
+}
+
+// loops rather than recursing; how deep the prefixes nest is checked with what they apply to
+fn prefixes(mut input: &str) -> (&str, Vec<bool>) {
+    let mut negations = vec![];
+    loop {
+        if let Ok((after, _)) = minus(input) {
+            negations.push(true);
+            input = after;
+        } else if let Ok((after, _)) = token("!")(input) {
+            negations.push(false);
+            input = after;
+        } else {
+            return (input, negations);
+        }
+    }
+}
+
+// `at` is where the prefixes start, for the error if they nest too deep
+fn negate(
+    at: &str,
+    (mut expr, depth): Tree,
+    prefixes: Vec<bool>,
+) -> Result<Tree, nom::Err<Expected<'_>>> {
+    let depth = depth + prefixes.len();
+    if depth > MAX_NESTING {
+        return Err(too_deep(at));
+    }
+    for negation in prefixes.into_iter().rev() {
+        expr = match negation {
+            true => Expr::Neg(Box::new(expr)),
+            false => Expr::Not(Box::new(expr)),
+        };
+    }
+    Ok((expr, depth))
+}
+
+fn negation<'i>(at: &'i str, names: &[String]) -> Parsed<'i, Tree> {
+    let (input, prefixes) = prefixes(at);
+    let (input, tree) = power(input, names)?;
+    Ok((input, negate(at, tree, prefixes)?))
+}
+
+// `^` groups to the right and binds tighter than negation, except in its exponent: `-x^-2`
+// is `-(x^(-2))`
+fn power<'i>(at: &'i str, names: &[String]) -> Parsed<'i, Tree> {
+    let (mut input, first) = atom(at, names)?;
+    let mut operands = vec![first];
+    while let Ok((after, _)) = token("^")(input) {
+        let (rest, prefixes) = prefixes(after);
+        let (rest, e) = atom(rest, names)?;
+        operands.push(negate(after, e, prefixes)?);
+        input = rest;
+    }
+    let (mut expr, mut depth) = operands.pop().unwrap();
+    for (a, d) in operands.into_iter().rev() {
+        depth = 1 + depth.max(d);
+        if depth > MAX_NESTING {
+            return Err(too_deep(at));
+        }
+        expr = Expr::Binary(Op::Pow, Box::new(a), Box::new(expr));
+    }
+    Ok((input, (expr, depth)))
+}
+
+fn atom<'i>(input: &'i str, names: &[String]) -> Parsed<'i, Tree> {
+    let (input, _) = multispace0(input)?;
+    if let Ok((after, name)) = identifier(input) {
+        return match names.iter().position(|n| n == name) {
+            Some(i) => Ok((after, (Expr::Parameter(i), 1))),
+            None => Err(nom::Err::Failure(Expected::new(
+                input,
+                "one of the parameters of the rule's head",
+            ))),
+        };
+    }
+    if let Ok((after, _)) = token("(")(input) {
+        let (after, e) = disjunction(after, names)?;
+        let (after, _) = token(")")(after)?;
+        return Ok((after, e));
+    }
+    if input.starts_with(|c: char| c.is_ascii_digit() || c == '.') {
+        return map(float, |n| (Expr::Number(n), 1))(input);
+    }
+    Err(nom::Err::Error(Expected::new(
+        input,
+        "a number, a parameter or `(`",
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names() -> Vec<String> {
+        vec![String::from("x"), String::from("y")]
+    }
+
+    fn eval(input: &str) -> f32 {
+        let (rest, expr) = parse(input, &names()).unwrap();
+        assert_eq!(rest, "");
+        expr.eval(&[2.0, 3.0])
+    }
+
+    #[test]
+    fn arithmetic_follows_precedence() {
+        assert_eq!(eval("x*0.5"), 1.0);
+        assert_eq!(eval("1 + x * y"), 7.0);
+        assert_eq!(eval("(1 + x) * y"), 9.0);
+        assert_eq!(eval("10 - 4 - 3"), 3.0);
+        assert_eq!(eval("2 ^ 3 ^ 2"), 512.0);
+        assert_eq!(eval("--x"), 2.0);
+        assert_eq!(eval("-x^2"), -4.0);
+        assert_eq!(eval("1e1 / x"), 5.0);
+        assert_eq!(eval("x^-1"), 0.5);
+    }
+
+    #[test]
+    fn conditions_are_one_or_zero() {
+        assert_eq!(eval("x > 1"), 1.0);
+        assert_eq!(eval("x + 1 >= y && y != 3"), 0.0);
+        assert_eq!(eval("x == 1 || !(y < 2)"), 1.0);
+        assert_eq!(eval("!x"), 0.0);
+
+        // the arrow after a guard isn't a subtraction
+        assert_eq!(parse("x > 1 -> F", &names()).unwrap().0, " -> F");
+        assert_eq!(parse("x > 1 -0.5-> F", &names()).unwrap().0, " -0.5-> F");
+        assert_eq!(parse("x > 1 - 0.5 -> F", &names()).unwrap().0, " -> F");
+    }
+
+    #[test]
+    fn expressions_print_back() {
+        for input in [
+            "x*0.7",
+            "(x+1)*y",
+            "x-(y-1)",
+            "2^3^x",
+            "(2^3)^x",
+            "-(x+y)",
+            "-x*2",
+            "-x^2",
+            "(-x)^2",
+            "x > 1 && !(y == x || y < 0)",
+        ] {
+            let (_, expr) = parse(input, &names()).unwrap();
+            assert_eq!(expr.display(&names()).to_string(), input);
+        }
+    }
+
+    #[test]
+    fn unknown_names_are_errors() {
+        assert!(parse("x * z", &names()).is_err());
+        assert!(parse("", &names()).is_err());
+        assert!(parse("(x", &names()).is_err());
+    }
+
+    #[test]
+    fn trees_nest_at_most_max_nesting_deep() {
+        let deepest = format!("{}x", "-".repeat(MAX_NESTING - 1));
+        assert_eq!(eval(&deepest), -2.0);
+        for deeper in [
+            format!("-{}", deepest),
+            format!("x{}", "+x".repeat(MAX_NESTING)),
+            format!("x{}", "^x".repeat(MAX_NESTING)),
+        ] {
+            assert!(matches!(
+                parse(&deeper, &names()),
+                Err(nom::Err::Failure(_))
+            ));
+        }
+        assert!(parse(&format!("x{}", "+x".repeat(MAX_NESTING - 1)), &names()).is_ok());
+    }
+}
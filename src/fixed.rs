@@ -0,0 +1,83 @@
+//! Integer arithmetic for turtle positions and headings, so that fixed-point drawing gives the
+//! same bits on every platform. Values have `FRACTION_BITS` binary places.
+
+pub const FRACTION_BITS: u32 = 32;
+const ONE: i128 = 1 << FRACTION_BITS;
+
+// π/2 and 2π, each rounded to the nearest representable value
+const HALF_PI: i128 = 6_746_518_852;
+const TWO_PI: i128 = 26_986_075_409;
+
+/// The nearest fixed-point value. Scaling by a power of two and rounding are exact in IEEE
+/// arithmetic, so this is deterministic too.
+pub fn from_f32(value: f32) -> i64 {
+    (value as f64 * ONE as f64).round() as i64
+}
+
+pub fn to_f32(value: i64) -> f32 {
+    (value as f64 / ONE as f64) as f32
+}
+
+pub fn mul(a: i64, b: i64) -> i64 {
+    ((a as i128 * b as i128) >> FRACTION_BITS) as i64
+}
+
+/// `turns` times `delta` radians, reduced to `0..2π`.
+pub fn heading(turns: i64, delta: f32) -> i64 {
+    (turns as i128 * from_f32(delta) as i128).rem_euclid(TWO_PI) as i64
+}
+
+/// The sine and cosine of `angle` radians.
+pub fn sin_cos(angle: i64) -> (i64, i64) {
+    // fold into -π/4..π/4 around the nearest multiple of π/2, where the series converge fast
+    let angle = (angle as i128).rem_euclid(TWO_PI);
+    let quadrant = (angle + HALF_PI / 2) / HALF_PI;
+    let r = angle - quadrant * HALF_PI;
+    let r2 = (r * r) >> FRACTION_BITS;
+
+    let (mut sin, mut cos) = (r, ONE);
+    let (mut sin_term, mut cos_term) = (r, ONE);
+    for k in 1..=7 {
+        sin_term = -((sin_term * r2) >> FRACTION_BITS) / ((2 * k) * (2 * k + 1));
+        cos_term = -((cos_term * r2) >> FRACTION_BITS) / ((2 * k - 1) * (2 * k));
+        sin += sin_term;
+        cos += cos_term;
+    }
+
+    let (sin, cos) = match quadrant % 4 {
+        0 => (sin, cos),
+        1 => (cos, -sin),
+        2 => (-sin, -cos),
+        _ => (-cos, sin),
+    };
+    (sin as i64, cos as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constants_agree() {
+        assert!((4 * HALF_PI - TWO_PI).abs() <= 2);
+    }
+
+    #[test]
+    fn sin_cos_are_accurate() {
+        for i in -100..=100 {
+            let angle = i as f64 * 0.0731;
+            let (sin, cos) = sin_cos((angle * ONE as f64).round() as i64);
+            assert!((sin as f64 / ONE as f64 - angle.sin()).abs() < 1e-8);
+            assert!((cos as f64 / ONE as f64 - angle.cos()).abs() < 1e-8);
+        }
+    }
+
+    #[test]
+    fn headings_wrap() {
+        let quarter = std::f32::consts::FRAC_PI_2;
+        assert_eq!(heading(0, quarter), 0);
+        let behind = heading(-1, quarter) as i128;
+        assert!((behind - 3 * HALF_PI).abs() < 1 << 12);
+        assert!((heading(9, quarter) as i128 - HALF_PI).abs() < 1 << 12);
+    }
+}
@@ -0,0 +1,137 @@
+use std::fmt::Write;
+
+use crate::geometry::Segment;
+
+/// Writes segments as a GeoJSON `FeatureCollection` of `LineString`s. Segments that continue
+/// where the previous one ended are joined into one line string.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GeoJson {
+    anchor: (f64, f64),
+    scale: f64,
+    flip_y: bool,
+}
+
+impl Default for GeoJson {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GeoJson {
+    pub fn new() -> Self {
+        Self {
+            anchor: (0.0, 0.0),
+            scale: 1.0,
+            flip_y: true,
+        }
+    }
+
+    /// The coordinate, e.g. a longitude and latitude, that the turtle's start ends up at.
+    pub fn anchor(self, anchor: (f64, f64)) -> Self {
+        Self { anchor, ..self }
+    }
+
+    /// Coordinate units per turtle unit.
+    pub fn scale(self, scale: f64) -> Self {
+        Self { scale, ..self }
+    }
+
+    /// Turtle y grows downwards like on a screen, while latitude grows north. On by default.
+    pub fn flip_y(self, flip_y: bool) -> Self {
+        Self { flip_y, ..self }
+    }
+
+    fn coordinate(&self, (x, y): (f32, f32)) -> (f64, f64) {
+        let y = if self.flip_y { -y } else { y };
+        (
+            self.anchor.0 + x as f64 * self.scale,
+            self.anchor.1 + y as f64 * self.scale,
+        )
+    }
+
+    pub fn to_string(&self, segments: &[Segment]) -> String {
+        let mut lines: Vec<(Vec<(f32, f32)>, &Segment)> = vec![];
+        for segment in segments {
+            match lines.last_mut() {
+                Some((points, last))
+                    if last.end == segment.start && last.color == segment.color =>
+                {
+                    points.push(segment.end);
+                    *last = segment;
+                }
+                _ => lines.push((vec![segment.start, segment.end], segment)),
+            }
+        }
+
+        let features: Vec<String> = lines
+            .iter()
+            .map(|(points, segment)| {
+                let mut coordinates = String::new();
+                for (i, &point) in points.iter().enumerate() {
+                    let (x, y) = self.coordinate(point);
+                    let comma = if i == 0 { "" } else { "," };
+                    write!(coordinates, "{}[{},{}]", comma, x, y).unwrap();
+                }
+                let properties = match segment.color {
+                    Some(c) => format!(r##"{{"stroke":"#{:02x}{:02x}{:02x}"}}"##, c.r, c.g, c.b),
+                    None => "{}".to_string(),
+                };
+                format!(
+                    r#"{{"type":"Feature","properties":{},"geometry":{{"type":"LineString","coordinates":[{}]}}}}"#,
+                    properties, coordinates
+                )
+            })
+            .collect();
+
+        format!(
+            r#"{{"type":"FeatureCollection","features":[{}]}}"#,
+            features.join(",")
+        )
+    }
+
+    pub fn save(&self, segments: &[Segment], path: &str) -> std::io::Result<()> {
+        std::fs::write(path, self.to_string(segments))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::palette::Color;
+
+    fn segment(start: (f32, f32), end: (f32, f32)) -> Segment {
+        Segment {
+            start,
+            end,
+            color: None,
+            alpha: 1.0,
+        }
+    }
+
+    #[test]
+    fn connected_segments_share_a_line_string() {
+        let segments = [
+            segment((0.0, 0.0), (1.0, 0.0)),
+            segment((1.0, 0.0), (1.0, 1.0)),
+            Segment {
+                color: Some(Color::new(255, 0, 16)),
+                ..segment((5.0, 5.0), (6.0, 5.0))
+            },
+        ];
+        let json = GeoJson::new()
+            .anchor((10.0, 50.0))
+            .scale(0.5)
+            .to_string(&segments);
+
+        assert_eq!(
+            json,
+            concat!(
+                r#"{"type":"FeatureCollection","features":["#,
+                r#"{"type":"Feature","properties":{},"geometry":{"type":"LineString","#,
+                r#""coordinates":[[10,50],[10.5,50],[10.5,49.5]]}},"#,
+                r##"{"type":"Feature","properties":{"stroke":"#ff0010"},"geometry":"##,
+                r#"{"type":"LineString","coordinates":[[12.5,47.5],[13,47.5]]}}]}"#,
+            )
+        );
+    }
+}
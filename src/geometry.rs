@@ -0,0 +1,251 @@
+use crate::graphics::{Graphics, LineStyle};
+use crate::palette::Color;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Segment {
+    pub start: (f32, f32),
+    pub end: (f32, f32),
+    pub color: Option<Color>,
+    pub alpha: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bounds {
+    pub min: (f32, f32),
+    pub max: (f32, f32),
+}
+
+impl Bounds {
+    fn around(point: (f32, f32)) -> Self {
+        Self {
+            min: point,
+            max: point,
+        }
+    }
+
+    fn include(&mut self, (x, y): (f32, f32)) {
+        self.min = (self.min.0.min(x), self.min.1.min(y));
+        self.max = (self.max.0.max(x), self.max.1.max(y));
+    }
+
+    pub fn width(&self) -> f32 {
+        self.max.0 - self.min.0
+    }
+
+    pub fn height(&self) -> f32 {
+        self.max.1 - self.min.1
+    }
+
+    pub fn center(&self) -> (f32, f32) {
+        (
+            (self.min.0 + self.max.0) / 2.0,
+            (self.min.1 + self.max.1) / 2.0,
+        )
+    }
+}
+
+/// A `Graphics` target that keeps every line instead of drawing it, so a figure can be measured
+/// before it is placed on a real canvas.
+#[derive(Debug, Default, Clone)]
+pub struct Recorder {
+    segments: Vec<Segment>,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    pub fn bounds(&self) -> Option<Bounds> {
+        let mut points = self.segments.iter().flat_map(|s| [s.start, s.end]);
+        let mut bounds = Bounds::around(points.next()?);
+        points.for_each(|p| bounds.include(p));
+        Some(bounds)
+    }
+
+    /// The recording with every run of connected, equally styled segments reduced to as few
+    /// segments as stay within `tolerance` of it, by Douglas–Peucker.
+    pub fn simplified(&self, tolerance: f32) -> Recorder {
+        let mut simplified = Recorder::new();
+        let mut rest = &self.segments[..];
+        while let Some(first) = rest.first() {
+            let run = 1 + rest
+                .windows(2)
+                .take_while(|w| w[0].end == w[1].start && w[0].style() == w[1].style())
+                .count();
+            let mut points = vec![first.start];
+            points.extend(rest[..run].iter().map(|s| s.end));
+
+            let kept = simplify(&points, tolerance);
+            simplified.segments.extend(kept.windows(2).map(|w| Segment {
+                start: w[0],
+                end: w[1],
+                ..*first
+            }));
+            rest = &rest[run..];
+        }
+        simplified
+    }
+
+    /// Draws the recorded lines again onto another target.
+    pub fn replay<G, R>(&self, graphics: &mut G) -> Result<(), R>
+    where
+        G: Graphics<R>,
+    {
+        for segment in &self.segments {
+            graphics.draw_styled_line(segment.start, segment.end, segment.style())?;
+        }
+        Ok(())
+    }
+}
+
+impl Segment {
+    pub fn style(&self) -> LineStyle {
+        LineStyle {
+            color: self.color,
+            alpha: self.alpha,
+        }
+    }
+}
+
+impl Graphics<()> for Recorder {
+    fn draw_line(&mut self, c_0: (f32, f32), c_1: (f32, f32)) -> Result<(), ()> {
+        self.draw_styled_line(c_0, c_1, LineStyle::default())
+    }
+
+    fn draw_colored_line(
+        &mut self,
+        c_0: (f32, f32),
+        c_1: (f32, f32),
+        color: Color,
+    ) -> Result<(), ()> {
+        let style = LineStyle {
+            color: Some(color),
+            ..LineStyle::default()
+        };
+        self.draw_styled_line(c_0, c_1, style)
+    }
+
+    fn draw_styled_line(
+        &mut self,
+        c_0: (f32, f32),
+        c_1: (f32, f32),
+        style: LineStyle,
+    ) -> Result<(), ()> {
+        self.segments.push(Segment {
+            start: c_0,
+            end: c_1,
+            color: style.color,
+            alpha: style.alpha,
+        });
+        Ok(())
+    }
+}
+
+// to the nearest point of the segment from `a` to `b`, so points past its ends are kept
+fn distance_to_segment(p: (f32, f32), a: (f32, f32), b: (f32, f32)) -> f32 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let length = dx * dx + dy * dy;
+    let t = match length {
+        0.0 => 0.0,
+        _ => (((p.0 - a.0) * dx + (p.1 - a.1) * dy) / length).clamp(0.0, 1.0),
+    };
+    f32::hypot(p.0 - (a.0 + t * dx), p.1 - (a.1 + t * dy))
+}
+
+/// The points of the polyline `points` that Douglas–Peucker keeps at `tolerance`, always
+/// including both ends.
+pub fn simplify(points: &[(f32, f32)], tolerance: f32) -> Vec<(f32, f32)> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+
+    let mut keep = vec![false; points.len()];
+    let (first, last) = (0, points.len() - 1);
+    keep[first] = true;
+    keep[last] = true;
+    // a stack instead of recursion, since the curves this is for have millions of points
+    let mut spans = vec![(first, last)];
+    while let Some((start, end)) = spans.pop() {
+        let farthest = (start + 1..end)
+            .map(|i| {
+                (
+                    i,
+                    distance_to_segment(points[i], points[start], points[end]),
+                )
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        if let Some((i, distance)) = farthest {
+            if distance > tolerance {
+                keep[i] = true;
+                spans.push((start, i));
+                spans.push((i, end));
+            }
+        }
+    }
+
+    points
+        .iter()
+        .zip(keep)
+        .filter_map(|(&p, keep)| keep.then_some(p))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn simplification_drops_only_invisible_points() {
+        let mut recorder = Recorder::new();
+        let mut last = (0.0, 0.0);
+        for i in 1..=100 {
+            let next = (i as f32, if i % 2 == 0 { 0.0 } else { 0.01 });
+            recorder.draw_line(last, next).unwrap();
+            last = next;
+        }
+        for corner in [(100.0, 50.0), (0.0, 50.0)] {
+            recorder.draw_line(last, corner).unwrap();
+            last = corner;
+        }
+        recorder
+            .draw_colored_line((0.0, 50.0), (0.0, 0.0), Color::new(1, 2, 3))
+            .unwrap();
+
+        let simplified = recorder.simplified(0.1);
+        let ends: Vec<_> = simplified.segments().iter().map(|s| s.end).collect();
+        assert_eq!(
+            ends,
+            vec![(100.0, 0.0), (100.0, 50.0), (0.0, 50.0), (0.0, 0.0)]
+        );
+        assert_eq!(simplified.segments()[3].color, Some(Color::new(1, 2, 3)));
+        assert_eq!(recorder.simplified(0.001).segments().len(), 103);
+    }
+
+    #[test]
+    fn simplification_keeps_turns_back() {
+        let there_and_back = [(0.0, 0.0), (10.0, 0.0), (5.0, 0.0)];
+        assert_eq!(simplify(&there_and_back, 0.1), there_and_back);
+    }
+
+    #[test]
+    fn empty_recorder_has_no_bounds() {
+        assert_eq!(Recorder::new().bounds(), None);
+    }
+
+    #[test]
+    fn bounds_cover_all_segments() {
+        let mut recorder = Recorder::new();
+        recorder.draw_line((0.0, 1.0), (2.0, -1.0)).unwrap();
+        recorder.draw_line((-3.0, 0.0), (0.0, 4.0)).unwrap();
+
+        let bounds = recorder.bounds().unwrap();
+        assert_eq!(bounds.min, (-3.0, -1.0));
+        assert_eq!(bounds.max, (2.0, 4.0));
+        assert_eq!(bounds.center(), (-0.5, 1.5));
+    }
+}
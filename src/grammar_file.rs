@@ -0,0 +1,292 @@
+use crate::graphics::TurtleConfig;
+use crate::lsystem::LSystem;
+use crate::palette::Color;
+#[cfg(feature = "toml")]
+use crate::palette::{Palette, Palettes};
+use crate::render::RenderConfig;
+
+/// A grammar together with everything it is drawn with, so that both can live in one file
+/// instead of a grammar string here and turtle settings in code there. Written in TOML it reads
+///
+/// ```toml
+/// grammar = "X; X -> F[+X]F[-X]+X; F -> FF;"
+/// generations = 5
+/// angle = 25    # degrees
+/// step = 2
+/// colors = ["#2d5016", "#6b8e23"]     # or palette = "forest", a name or a palette file
+///
+/// [symbols]
+/// draw_forward = "F"
+/// forward = "f"
+///
+/// [render]
+/// theme = "sepia"
+/// padding = 10
+/// ```
+///
+/// where only `grammar` is required; the rest default the way `TurtleConfig` and
+/// `RenderConfig` do, a `stroke` or `background` overrides the `theme`'s, and `colors` or a
+/// `palette` its palette.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GrammarFile {
+    pub lsystem: LSystem,
+    pub generations: usize,
+    /// In degrees.
+    pub angle: f32,
+    pub step: f32,
+    pub draw_forward: String,
+    pub draw_backward: String,
+    pub forward: String,
+    pub backwards: String,
+    pub colors: Vec<Color>,
+    pub render: RenderConfig,
+}
+
+impl GrammarFile {
+    /// Settings as `TurtleConfig::default` has them, but for the angle and step the grammar
+    /// declares.
+    pub fn new(lsystem: LSystem) -> Self {
+        Self {
+            generations: 0,
+            angle: lsystem.angle().unwrap_or(45.0),
+            step: lsystem.step_size().unwrap_or(1.0),
+            lsystem,
+            draw_forward: String::from("F"),
+            draw_backward: String::from("f"),
+            forward: String::new(),
+            backwards: String::new(),
+            colors: vec![],
+            render: RenderConfig::default(),
+        }
+    }
+
+    pub fn turtle(&self) -> TurtleConfig<'_, '_, '_, '_> {
+        TurtleConfig::default()
+            .delta_ang(self.angle.to_radians())
+            .stepsize(self.step)
+            .draw_forward(&self.draw_forward)
+            .draw_backward(&self.draw_backward)
+            .forward(&self.forward)
+            .backwards(&self.backwards)
+            .colors(self.colors.clone())
+    }
+
+    /// Unknown keys are errors, so a misspelt setting doesn't go unnoticed. A `palette` is read
+    /// from the file it names.
+    #[cfg(feature = "toml")]
+    pub fn from_toml(input: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::from_toml_with(input, &Palettes::new())
+    }
+
+    /// Like `from_toml`, but a `palette` is looked up by name in `palettes` first, and only read
+    /// from a file if it isn't there.
+    #[cfg(feature = "toml")]
+    pub fn from_toml_with(
+        input: &str,
+        palettes: &Palettes,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        use toml_edit::{Document, Item};
+
+        let document = Document::parse(input)?;
+        let top = document.as_table();
+        check_keys(top.iter().map(|(key, _)| key), "", TOP)?;
+        let grammar = top
+            .get("grammar")
+            .ok_or("the file has no grammar")?
+            .as_str()
+            .ok_or("'grammar' is not a string")?;
+        let mut file = Self::new(LSystem::from_str(grammar)?);
+
+        let number = |item: &Item, key: &str| {
+            item.as_float()
+                .or_else(|| item.as_integer().map(|n| n as f64))
+                .ok_or_else(|| format!("'{}' is not a number", key))
+        };
+        let string = |item: &Item, key: &str| {
+            item.as_str()
+                .map(String::from)
+                .ok_or_else(|| format!("'{}' is not a string", key))
+        };
+        let color = |item: &Item, key: &str| Color::from_hex(&string(item, key)?);
+
+        if let Some(item) = top.get("generations") {
+            let generations = item.as_integer().filter(|n| *n >= 0);
+            file.generations = generations.ok_or("'generations' is not a count")? as usize;
+        }
+        if let Some(item) = top.get("angle") {
+            file.angle = number(item, "angle")? as f32;
+        }
+        if let Some(item) = top.get("step") {
+            file.step = number(item, "step")? as f32;
+        }
+        if let Some(item) = top.get("colors") {
+            let colors = item.as_array().ok_or("'colors' is not a list")?;
+            let hex = colors
+                .iter()
+                .map(|color| color.as_str().ok_or("'colors' holds a non-string"));
+            file.colors = hex
+                .map(|hex| Color::from_hex(hex?))
+                .collect::<Result<_, _>>()?;
+        }
+        if let Some(item) = top.get("palette") {
+            if top.contains_key("colors") {
+                return Err("'colors' and 'palette' both give the colors".into());
+            }
+            let name = string(item, "palette")?;
+            file.colors = match palettes.get(&name) {
+                Some(palette) => palette.colors().to_vec(),
+                None => Palette::from_file(&name)
+                    .map_err(|e| format!("no palette '{}': {}", name, e))?
+                    .colors()
+                    .to_vec(),
+            };
+        }
+
+        if let Some(symbols) = top.get("symbols") {
+            let symbols = symbols.as_table_like().ok_or("'symbols' is not a table")?;
+            check_keys(symbols.iter().map(|(key, _)| key), "symbols.", SYMBOLS)?;
+            for (key, item) in symbols.iter() {
+                let symbols = string(item, key)?;
+                match key {
+                    "draw_forward" => file.draw_forward = symbols,
+                    "draw_backward" => file.draw_backward = symbols,
+                    "forward" => file.forward = symbols,
+                    _ => file.backwards = symbols,
+                }
+            }
+        }
+
+        if let Some(render) = top.get("render") {
+            let render = render.as_table_like().ok_or("'render' is not a table")?;
+            check_keys(render.iter().map(|(key, _)| key), "render.", RENDER)?;
+            if let Some(item) = render.get("theme") {
+                let name = string(item, "theme")?;
+                let theme = crate::theme::Theme::by_name(&name)
+                    .ok_or_else(|| format!("unknown theme '{}'", name))?;
+                file.render = file.render.theme(&theme);
+                if !top.contains_key("colors") && !top.contains_key("palette") {
+                    file.colors = theme.palette.to_vec();
+                }
+            }
+            for (key, item) in render.iter() {
+                file.render = match key {
+                    "background" => file.render.background(Some(color(item, key)?)),
+                    "stroke" => file.render.stroke(color(item, key)?),
+                    "padding" => file.render.padding(number(item, key)? as f32),
+                    _ => file.render,
+                };
+            }
+        }
+        Ok(file)
+    }
+}
+
+#[cfg(feature = "toml")]
+const TOP: &[&str] = &[
+    "grammar",
+    "generations",
+    "angle",
+    "step",
+    "colors",
+    "palette",
+    "symbols",
+    "render",
+];
+#[cfg(feature = "toml")]
+const SYMBOLS: &[&str] = &["draw_forward", "draw_backward", "forward", "backwards"];
+#[cfg(feature = "toml")]
+const RENDER: &[&str] = &["theme", "background", "stroke", "padding"];
+
+#[cfg(feature = "toml")]
+fn check_keys<'k>(
+    mut keys: impl Iterator<Item = &'k str>,
+    prefix: &str,
+    known: &[&str],
+) -> Result<(), String> {
+    match keys.find(|key| !known.contains(key)) {
+        Some(key) => Err(format!("unknown key '{}{}'", prefix, key)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(all(test, feature = "toml"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grammars_are_read_with_their_settings() {
+        let file = GrammarFile::from_toml(
+            r##"
+            grammar = "X; X -> F[+X]F[-X]+X; F -> FF;"
+            generations = 5
+            angle = 25
+            step = 2.5
+            colors = ["#2d5016", "6b8e23"]
+
+            [symbols]
+            forward = "f"
+            draw_backward = ""
+
+            [render]
+            theme = "sepia"
+            stroke = "#ff0000"
+            "##,
+        )
+        .unwrap();
+        assert_eq!(
+            file.lsystem,
+            LSystem::from_str("X; X -> F[+X]F[-X]+X; F -> FF;").unwrap()
+        );
+        assert_eq!((file.generations, file.angle, file.step), (5, 25.0, 2.5));
+        assert_eq!(
+            file.colors,
+            [Color::new(45, 80, 22), Color::new(107, 142, 35)]
+        );
+        assert_eq!((&*file.forward, &*file.draw_forward), ("f", "F"));
+        assert_eq!(file.draw_backward, "");
+        let sepia = crate::theme::Theme::by_name("sepia").unwrap();
+        let render = RenderConfig::default()
+            .theme(&sepia)
+            .stroke(Color::new(255, 0, 0));
+        assert_eq!(file.render, render);
+        assert!((file.turtle().delta_ang - 25f32.to_radians()).abs() < 1e-6);
+
+        let themed =
+            GrammarFile::from_toml("grammar = \"F;\"\n[render]\ntheme = \"sepia\"").unwrap();
+        assert_eq!(themed.colors, sepia.palette);
+
+        let forest = [Color::new(45, 80, 22), Color::new(107, 142, 35)];
+        let mut palettes = Palettes::new();
+        palettes.insert(Palette::new("forest", forest.to_vec()));
+        let named = "grammar = \"F;\"\npalette = \"forest\"\n[render]\ntheme = \"sepia\"";
+        let named = GrammarFile::from_toml_with(named, &palettes).unwrap();
+        assert_eq!(named.colors, forest);
+        let path = std::env::temp_dir().join(format!("lsys-palette-{}.hex", std::process::id()));
+        std::fs::write(&path, "#2d5016 #6b8e23").unwrap();
+        let read = format!("grammar = \"F;\"\npalette = {:?}", path.to_str().unwrap());
+        let read = GrammarFile::from_toml(&read);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read.unwrap().colors, forest);
+
+        let declared = GrammarFile::from_toml("grammar = \"angle 60; F; F -> F+F;\"").unwrap();
+        assert_eq!((declared.angle, declared.step), (60.0, 1.0));
+
+        let error = |input: &str| GrammarFile::from_toml(input).unwrap_err().to_string();
+        assert_eq!(error("angle = 90"), "the file has no grammar");
+        assert_eq!(
+            error("grammar = \"F;\"\n[symbols]\ndraw = \"F\""),
+            "unknown key 'symbols.draw'"
+        );
+        assert_eq!(
+            error("grammar = \"F;\"\nstep = \"F\""),
+            "'step' is not a number"
+        );
+        assert!(
+            error("grammar = \"F;\"\npalette = \"no such\"").starts_with("no palette 'no such'")
+        );
+        assert_eq!(
+            error("grammar = \"F;\"\npalette = \"forest\"\ncolors = []"),
+            "'colors' and 'palette' both give the colors"
+        );
+    }
+}
@@ -1,7 +1,67 @@
-use crate::lsystem::{Instruction, Instructions};
+use std::sync::Arc;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::compress::Event;
+use crate::environment::{Environment, TurtleState};
+use crate::fixed;
+use crate::lsystem::{Instruction, LSystem};
+use crate::noise::Perlin;
+use crate::palette::{Color, Gradient};
+use crate::symbol::Symbol;
+
+/// How one line looks. A `None` color is the target's own stroke color; `alpha` is the line's
+/// opacity, from 0 to 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineStyle {
+    pub color: Option<Color>,
+    pub alpha: f32,
+}
+
+impl Default for LineStyle {
+    fn default() -> Self {
+        Self {
+            color: None,
+            alpha: 1.0,
+        }
+    }
+}
 
 pub trait Graphics<R> {
     fn draw_line(&mut self, c_0: (f32, f32), c_1: (f32, f32)) -> Result<(), R>;
+
+    /// Used instead of `draw_line` when the turtle has a color table. Targets without color
+    /// support can ignore the color.
+    fn draw_colored_line(
+        &mut self,
+        c_0: (f32, f32),
+        c_1: (f32, f32),
+        _color: Color,
+    ) -> Result<(), R> {
+        self.draw_line(c_0, c_1)
+    }
+
+    /// Used for every line the turtle draws. Targets without transparency ignore the alpha.
+    fn draw_styled_line(
+        &mut self,
+        c_0: (f32, f32),
+        c_1: (f32, f32),
+        style: LineStyle,
+    ) -> Result<(), R> {
+        match style.color {
+            Some(color) => self.draw_colored_line(c_0, c_1, color),
+            None => self.draw_line(c_0, c_1),
+        }
+    }
+
+    /// Called before the turtle enters a branch, with where it stands and which way it heads.
+    fn begin_branch(&mut self, _origin: (f32, f32), _angle: f32) -> Result<(), R> {
+        Ok(())
+    }
+
+    fn end_branch(&mut self) -> Result<(), R> {
+        Ok(())
+    }
 }
 
 #[derive(Clone)]
@@ -9,25 +69,83 @@ pub struct Turtle<'a, 'b, 'c, 'd, 'e> {
     x: f32,
     y: f32,
     angle: f32,
+    color: usize,
+    home: (f32, f32, f32),
+    stepsize: f32,
+    travelled: f32,
+    // position and heading in fixed-point mode, where `x`, `y` and `angle` only mirror them
+    fixed_pos: (i64, i64),
+    turns: i64,
+    rng: Option<StdRng>,
     config: &'a TurtleConfig<'b, 'c, 'd, 'e>,
 }
 
+#[derive(Clone)]
 pub struct TurtleConfig<'a, 'b, 'c, 'd> {
-    delta_ang: f32,
-    stepsize: f32,
+    pub(crate) delta_ang: f32,
+    pub(crate) stepsize: f32,
     draw_forward: &'a str,
     draw_backward: &'b str,
     forward: &'c str,
     backwards: &'d str,
+    colors: Vec<Color>,
+    heading_grid: Option<f32>,
+    home: Option<Symbol>,
+    field: Option<Field>,
+    jitter: Option<(f32, u64)>,
+    noise: Option<(Perlin, f32, f32)>,
+    step_decay: f32,
+    gradient: Option<(Gradient, Along)>,
+    opacity: f32,
+    fixed_point: bool,
+}
+
+/// What a gradient is stretched along.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Along {
+    /// Everything drawn, in drawing order.
+    Path,
+    /// The distance drawn from the start down to each line, so every tip of a tree ends in the
+    /// same color.
+    Branch,
+}
+
+#[derive(Default)]
+struct Progress<'s> {
+    drawn: f32,
+    furthest: f32,
+    total: Option<f32>,
+    should_continue: Option<&'s mut dyn FnMut() -> bool>,
+    stopped: bool,
+}
+
+impl Progress<'_> {
+    // asked before every instruction, and never again once it says no
+    fn stopped(&mut self) -> bool {
+        if let (false, Some(should_continue)) = (self.stopped, &mut self.should_continue) {
+            self.stopped = !should_continue();
+        }
+        self.stopped
+    }
+}
+
+struct Measure;
+
+impl<R> Graphics<R> for Measure {
+    fn draw_line(&mut self, _: (f32, f32), _: (f32, f32)) -> Result<(), R> {
+        Ok(())
+    }
 }
 
+type Field = Arc<dyn Fn(f32, f32) -> (f32, f32) + Send + Sync>;
+
 impl<'a, 'b, 'c, 'd> Default for TurtleConfig<'a, 'b, 'c, 'd> {
     fn default() -> Self {
         Self::new()
     }
 }
 
-enum Step {
+pub(crate) enum Step {
     Forward,
     Backward,
     DrawForward,
@@ -43,6 +161,16 @@ impl<'a, 'b, 'c, 'd> TurtleConfig<'a, 'b, 'c, 'd> {
             draw_backward: "f",
             forward: "",
             backwards: "",
+            colors: vec![],
+            heading_grid: None,
+            home: None,
+            field: None,
+            jitter: None,
+            noise: None,
+            step_decay: 1.0,
+            gradient: None,
+            opacity: 1.0,
+            fixed_point: false,
         }
     }
 
@@ -58,6 +186,20 @@ impl<'a, 'b, 'c, 'd> TurtleConfig<'a, 'b, 'c, 'd> {
         Self { stepsize, ..self }
     }
 
+    /// The angle the turtle turns by, in radians.
+    pub fn angle(&self) -> f32 {
+        self.delta_ang
+    }
+
+    /// The angle and step a grammar declares with `angle 25.7;` and `step 4;`, where it does.
+    pub fn declared_by(self, lsystem: &LSystem) -> Self {
+        Self {
+            delta_ang: lsystem.angle().map_or(self.delta_ang, f32::to_radians),
+            stepsize: lsystem.step_size().unwrap_or(self.stepsize),
+            ..self
+        }
+    }
+
     pub fn draw_forward(self, draw_forward: &'a str) -> Self {
         Self {
             draw_forward,
@@ -65,7 +207,6 @@ impl<'a, 'b, 'c, 'd> TurtleConfig<'a, 'b, 'c, 'd> {
         }
     }
 
-    #[allow(dead_code)]
     pub fn draw_backward(self, draw_backward: &'b str) -> Self {
         Self {
             draw_backward,
@@ -73,30 +214,118 @@ impl<'a, 'b, 'c, 'd> TurtleConfig<'a, 'b, 'c, 'd> {
         }
     }
 
-    #[allow(dead_code)]
     pub fn forward(self, forward: &'c str) -> Self {
         Self { forward, ..self }
     }
 
-    #[allow(dead_code)]
     pub fn backwards(self, backwards: &'d str) -> Self {
         Self { backwards, ..self }
     }
 
-    fn classify(&self, symbol: char) -> Option<Step> {
+    /// The color table; `'` advances to the next color, wrapping around at the end.
+    pub fn colors(self, colors: Vec<Color>) -> Self {
+        Self { colors, ..self }
+    }
+
+    /// Rounds the heading to the nearest multiple of `grid` after every turn, so rounding errors
+    /// don't pile up over many turns and closed curves actually close.
+    pub fn snap_heading(self, grid: f32) -> Self {
+        Self {
+            heading_grid: Some(grid).filter(|grid| *grid > 0.0),
+            ..self
+        }
+    }
+
+    /// A symbol that moves the turtle back to where it started, facing the way it started,
+    /// without drawing.
+    pub fn home(self, home: impl Into<Symbol>) -> Self {
+        Self {
+            home: Some(home.into()),
+            ..self
+        }
+    }
+
+    /// Bends the turtle towards a vector field before every step, the way tropism bends plants:
+    /// the heading turns towards the field at the turtle's position by the cross product of the
+    /// two, so the field's magnitude is how strongly it bends.
+    pub fn field(self, field: impl Fn(f32, f32) -> (f32, f32) + Send + Sync + 'static) -> Self {
+        Self {
+            field: Some(Arc::new(field)),
+            ..self
+        }
+    }
+
+    /// Turns the heading by up to `amount` before every step, following smooth noise sampled at
+    /// the turtle's position with features about `scale` apart. Unlike jitter, nearby steps turn
+    /// alike, so branches curve as if blown by wind.
+    pub fn noise(self, amount: f32, scale: f32, seed: u64) -> Self {
+        Self {
+            noise: Some((Perlin::new(seed), amount, scale))
+                .filter(|_| amount != 0.0 && scale > 0.0),
+            ..self
+        }
+    }
+
+    /// Adds a random angle between `-amount` and `amount` to every turn. The same seed always
+    /// gives the same figure.
+    pub fn jitter(self, amount: f32, seed: u64) -> Self {
+        Self {
+            jitter: Some((amount.abs(), seed)).filter(|(amount, _)| *amount > 0.0),
+            ..self
+        }
+    }
+
+    /// Every branch steps `decay` times as far as the sequence it branches off from.
+    pub fn step_decay(self, step_decay: f32) -> Self {
+        Self { step_decay, ..self }
+    }
+
+    /// Colors lines by how far along the drawing they are, instead of by the color table.
+    pub fn gradient(self, gradient: Gradient, along: Along) -> Self {
+        Self {
+            gradient: Some((gradient, along)),
+            ..self
+        }
+    }
+
+    /// The alpha of every line. Many faint lines on top of each other build up density instead of
+    /// a solid blob, on targets that support it.
+    pub fn opacity(self, opacity: f32) -> Self {
+        Self {
+            opacity: opacity.clamp(0.0, 1.0),
+            ..self
+        }
+    }
+
+    /// Keeps positions in integers and headings as a count of turns, so that the same word draws
+    /// the same bits on every platform. Jitter, noise, heading snapping and fields don't
+    /// apply.
+    pub fn fixed_point(self, fixed_point: bool) -> Self {
+        Self {
+            fixed_point,
+            ..self
+        }
+    }
+
+    pub(crate) fn classify(&self, symbol: &Symbol) -> Option<Step> {
         use Step::*;
-        if self.draw_forward.contains(symbol) {
+        if symbol.is_in(self.draw_forward) {
             Some(DrawForward)
-        } else if self.draw_backward.contains(symbol) {
+        } else if symbol.is_in(self.draw_backward) {
             Some(DrawBackward)
-        } else if self.forward.contains(symbol) {
+        } else if symbol.is_in(self.forward) {
             Some(Forward)
-        } else if self.backwards.contains(symbol) {
+        } else if symbol.is_in(self.backwards) {
             Some(Backward)
         } else {
             None
         }
     }
+
+    // whether the turtle does anything when it meets this symbol
+    pub(crate) fn acts_on(&self, symbol: &Symbol) -> bool {
+        symbol.is_in("+-'") || self.home.as_ref() == Some(symbol) || self.classify(symbol).is_some()
+    }
 }
 
 impl<'a, 'b, 'c, 'd, 'e> Turtle<'a, 'b, 'c, 'd, 'e> {
@@ -105,67 +334,707 @@ impl<'a, 'b, 'c, 'd, 'e> Turtle<'a, 'b, 'c, 'd, 'e> {
             x: 0.0,
             y: 0.0,
             angle: 0.0,
+            color: 0,
+            home: (0.0, 0.0, 0.0),
+            stepsize: config.stepsize,
+            travelled: 0.0,
+            fixed_pos: (0, 0),
+            turns: 0,
+            rng: config.jitter.map(|(_, seed)| StdRng::seed_from_u64(seed)),
             config,
         }
     }
 
+    // a branch draws from its own generator, seeded from its parent's, so it doesn't repeat its
+    // siblings' jitter
+    fn branch(&mut self) -> Self {
+        let mut branch = self.clone();
+        branch.stepsize *= self.config.step_decay;
+        if let Some(rng) = &mut self.rng {
+            branch.rng = Some(StdRng::seed_from_u64(rng.gen()));
+        }
+        branch
+    }
+
     fn pos(&self) -> (f32, f32) {
         (self.x, self.y)
     }
 
+    fn line<G, R>(
+        &mut self,
+        graphics: &mut G,
+        before: (f32, f32),
+        progress: &mut Progress,
+    ) -> Result<(), R>
+    where
+        G: Graphics<R>,
+    {
+        let length = f32::hypot(self.x - before.0, self.y - before.1);
+        let start = match self.config.gradient {
+            Some((_, Along::Path)) => progress.drawn,
+            _ => self.travelled,
+        };
+        progress.drawn += length;
+        self.travelled += length;
+        progress.furthest = progress.furthest.max(self.travelled);
+
+        let colors = &self.config.colors;
+        let color =
+            if let (Some((gradient, _)), Some(total)) = (&self.config.gradient, progress.total) {
+                let t = (start + length / 2.0) / total.max(f32::EPSILON);
+                Some(gradient.at(t))
+            } else if colors.is_empty() {
+                None
+            } else {
+                Some(colors[self.color % colors.len()])
+            };
+        let style = LineStyle {
+            color,
+            alpha: self.config.opacity,
+        };
+        graphics.draw_styled_line(before, self.pos(), style)
+    }
+
+    fn go_home(&mut self) {
+        (self.x, self.y, self.angle) = self.home;
+        (self.fixed_pos, self.turns) = ((0, 0), 0);
+    }
+
+    fn ahead(&self) -> (f32, f32) {
+        (
+            self.x + f32::cos(self.angle) * self.stepsize,
+            self.y + f32::sin(self.angle) * self.stepsize,
+        )
+    }
+
+    fn bend(&mut self) {
+        if let Some(field) = &self.config.field {
+            let (fx, fy) = field(self.x, self.y);
+            let torque = f32::cos(self.angle) * fy - f32::sin(self.angle) * fx;
+            if torque.is_finite() {
+                self.angle = (self.angle + torque).rem_euclid(2.0 * std::f32::consts::PI);
+            }
+        }
+        if let Some((noise, amount, scale)) = &self.config.noise {
+            self.angle += amount * noise.at(self.x / scale, self.y / scale);
+        }
+    }
+
+    fn step_fixed(&mut self, sign: i64) {
+        let (sin, cos) = fixed::sin_cos(fixed::heading(self.turns, self.config.delta_ang));
+        let step = sign * fixed::from_f32(self.stepsize);
+        self.fixed_pos.0 += fixed::mul(cos, step);
+        self.fixed_pos.1 += fixed::mul(sin, step);
+        (self.x, self.y) = (
+            fixed::to_f32(self.fixed_pos.0),
+            fixed::to_f32(self.fixed_pos.1),
+        );
+    }
+
+    fn turn_fixed(&mut self, turns: i64) {
+        self.turns += turns;
+        self.angle = fixed::to_f32(fixed::heading(self.turns, self.config.delta_ang));
+    }
+
     fn step_forward(&mut self) {
-        self.x += f32::cos(self.angle) * self.config.stepsize;
-        self.y += f32::sin(self.angle) * self.config.stepsize;
+        if self.config.fixed_point {
+            return self.step_fixed(1);
+        }
+        self.bend();
+        self.x += f32::cos(self.angle) * self.stepsize;
+        self.y += f32::sin(self.angle) * self.stepsize;
     }
 
     fn step_backwards(&mut self) {
-        self.x -= f32::cos(self.angle) * self.config.stepsize;
-        self.y -= f32::sin(self.angle) * self.config.stepsize;
+        if self.config.fixed_point {
+            return self.step_fixed(-1);
+        }
+        self.bend();
+        self.x -= f32::cos(self.angle) * self.stepsize;
+        self.y -= f32::sin(self.angle) * self.stepsize;
+    }
+
+    fn turn(&mut self, delta: f32) {
+        let mut angle = self.angle + delta;
+        if let (Some(rng), Some((amount, _))) = (&mut self.rng, self.config.jitter) {
+            angle += rng.gen_range(-amount..=amount);
+        }
+        if let Some(grid) = self.config.heading_grid {
+            angle = (angle / grid).round() * grid;
+        }
+        self.angle = angle.rem_euclid(2.0 * std::f32::consts::PI);
     }
 
     fn turn_left(&mut self) {
-        self.angle = (self.angle - self.config.delta_ang).rem_euclid(2.0 * std::f32::consts::PI);
+        if self.config.fixed_point {
+            self.turn_fixed(-1);
+        } else {
+            self.turn(-self.config.delta_ang);
+        }
     }
 
     fn turn_right(&mut self) {
-        self.angle = (self.angle + self.config.delta_ang).rem_euclid(2.0 * std::f32::consts::PI);
+        if self.config.fixed_point {
+            self.turn_fixed(1);
+        } else {
+            self.turn(self.config.delta_ang);
+        }
+    }
+
+    /// Draws a derived word, or a `Word` parsed from text.
+    pub fn draw<G, R>(
+        self,
+        graphics: &mut G,
+        instructions: impl AsRef<[Instruction]>,
+    ) -> Result<(), R>
+    where
+        G: Graphics<R>,
+    {
+        let instructions = instructions.as_ref();
+        let mut progress = self.measure(instructions, None);
+        self.run(graphics, instructions, None, &mut progress)
+    }
+
+    /// Like `draw`, but asks `should_continue` before every instruction and stops drawing as
+    /// soon as it says no, e.g. when a user gives up on a large drawing. Whether the whole word
+    /// was drawn.
+    pub fn draw_while<G, R>(
+        self,
+        graphics: &mut G,
+        instructions: impl AsRef<[Instruction]>,
+        mut should_continue: impl FnMut() -> bool,
+    ) -> Result<bool, R>
+    where
+        G: Graphics<R>,
+    {
+        let instructions = instructions.as_ref();
+        let mut progress = Progress {
+            should_continue: Some(&mut should_continue),
+            ..self.measure(instructions, None)
+        };
+        self.run(graphics, instructions, None, &mut progress)?;
+        Ok(!progress.stopped)
+    }
+
+    /// Like `draw`, but also marks every line in `env` and answers its query symbols.
+    pub fn draw_in<G, R>(
+        self,
+        graphics: &mut G,
+        instructions: &[Instruction],
+        env: &mut Environment,
+    ) -> Result<(), R>
+    where
+        G: Graphics<R>,
+    {
+        let mut progress = self.measure(instructions, Some(env));
+        self.run(graphics, instructions, Some(env), &mut progress)
     }
 
-    pub fn draw<G, R>(mut self, graphics: &mut G, instructions: Instructions) -> Result<(), R>
+    // a gradient needs the length of the whole drawing before the first line is colored
+    fn measure<'s>(&self, instructions: &[Instruction], env: Option<&Environment>) -> Progress<'s> {
+        let along = match self.config.gradient {
+            Some((_, along)) => along,
+            None => return Progress::default(),
+        };
+        let mut env = env.cloned();
+        let mut measured = Progress::default();
+        let _: Result<(), ()> =
+            self.clone()
+                .run(&mut Measure, instructions, env.as_mut(), &mut measured);
+        Progress {
+            total: Some(match along {
+                Along::Path => measured.drawn,
+                Along::Branch => measured.furthest,
+            }),
+            ..Progress::default()
+        }
+    }
+
+    /// Like `draw`, but for a stream of events such as a `Decoder` reads, so the whole word
+    /// never has to be in memory. Gradients can't know the length of a stream and aren't used.
+    pub fn draw_events<G, R>(
+        self,
+        graphics: &mut G,
+        events: impl IntoIterator<Item = Event>,
+    ) -> Result<(), R>
+    where
+        G: Graphics<R>,
+    {
+        let mut progress = Progress::default();
+        let mut parents = vec![];
+        let mut turtle = self;
+        // after a cut, how many branches deep the events being skipped are
+        let mut cut: Option<usize> = None;
+        for event in events {
+            if let Some(depth) = &mut cut {
+                match event {
+                    Event::Push => *depth += 1,
+                    Event::Pop if *depth > 0 => *depth -= 1,
+                    Event::Pop => cut = None,
+                    _ => {}
+                }
+                if cut.is_some() {
+                    continue;
+                }
+            }
+            match event {
+                Event::Symbol(s) if s == '%' => cut = Some(0),
+                Event::Symbol(s) => turtle.symbol(&s, graphics, None, &mut progress)?,
+                Event::Module(s, values) => {
+                    turtle.module(&s, &values.0, graphics, None, &mut progress)?
+                }
+                Event::Push => {
+                    graphics.begin_branch(turtle.pos(), turtle.angle)?;
+                    let branch = turtle.branch();
+                    parents.push(std::mem::replace(&mut turtle, branch));
+                }
+                Event::Pop => {
+                    if let Some(parent) = parents.pop() {
+                        turtle = parent;
+                        graphics.end_branch()?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn run<G, R>(
+        mut self,
+        graphics: &mut G,
+        instructions: &[Instruction],
+        mut env: Option<&mut Environment>,
+        progress: &mut Progress,
+    ) -> Result<(), R>
     where
         G: Graphics<R>,
     {
         for instruction in instructions {
-            use Instruction::*;
+            if progress.stopped() {
+                break;
+            }
             match instruction {
-                Symbol('+') => self.turn_left(),
-                Symbol('-') => self.turn_right(),
-                Symbol(c) => {
-                    if let Some(step) = self.config.classify(c) {
-                        let before = self.pos();
-                        match step {
-                            Step::Forward => {
-                                self.step_forward();
-                            }
-                            Step::DrawForward => {
-                                self.step_forward();
-
-                                graphics.draw_line(before, self.pos())?;
-                            }
-                            Step::Backward => {
-                                self.step_backwards();
-                            }
-                            Step::DrawBackward => {
-                                self.step_backwards();
-                                graphics.draw_line(before, self.pos())?;
-                            }
+                Instruction::Symbol(s) if *s == '%' => break,
+                Instruction::Symbol(s) => self.symbol(s, graphics, env.as_deref_mut(), progress)?,
+                Instruction::Module(s, values) => {
+                    self.module(s, &values.0, graphics, env.as_deref_mut(), progress)?
+                }
+                Instruction::Branch(ins) => {
+                    graphics.begin_branch(self.pos(), self.angle)?;
+                    self.branch()
+                        .run(graphics, ins, env.as_deref_mut(), progress)?;
+                    graphics.end_branch()?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // the first value of a module is the length of a step or the degrees of a turn; modules of
+    // other symbols, and turns on the fixed point grid, act like their plain symbol
+    fn module<G, R>(
+        &mut self,
+        s: &Symbol,
+        values: &[f32],
+        graphics: &mut G,
+        env: Option<&mut Environment>,
+        progress: &mut Progress,
+    ) -> Result<(), R>
+    where
+        G: Graphics<R>,
+    {
+        match values.first() {
+            Some(degrees) if *s == '+' && !self.config.fixed_point => {
+                self.turn(-degrees.to_radians())
+            }
+            Some(degrees) if *s == '-' && !self.config.fixed_point => {
+                self.turn(degrees.to_radians())
+            }
+            Some(&length) if self.config.classify(s).is_some() => {
+                let stepsize = std::mem::replace(&mut self.stepsize, length);
+                let drawn = self.symbol(s, graphics, env, progress);
+                self.stepsize = stepsize;
+                drawn?
+            }
+            _ => self.symbol(s, graphics, env, progress)?,
+        }
+        Ok(())
+    }
+
+    fn symbol<G, R>(
+        &mut self,
+        s: &Symbol,
+        graphics: &mut G,
+        env: Option<&mut Environment>,
+        progress: &mut Progress,
+    ) -> Result<(), R>
+    where
+        G: Graphics<R>,
+    {
+        match s {
+            s if *s == '+' => self.turn_left(),
+            s if *s == '-' => self.turn_right(),
+            s if *s == '\'' => self.color += 1,
+            s if self.config.home.as_ref() == Some(s) => self.go_home(),
+            s if env.as_ref().is_some_and(|env| env.query_symbol() == s) => {
+                if let Some(env) = env {
+                    env.answer(self.ahead());
+                }
+            }
+            s if env.as_ref().is_some_and(|env| env.responds_to(s)) => {
+                if let Some(env) = env {
+                    let state = TurtleState {
+                        position: self.pos(),
+                        heading: self.angle,
+                    };
+                    env.record(s, &state);
+                }
+            }
+            s => {
+                if let Some(step) = self.config.classify(s) {
+                    let before = self.pos();
+                    match step {
+                        Step::Forward => {
+                            self.step_forward();
+                        }
+                        Step::DrawForward => {
+                            self.step_forward();
+
+                            self.line(graphics, before, progress)?;
+                        }
+                        Step::Backward => {
+                            self.step_backwards();
+                        }
+                        Step::DrawBackward => {
+                            self.step_backwards();
+                            self.line(graphics, before, progress)?;
                         }
                     }
+
+                    if let (Some(env), Step::DrawForward | Step::DrawBackward) = (env, step) {
+                        env.mark(before, self.pos());
+                    }
                 }
-                Branch(ins) => self.clone().draw(graphics, ins)?,
             }
         }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Recorder;
+    use crate::lsystem::{LSystem, Word};
+
+    #[test]
+    fn home_returns_to_the_start() {
+        let word = LSystem::from_str("+F@F;").unwrap().word().clone();
+        let mut recorder = Recorder::new();
+        TurtleConfig::default()
+            .home('@')
+            .create_turtle()
+            .draw(&mut recorder, word)
+            .unwrap();
+
+        let segments = recorder.segments();
+        assert_eq!(segments[1].start, (0.0, 0.0));
+        assert_eq!(segments[1].end, (1.0, 0.0));
+        assert_ne!(segments[0].end, segments[1].end);
+    }
+
+    #[test]
+    fn modules_set_their_step_and_turn() {
+        let word = Word::parse("F(2)-(90)F(0.5)F").unwrap();
+        let mut recorder = Recorder::new();
+        TurtleConfig::default()
+            .create_turtle()
+            .draw(&mut recorder, word)
+            .unwrap();
+
+        let ends: Vec<_> = recorder
+            .segments()
+            .iter()
+            .map(|s| (s.end.0.round(), (s.end.1 * 2.0).round() / 2.0))
+            .collect();
+        assert_eq!(ends, [(2.0, 0.0), (2.0, 0.5), (2.0, 1.5)]);
+    }
+
+    #[test]
+    fn cuts_end_their_branch() {
+        let word = Word::parse("F[F%F[F]]F%F").unwrap();
+        let mut drawn = Recorder::new();
+        TurtleConfig::default()
+            .create_turtle()
+            .draw(&mut drawn, &word)
+            .unwrap();
+        assert_eq!(drawn.segments().len(), 3);
+
+        let mut compressed = vec![];
+        crate::compress::compress(word.instructions(), &mut compressed).unwrap();
+        let mut streamed = Recorder::new();
+        TurtleConfig::default()
+            .create_turtle()
+            .draw_events(
+                &mut streamed,
+                crate::compress::Decoder::new(&compressed[..]).unwrap(),
+            )
+            .unwrap();
+        assert_eq!(streamed.segments(), drawn.segments());
+    }
+
+    #[test]
+    fn field_bends_towards_itself() {
+        let word = LSystem::from_str("FFFFFFFFFFFFFFFFFFFFFFFFFFFFFF;")
+            .unwrap()
+            .word()
+            .clone();
+        let mut recorder = Recorder::new();
+        TurtleConfig::default()
+            .field(|_, _| (0.0, 0.2))
+            .create_turtle()
+            .draw(&mut recorder, word)
+            .unwrap();
+
+        let segments = recorder.segments();
+        let direction = |i: usize| {
+            let segment: &crate::geometry::Segment = &segments[i];
+            (segment.end.1 - segment.start.1).atan2(segment.end.0 - segment.start.0)
+        };
+        assert!((direction(0) - 0.2).abs() < 1e-6);
+        assert!(direction(1) > direction(0));
+        assert!((direction(29) - std::f32::consts::FRAC_PI_2).abs() < 0.05);
+    }
+
+    #[test]
+    fn noise_curves_smoothly() {
+        let word = LSystem::from_str("FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF;")
+            .unwrap()
+            .word()
+            .clone();
+        let draw = |config: TurtleConfig| {
+            let mut recorder = Recorder::new();
+            config.create_turtle().draw(&mut recorder, &word).unwrap();
+            recorder.segments().to_vec()
+        };
+
+        let segments = draw(TurtleConfig::default().stepsize(0.1).noise(0.3, 1.0, 7));
+        assert_eq!(
+            segments,
+            draw(TurtleConfig::default().stepsize(0.1).noise(0.3, 1.0, 7))
+        );
+        let direction =
+            |s: &crate::geometry::Segment| (s.end.1 - s.start.1).atan2(s.end.0 - s.start.0);
+        let turns: Vec<f32> = segments
+            .windows(2)
+            .map(|pair| direction(&pair[1]) - direction(&pair[0]))
+            .collect();
+        assert!(turns.iter().any(|turn| turn.abs() > 1e-3));
+        for pair in turns.windows(2) {
+            assert!((pair[1] - pair[0]).abs() < 0.1);
+        }
+    }
+
+    #[test]
+    fn jitter_is_seeded() {
+        let word = LSystem::from_str("F; F -> F[+F][-F]F;")
+            .unwrap()
+            .nth(3)
+            .unwrap();
+        let draw = |turtle: TurtleConfig| {
+            let mut recorder = Recorder::new();
+            turtle
+                .create_turtle()
+                .draw(&mut recorder, word.clone())
+                .unwrap();
+            recorder.segments().to_vec()
+        };
+
+        let plain = draw(TurtleConfig::default());
+        let jittered = draw(TurtleConfig::default().jitter(0.1, 1));
+        assert_ne!(plain, jittered);
+        assert_eq!(jittered, draw(TurtleConfig::default().jitter(0.1, 1)));
+        assert_ne!(jittered, draw(TurtleConfig::default().jitter(0.1, 2)));
+    }
+
+    #[test]
+    fn branches_decay() {
+        let word = LSystem::from_str("F[F[F]];").unwrap().word().clone();
+        let mut recorder = Recorder::new();
+        TurtleConfig::default()
+            .step_decay(0.5)
+            .create_turtle()
+            .draw(&mut recorder, word)
+            .unwrap();
+
+        let lengths: Vec<_> = recorder
+            .segments()
+            .iter()
+            .map(|s| s.end.0 - s.start.0)
+            .collect();
+        assert_eq!(lengths, vec![1.0, 0.5, 0.25]);
+    }
+
+    #[test]
+    fn gradient_follows_the_drawing() {
+        let word = LSystem::from_str("FF[FF]F;").unwrap().word().clone();
+        let (black, white) = (Color::new(0, 0, 0), Color::new(250, 250, 250));
+        let colors = |along| {
+            let mut recorder = Recorder::new();
+            TurtleConfig::default()
+                .gradient(Gradient::even(&[black, white]), along)
+                .create_turtle()
+                .draw(&mut recorder, word.clone())
+                .unwrap();
+            let segments = recorder.segments().to_vec();
+            segments
+                .iter()
+                .map(|s| s.color.unwrap().r)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(colors(Along::Path), vec![25, 75, 125, 175, 225]);
+        assert_eq!(colors(Along::Branch), vec![31, 94, 156, 219, 156]);
+    }
+
+    #[test]
+    fn opacity_reaches_every_line() {
+        let word = LSystem::from_str("F[F]F;").unwrap().word().clone();
+        let mut recorder = Recorder::new();
+        TurtleConfig::default()
+            .opacity(0.25)
+            .create_turtle()
+            .draw(&mut recorder, word)
+            .unwrap();
+        assert!(recorder.segments().iter().all(|s| s.alpha == 0.25));
+    }
+
+    #[test]
+    fn parsed_words_draw_like_derived_ones() {
+        let derived = LSystem::from_str("F; F -> F+F;").unwrap().nth(3).unwrap();
+        let parsed = Word::parse(&Word::from(derived.clone()).to_string()).unwrap();
+
+        let (mut a, mut b) = (Recorder::new(), Recorder::new());
+        let config = TurtleConfig::default();
+        config.create_turtle().draw(&mut a, derived).unwrap();
+        config.create_turtle().draw(&mut b, &parsed).unwrap();
+        assert_eq!(a.segments(), b.segments());
+    }
+
+    #[test]
+    fn fixed_point_follows_floating_point() {
+        let word = LSystem::from_str("FX; X -> X+YF+; Y -> -FX-Y;")
+            .unwrap()
+            .nth(8)
+            .unwrap();
+        let config = TurtleConfig::default().delta_ang(std::f32::consts::FRAC_PI_2);
+        let (mut float, mut fixed) = (Recorder::new(), Recorder::new());
+        config.create_turtle().draw(&mut float, &word).unwrap();
+        config
+            .clone()
+            .fixed_point(true)
+            .create_turtle()
+            .draw(&mut fixed, &word)
+            .unwrap();
+
+        assert_eq!(float.segments().len(), fixed.segments().len());
+        for (a, b) in float.segments().iter().zip(fixed.segments()) {
+            assert!((a.end.0 - b.end.0).abs() < 1e-3 && (a.end.1 - b.end.1).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn streamed_words_draw_like_whole_ones() {
+        let word = LSystem::from_str("X; X -> F[+X][-X]FX; F -> FF;")
+            .unwrap()
+            .nth(5)
+            .unwrap();
+        let mut compressed = vec![];
+        crate::compress::compress(&word, &mut compressed).unwrap();
+
+        let config = TurtleConfig::default().step_decay(0.8).home('X');
+        let (mut whole, mut streamed) = (Recorder::new(), Recorder::new());
+        config.create_turtle().draw(&mut whole, &word).unwrap();
+        let mut decoder = crate::compress::Decoder::new(&compressed[..]).unwrap();
+        config
+            .create_turtle()
+            .draw_events(&mut streamed, &mut decoder)
+            .unwrap();
+        decoder.finish().unwrap();
+        assert_eq!(whole.segments(), streamed.segments());
+    }
+
+    #[test]
+    fn drawing_stops_when_asked() {
+        let word = LSystem::from_str("F; F -> F[+F]F;")
+            .unwrap()
+            .nth(3)
+            .unwrap();
+        let config = TurtleConfig::default();
+        let mut whole = Recorder::new();
+        config.create_turtle().draw(&mut whole, &word).unwrap();
+
+        let mut asked = 0;
+        let mut partial = Recorder::new();
+        let finished = config
+            .create_turtle()
+            .draw_while(&mut partial, &word, || {
+                asked += 1;
+                asked <= 10
+            })
+            .unwrap();
+        assert!(!finished);
+        assert_eq!(asked, 11);
+        assert!(!partial.segments().is_empty());
+        assert!(partial.segments().len() < whole.segments().len());
+        assert_eq!(
+            partial.segments(),
+            &whole.segments()[..partial.segments().len()]
+        );
+
+        let mut again = Recorder::new();
+        let finished = config
+            .create_turtle()
+            .draw_while(&mut again, &word, || true);
+        assert!(finished.unwrap());
+        assert_eq!(again.segments(), whole.segments());
+    }
+
+    #[test]
+    fn snapped_heading_does_not_drift() {
+        let grid = 2.0 * std::f32::consts::PI / 7.0;
+        let config = TurtleConfig::default().delta_ang(grid);
+        let snapped = config.clone().snap_heading(grid);
+        let (mut free, mut snapped) = (config.create_turtle(), snapped.create_turtle());
+        for _ in 0..700_000 {
+            free.turn_left();
+            snapped.turn_left();
+        }
+        assert_ne!(free.angle, 0.0);
+        assert_eq!(snapped.angle, 0.0);
+    }
+
+    #[test]
+    fn snapped_curves_close() {
+        let word = LSystem::from_str("F--F--F; F -> F+F--F+F;")
+            .unwrap()
+            .nth(7)
+            .unwrap();
+        let distance_from_start = |turtle: TurtleConfig| {
+            let mut recorder = Recorder::new();
+            turtle
+                .create_turtle()
+                .draw(&mut recorder, word.clone())
+                .unwrap();
+            let (x, y) = recorder.segments().last().unwrap().end;
+            x.hypot(y)
+        };
+
+        let sixty = TurtleConfig::default().delta_ang(std::f32::consts::FRAC_PI_3);
+        let drifted = distance_from_start(sixty.clone());
+        let snapped = distance_from_start(sixty.snap_heading(std::f32::consts::FRAC_PI_3));
+        assert!(snapped < drifted / 2.0);
+    }
+}
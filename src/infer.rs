@@ -0,0 +1,185 @@
+use std::ops::Range;
+
+use crate::lsystem::{Instruction, Instructions, Letter, Rule};
+
+/// Proposes rules that rewrite `before` into `after` in one step, as a grammar without
+/// randomness or context would: each candidate has a rule for every symbol of `before`, in the
+/// order they first appear, with a successor that isn't empty, and rewriting `before` with it
+/// gives `after`. Branches of `before` must be rewritten into branches of `after`; successors
+/// may add branches of their own. Symbols that stay as they are get a rule rewriting them into
+/// themselves. At most `max_candidates` are proposed, the shortest successors for the first
+/// symbols first, e.g. to find out how a drawing was grown, or to check an answer.
+pub fn infer<S: Letter>(
+    before: &[Instruction<S>],
+    after: &[Instruction<S>],
+    max_candidates: usize,
+) -> Vec<Vec<Rule<S>>> {
+    let mut search = Search {
+        before: vec![],
+        after: vec![],
+        assigned: vec![],
+        found: vec![],
+        max_candidates,
+    };
+    flatten(before, &mut search.before);
+    flatten(after, &mut search.after);
+    search.search(0, 0);
+    search.found
+}
+
+// words are aligned as flat sequences, so a successor can end in another branch than it
+// starts in only if brackets balance within it
+enum Token<'w, S> {
+    Push,
+    Pop,
+    Letter(&'w Instruction<S>),
+}
+
+impl<S: PartialEq> PartialEq for Token<'_, S> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Token::Push, Token::Push) | (Token::Pop, Token::Pop) => true,
+            (Token::Letter(a), Token::Letter(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+fn flatten<'w, S>(instrs: &'w [Instruction<S>], tokens: &mut Vec<Token<'w, S>>) {
+    for instr in instrs {
+        match instr {
+            Instruction::Branch(inner) => {
+                tokens.push(Token::Push);
+                flatten(inner, tokens);
+                tokens.push(Token::Pop);
+            }
+            Instruction::Symbol(_) | Instruction::Module(..) => tokens.push(Token::Letter(instr)),
+        }
+    }
+}
+
+fn assemble<S: Clone>(tokens: &[Token<S>]) -> Instructions<S> {
+    let mut stack = vec![vec![]];
+    for token in tokens {
+        match token {
+            Token::Push => stack.push(vec![]),
+            Token::Pop => {
+                let branch = stack.pop().unwrap();
+                stack.last_mut().unwrap().push(Instruction::Branch(branch));
+            }
+            Token::Letter(instr) => stack.last_mut().unwrap().push((*instr).clone()),
+        }
+    }
+    stack.pop().unwrap()
+}
+
+struct Search<'w, S> {
+    before: Vec<Token<'w, S>>,
+    after: Vec<Token<'w, S>>,
+    // the successor picked for each symbol so far, as a range of `after`
+    assigned: Vec<(&'w Instruction<S>, Range<usize>)>,
+    found: Vec<Vec<Rule<S>>>,
+    max_candidates: usize,
+}
+
+impl<S: Letter> Search<'_, S> {
+    // aligns `before[i..]` with `after[j..]`
+    fn search(&mut self, i: usize, j: usize) {
+        if self.found.len() >= self.max_candidates {
+            return;
+        }
+        let instr = match self.before.get(i) {
+            None => {
+                if j == self.after.len() {
+                    let rules = self.assigned.iter().map(|(from, to)| {
+                        Rule::new((*from).clone(), assemble(&self.after[to.clone()]))
+                    });
+                    self.found.push(rules.collect());
+                }
+                return;
+            }
+            Some(Token::Letter(instr)) => *instr,
+            Some(bracket) => {
+                if self.after.get(j) == Some(bracket) {
+                    self.search(i + 1, j + 1);
+                }
+                return;
+            }
+        };
+
+        if let Some((_, to)) = self.assigned.iter().find(|(from, _)| *from == instr) {
+            let end = j + to.len();
+            if end <= self.after.len() && self.after[to.clone()] == self.after[j..end] {
+                self.search(i + 1, end);
+            }
+            return;
+        }
+
+        // every token still to align takes at least one of `after`
+        let last = self.after.len() - (self.before.len() - i - 1).min(self.after.len());
+        let mut depth = 0;
+        for end in j + 1..=last {
+            match self.after[end - 1] {
+                Token::Push => depth += 1,
+                Token::Pop if depth == 0 => break,
+                Token::Pop => depth -= 1,
+                Token::Letter(_) => {}
+            }
+            if depth == 0 {
+                self.assigned.push((instr, j..end));
+                self.search(i + 1, end);
+                self.assigned.pop();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lsystem::{LSystem, Word};
+
+    fn parse(word: &str) -> Instructions {
+        Word::parse(word).unwrap().into_instructions()
+    }
+
+    #[test]
+    fn rules_are_proposed_that_rewrite_one_word_into_the_next() {
+        let candidates = infer(&parse("A"), &parse("AB"), 10);
+        assert_eq!(
+            candidates,
+            [LSystem::from_str("A; A -> AB;").unwrap().rules()]
+        );
+
+        let candidates = infer(&parse("AB"), &parse("ABA"), 10);
+        let printed: Vec<Vec<String>> = candidates
+            .iter()
+            .map(|rules| {
+                rules
+                    .iter()
+                    .map(|rule| Word::from(rule.to().clone()).to_string())
+                    .collect()
+            })
+            .collect();
+        assert_eq!(printed, [["A", "BA"], ["AB", "A"]]);
+
+        let mut plant = LSystem::from_str("X; X -> F[+X]F[-X]+X; F -> FF;").unwrap();
+        plant.nth(1);
+        let (before, after) = (plant.next().unwrap(), plant.next().unwrap());
+        let candidates = infer(&before, &after, 100);
+        assert!(candidates.iter().any(|rules| {
+            let rewrites = |from: char, to: &str| {
+                rules.iter().any(|rule| {
+                    *rule.from() == Instruction::Symbol(from.into()) && *rule.to() == parse(to)
+                })
+            };
+            rewrites('X', "F[+X]F[-X]+X") && rewrites('F', "FF") && rewrites('+', "+")
+        }));
+        for rules in &candidates {
+            let mut lsys = LSystem::new(before.clone(), rules.clone());
+            assert_eq!(lsys.nth(1).unwrap(), after);
+        }
+
+        assert!(infer(&parse("A[B]"), &parse("AB"), 10).is_empty());
+    }
+}
@@ -0,0 +1,134 @@
+//! The parameters of Inkscape's "Render > L-system" extension, written one per line as `name=value`
+//! the way they are usually shared, for example
+//!
+//! ```text
+//! axiom=F
+//! rules=F=F+F--F+F
+//! order=3
+//! langle=60
+//! rangle=60
+//! step=25
+//! ```
+//!
+//! The labels the extension shows, such as `Left angle:`, work as names too, and other
+//! parameters are ignored.
+
+use std::error::Error;
+
+use crate::graphics::TurtleConfig;
+use crate::lsystem::LSystem;
+
+/// A grammar imported from the extension, with how it asks to be drawn.
+#[derive(Debug, Clone)]
+pub struct Inkscape {
+    pub lsystem: LSystem,
+    /// How many times to rewrite the axiom.
+    pub order: usize,
+    pub step: f32,
+    /// In degrees.
+    pub angle: f32,
+}
+
+impl Inkscape {
+    pub fn parse(input: &str) -> Result<Self, Box<dyn Error>> {
+        let (mut axiom, mut rules) = (None, None);
+        let (mut order, mut step) = (3, 25.0);
+        let (mut left, mut right) = (16.0f32, 16.0f32);
+
+        for line in input.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            let split = line
+                .find(['=', ':'])
+                .ok_or_else(|| format!("'{}' is not a parameter", line))?;
+            let (name, value) = (&line[..split], line[split + 1..].trim());
+            match name.trim().to_lowercase().as_str() {
+                "axiom" => axiom = Some(value.to_uppercase()),
+                "rules" => rules = Some(value.to_uppercase()),
+                "order" => order = value.parse()?,
+                "step" | "step length" | "step length (px)" => step = value.parse()?,
+                "langle" | "left angle" => left = value.parse()?,
+                "rangle" | "right angle" => right = value.parse()?,
+                _ => {}
+            }
+        }
+
+        if left != right {
+            return Err(format!("left and right angles differ ({} and {})", left, right).into());
+        }
+        let axiom = axiom.ok_or("missing the axiom")?;
+        let mut grammar = format!("{};", axiom);
+        for rule in rules.iter().flat_map(|rules| rules.split(';')) {
+            let rule: String = rule.chars().filter(|c| !c.is_whitespace()).collect();
+            if let Some((from, to)) = rule.split_once('=') {
+                grammar += &format!(" {} -> {};", from, to);
+            }
+        }
+
+        if grammar.contains('|') {
+            // a half turn, only possible with whole turns of the angle
+            let turns = 180.0 / left;
+            if turns.fract() != 0.0 || turns < 1.0 {
+                return Err(format!("'|' can't be drawn with an angle of {}", left).into());
+            }
+            grammar = grammar.replace('|', &"+".repeat(turns as usize));
+        }
+
+        Ok(Self {
+            lsystem: LSystem::from_str(&grammar)?,
+            order,
+            step,
+            angle: left,
+        })
+    }
+
+    /// Like the extension, `A` to `F` draw and `G` to `L` move without drawing.
+    pub fn turtle(&self) -> TurtleConfig<'static, 'static, 'static, 'static> {
+        TurtleConfig::default()
+            .delta_ang(self.angle.to_radians())
+            .stepsize(self.step)
+            .draw_forward("ABCDEF")
+            .forward("GHIJKL")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Recorder;
+
+    #[test]
+    fn extension_parameters_import() {
+        let imported = Inkscape::parse(
+            "axiom=f\nrules=F=F+G-F-G+F ; G=GG\norder=2\nlangle=90\nrangle=90\nstep=5\n",
+        )
+        .unwrap();
+        assert_eq!(imported.order, 2);
+        assert_eq!(
+            imported.lsystem.to_string(),
+            LSystem::from_str("F; F -> F+G-F-G+F; G -> GG;")
+                .unwrap()
+                .to_string()
+        );
+
+        let word = imported.lsystem.clone().nth(imported.order).unwrap();
+        let mut recorder = Recorder::new();
+        imported
+            .turtle()
+            .create_turtle()
+            .draw(&mut recorder, &word)
+            .unwrap();
+        assert_eq!(recorder.segments().len(), 9);
+        assert_eq!(recorder.segments()[0].end, (5.0, 0.0));
+    }
+
+    #[test]
+    fn labels_and_half_turns() {
+        let imported = Inkscape::parse("Axiom: F|F\nLeft angle: 60\nRight angle: 60").unwrap();
+        assert_eq!(
+            imported.lsystem.word(),
+            LSystem::from_str("F+++F;").unwrap().word()
+        );
+        assert!(Inkscape::parse("axiom=F|F\nlangle=70\nrangle=70").is_err());
+        assert!(Inkscape::parse("axiom=F\nlangle=30\nrangle=60").is_err());
+        assert!(Inkscape::parse("rules=F=FF").is_err());
+    }
+}
@@ -0,0 +1,35 @@
+pub mod adaptive;
+pub mod builder;
+pub mod checkpoint;
+pub mod compress;
+pub mod dump;
+pub mod ensemble;
+pub mod environment;
+pub mod explore;
+pub mod expr;
+pub mod fixed;
+pub mod geojson;
+pub mod geometry;
+pub mod grammar_file;
+pub mod graphics;
+pub mod infer;
+pub mod inkscape;
+pub mod lstudio;
+pub mod lsystem;
+#[cfg(feature = "nannou")]
+pub mod nannou;
+pub mod noise;
+pub mod palette;
+pub mod preset;
+pub mod prune;
+pub mod random;
+pub mod raster;
+pub mod render;
+pub mod scene;
+pub mod sdf;
+pub mod snapshot;
+pub mod stats;
+pub mod symbol;
+pub mod theme;
+pub mod trace;
+pub mod zoom;
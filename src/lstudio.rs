@@ -0,0 +1,209 @@
+//! Grammars in the dialect of cpfg and L-studio, as most grammars in the literature are
+//! published, for example
+//!
+//! ```text
+//! /* Fractal plant */
+//! Lsystem: 1
+//! derivation length: 5
+//! angle: 25
+//! Axiom: X
+//! X --> F[+X]F[-X]+X
+//! F --> FF
+//! endlsystem
+//! ```
+//!
+//! Productions may have contexts, `lc < pred > rc`, a condition after `:`, and a probability
+//! after the successor, `: 0.3`; `*` stands for no context or condition, and for an empty
+//! successor. `ignore:` lists the symbols contexts skip. Preprocessor lines, starting with `#`,
+//! are skipped, and other headers ignored, except for homomorphisms and decompositions, which
+//! are rejected.
+
+use std::error::Error;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::graphics::TurtleConfig;
+use crate::lsystem::{LSystem, Rule, RuleSelection, Symbol, Word};
+
+/// A grammar imported from an L-studio file, with what its headers say about drawing it.
+#[derive(Debug, Clone)]
+pub struct LStudio {
+    pub lsystem: LSystem,
+    /// How many times to rewrite the axiom.
+    pub derivation_length: usize,
+    /// In degrees, or `None` when the file leaves it to its view file.
+    pub angle: Option<f32>,
+}
+
+impl LStudio {
+    pub fn parse(input: &str) -> Result<Self, Box<dyn Error>> {
+        let (mut axiom, mut rules, mut weighted) = (None, vec![], false);
+        let (mut derivation_length, mut angle) = (0, None);
+        let mut ignored = vec![];
+
+        for line in strip_block_comments(input).lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some((head, successor)) = line.split_once("-->").or(line.split_once("->")) {
+                let (rule, probability) = production(head, successor)?;
+                rules.push(rule);
+                weighted |= probability;
+                continue;
+            }
+
+            let (name, value) = line.split_once(':').unwrap_or((line, ""));
+            let value = value.trim();
+            match name.trim().to_lowercase().as_str() {
+                "endlsystem" => break,
+                "axiom" => axiom = Some(Word::parse(value)?),
+                "derivation length" => derivation_length = value.parse()?,
+                "angle" | "angle increment" => angle = Some(value.parse()?),
+                "ignore" => ignored.extend(symbols(value)),
+                name @ ("homomorphism" | "decomposition") => {
+                    return Err(format!("{}s aren't supported", name).into())
+                }
+                _ => {}
+            }
+        }
+
+        let axiom = axiom.ok_or("missing the axiom")?;
+        let mut lsystem = LSystem::new(axiom.into_instructions(), rules);
+        lsystem.set_ignored(ignored);
+        lsystem.set_angle(angle);
+        if weighted {
+            lsystem.set_selection(RuleSelection::Weighted);
+            lsystem.check_weights()?;
+        }
+        Ok(Self {
+            lsystem,
+            derivation_length,
+            angle,
+        })
+    }
+
+    /// Like cpfg, `F` draws and `f` moves without drawing; the angle is 90 degrees unless the
+    /// file has one.
+    pub fn turtle(&self) -> TurtleConfig<'static, 'static, 'static, 'static> {
+        TurtleConfig::default()
+            .delta_ang(self.angle.unwrap_or(90.0).to_radians())
+            .draw_forward("F")
+            .draw_backward("")
+            .forward("f")
+    }
+}
+
+fn strip_block_comments(input: &str) -> String {
+    let mut stripped = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(start) = rest.find("/*") {
+        stripped.push_str(&rest[..start]);
+        rest = rest[start..]
+            .find("*/")
+            .map_or("", |end| &rest[start + end + 2..]);
+    }
+    stripped + rest
+}
+
+fn symbols(word: &str) -> Vec<Symbol> {
+    word.graphemes(true)
+        .filter(|g| !g.trim().is_empty())
+        .map(Symbol::new)
+        .collect()
+}
+
+// `[lc <] pred [> rc] [: cond] --> succ [: prob]`, and whether it has a probability
+fn production(head: &str, successor: &str) -> Result<(Rule, bool), Box<dyn Error>> {
+    let (head, condition) = head.split_once(':').unwrap_or((head, "*"));
+    let (left, head) = head.split_once('<').unwrap_or(("*", head));
+    let (predecessor, right) = head.split_once('>').unwrap_or((head, "*"));
+    let (successor, probability) = successor.split_once(':').unwrap_or((successor, ""));
+
+    let mut rule = predecessor.trim().to_string();
+    if condition.trim() != "*" {
+        rule += &format!(" : {}", condition.trim());
+    }
+    match probability.trim() {
+        "" => rule += " ->",
+        probability => rule += &format!(" -{}->", probability.parse::<f32>()?),
+    }
+    if successor.trim() != "*" {
+        rule += &format!(" {}", successor.trim());
+    }
+    rule += ";";
+
+    let mut parsed = Rule::parse_uncommented(&rule)
+        .map_err(|e| format!("can't read production '{}': {}", rule, e))?;
+    let mut rule = match parsed.len() {
+        1 => parsed.remove(0),
+        _ => return Err(format!("can't read production '{}'", rule).into()),
+    };
+    let context = |context: &str| match context.trim() {
+        "*" => Ok(vec![]),
+        context if context.contains('(') => Err(format!(
+            "parametric contexts like '{}' aren't supported",
+            context
+        )),
+        context => Ok(symbols(context)),
+    };
+    rule.set_context(context(left)?, context(right)?);
+    Ok((rule, !probability.trim().is_empty()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Recorder;
+
+    #[test]
+    fn published_grammars_import() {
+        let imported = LStudio::parse(
+            "/* Fractal plant,\n   ABOP figure 1.24f */\n#define STEPS 5\nLsystem: 1\n\
+             derivation length: 5\nangle: 25\nAxiom: X\n\
+             X --> F[+X]F[-X]+X\nF --> FF\nendlsystem\nF --> G\n",
+        )
+        .unwrap();
+        assert_eq!(
+            (imported.derivation_length, imported.angle),
+            (5, Some(25.0))
+        );
+        assert_eq!(
+            imported.lsystem.to_string(),
+            LSystem::from_str("angle 25; X; X -> F[+X]F[-X]+X; F -> FF;")
+                .unwrap()
+                .to_string()
+        );
+
+        let moving = LStudio::parse("axiom: FfF").unwrap();
+        let mut recorder = Recorder::new();
+        let turtle = moving.turtle();
+        let word = moving.lsystem.word();
+        turtle.create_turtle().draw(&mut recorder, word).unwrap();
+        assert_eq!(recorder.segments().len(), 2);
+    }
+
+    #[test]
+    fn contexts_conditions_and_probabilities() {
+        let mut imported = LStudio::parse(
+            "axiom: BAAA\nignore: +-\nB < A --> B\nB --> *\n\
+             A(x) : x > 1 --> A(x-1)\nC --> C : 0.5\nC --> CC : 0.5",
+        )
+        .unwrap();
+        let words: Vec<String> = (&mut imported.lsystem)
+            .take(3)
+            .map(|word| Word::from(word).to_string())
+            .collect();
+        assert_eq!(words, ["BAAA", "BAA", "BA"]);
+        assert_eq!(imported.lsystem.ignored().len(), 2);
+        assert_eq!(imported.lsystem.selection(), RuleSelection::Weighted);
+        assert!(imported
+            .lsystem
+            .to_string()
+            .contains("B < A -1-> B; B -1-> ; A(x) : x > 1 -1-> A(x-1);"));
+
+        assert!(LStudio::parse("axiom: A\nA --> F<F").is_ok());
+        assert!(LStudio::parse("axiom: A\nA(x) < B --> B").is_err());
+        assert!(LStudio::parse("axiom: A\nhomomorphism\nA --> B").is_err());
+        assert!(LStudio::parse("A --> B").is_err());
+    }
+}
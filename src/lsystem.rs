@@ -1,82 +1,364 @@
+use crate::checkpoint::Checkpoint;
+use crate::compress::Event;
+use crate::environment::Environment;
+use crate::expr::{self, Expr};
+use crate::graphics::{Graphics, Turtle, TurtleConfig};
+use crate::prune::Pruning;
+use crate::stats::{DerivationStats, WordStats};
+pub use crate::symbol::{Letter, Symbol};
+use crate::trace::{DerivationTrace, Production, ReplayStep, TraceStep, Tracer};
 use nom::{
     branch::alt,
-    bytes::complete::{tag, take_while_m_n},
-    combinator::{iterator, map_res, opt},
-    error::{Error, ErrorKind},
+    bytes::complete::{tag, take_while1},
+    character::complete::{digit1, multispace0, multispace1},
+    combinator::{cut, iterator, map, map_res, opt, peek, verify},
+    error::{ErrorKind, FromExternalError, ParseError as NomError},
+    number::complete::float,
+    sequence::terminated,
     IResult,
 };
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::{Index, Range};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::slice::SliceIndex;
+use unicode_segmentation::UnicodeSegmentation;
 
-pub type Instructions = Vec<Instruction>;
+pub type Instructions<S = Symbol> = Vec<Instruction<S>>;
 
+/// A part of a word over `S`: the `Symbol`s of parsed grammars unless built in code.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Instruction<S = Symbol> {
+    Symbol(S),
+    /// A symbol with numeric parameters, like `F(1.5)`.
+    Module(S, Params),
+    Branch(Instructions<S>),
+}
+
+/// The values of a module. They compare bit for bit, so words can be hashed.
+#[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Params(pub Vec<f32>);
+
+impl PartialEq for Params {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.len() == other.0.len()
+            && self
+                .0
+                .iter()
+                .zip(&other.0)
+                .all(|(a, b)| a.to_bits() == b.to_bits())
+    }
+}
+
+impl Eq for Params {}
+
+impl Hash for Params {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.len().hash(state);
+        self.0.iter().for_each(|v| v.to_bits().hash(state));
+    }
+}
+
+// a successor as written, with the arguments of its modules still to be evaluated
 #[derive(Debug, PartialEq, Clone)]
-pub enum Instruction {
-    Symbol(char),
-    Branch(Instructions),
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+enum Template<S = Symbol> {
+    Symbol(S),
+    Module(S, Vec<Expr>),
+    Branch(Vec<Template<S>>),
 }
 
-fn to_symbol(input: &str) -> Result<Instruction, Box<dyn std::error::Error>> {
-    if input.len() != 1 {
-        return Err(format!(
-            "'{}' is not a single instruction that is able to be parsed",
-            input
-        )
-        .into());
+fn instantiate<S: Clone>(templates: &[Template<S>], values: &[f32]) -> Instructions<S> {
+    templates
+        .iter()
+        .map(|template| match template {
+            Template::Symbol(s) => Instruction::Symbol(s.clone()),
+            Template::Module(s, args) => Instruction::Module(
+                s.clone(),
+                Params(args.iter().map(|arg| arg.eval(values)).collect()),
+            ),
+            Template::Branch(inner) => Instruction::Branch(instantiate(inner, values)),
+        })
+        .collect()
+}
+
+// the successor with its modules as plain symbols
+fn shape<S: Clone>(templates: &[Template<S>]) -> Instructions<S> {
+    templates
+        .iter()
+        .map(|template| match template {
+            Template::Symbol(s) | Template::Module(s, _) => Instruction::Symbol(s.clone()),
+            Template::Branch(inner) => Instruction::Branch(shape(inner)),
+        })
+        .collect()
+}
+
+fn has_modules<S>(templates: &[Template<S>]) -> bool {
+    templates.iter().any(|template| match template {
+        Template::Symbol(_) => false,
+        Template::Module(..) => true,
+        Template::Branch(inner) => has_modules(inner),
+    })
+}
+
+/// The error of every grammar parser: where parsing failed, and what was expected there.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Expected<'i> {
+    pub(crate) input: &'i str,
+    pub(crate) what: Cow<'static, str>,
+}
+
+pub(crate) type Parsed<'i, T> = IResult<&'i str, T, Expected<'i>>;
+
+impl<'i> Expected<'i> {
+    pub(crate) fn new(input: &'i str, what: impl Into<Cow<'static, str>>) -> Self {
+        Self {
+            input,
+            what: what.into(),
+        }
+    }
+}
+
+impl<'i> NomError<&'i str> for Expected<'i> {
+    fn from_error_kind(input: &'i str, kind: ErrorKind) -> Self {
+        let what = match kind {
+            ErrorKind::Digit | ErrorKind::Float => "a number",
+            ErrorKind::Eof => "more input",
+            _ => "something else",
+        };
+        Self::new(input, what)
+    }
+
+    fn append(_: &'i str, _: ErrorKind, other: Self) -> Self {
+        other
+    }
+
+    fn from_char(input: &'i str, c: char) -> Self {
+        Self::new(input, format!("`{}`", c))
+    }
+
+    // the alternative that got further knows best what went wrong
+    fn or(self, other: Self) -> Self {
+        match other.input.len() <= self.input.len() {
+            true => other,
+            false => self,
+        }
     }
+}
+
+impl<'i, E> FromExternalError<&'i str, E> for Expected<'i> {
+    fn from_external_error(input: &'i str, kind: ErrorKind, _: E) -> Self {
+        Self::from_error_kind(input, kind)
+    }
+}
+
+// `tag(t)`, expecting `t` by name when it isn't there
+fn expect_tag<'i>(t: &'static str) -> impl Fn(&'i str) -> Parsed<'i, &'i str> {
+    move |input| {
+        tag(t)(input)
+            .map_err(|e: nom::Err<Expected>| e.map(|_| Expected::new(input, format!("`{}`", t))))
+    }
+}
 
-    // safe to unwrap since input is exactly one character
-    let c = input.chars().next().unwrap();
+// `f`, expecting `what` when it doesn't apply at all; failures past its start keep their own
+fn expecting<'i, O>(
+    what: &'static str,
+    mut f: impl FnMut(&'i str) -> Parsed<'i, O>,
+) -> impl FnMut(&'i str) -> Parsed<'i, O> {
+    move |input| match f(input) {
+        Err(nom::Err::Error(e)) if e.input.len() >= input.len() => {
+            Err(nom::Err::Error(Expected::new(input, what)))
+        }
+        res => res,
+    }
+}
 
-    if is_branch_symbol(c) {
-        Err(format!("{} is branch symbol", input).into())
-    } else if c.is_whitespace() {
-        Err(format!("'{}' is whitespace", input).into())
-    } else if c == ';' {
-        Err(format!("'{}' is terminator", input).into())
+fn to_symbol(input: &str) -> Result<Symbol, String> {
+    if input.starts_with(is_branch_symbol) {
+        Err(format!("{} is branch symbol", input))
+    } else if input.chars().all(char::is_whitespace) {
+        Err(format!("'{}' is whitespace", input))
+    } else if input == ";" {
+        Err(format!("'{}' is terminator", input))
     } else {
-        Ok(Instruction::Symbol(c))
+        Ok(Symbol::new(input))
     }
 }
 
 fn is_branch_symbol(c: char) -> bool {
-    c == '[' || c == ']'
+    c == '[' || c == ']' || c == '(' || c == ')'
 }
 
-fn single_instruction(input: &str) -> IResult<&str, Instruction> {
-    map_res(take_while_m_n(1, 1, |c| !is_branch_symbol(c)), to_symbol)(input)
+fn grapheme(input: &str) -> Parsed<'_, &str> {
+    match input.graphemes(true).next() {
+        Some(grapheme) => Ok((&input[grapheme.len()..], grapheme)),
+        None => Err(nom::Err::Error(Expected::new(input, "a symbol"))),
+    }
 }
 
-fn simple_instructions(input: &str) -> IResult<&str, Instructions> {
-    let mut it = iterator(input, single_instruction);
+// `$Apex`; a name has two letters or more, so `$F` stays `$` and `F`
+fn name(input: &str) -> Parsed<'_, Symbol> {
+    let (input, _) = tag("$")(input)?;
+    map(
+        verify(expr::identifier, |name: &str| name.len() > 1),
+        Symbol::named,
+    )(input)
+}
 
-    let parsed: Instructions = it.collect();
-    if parsed.is_empty() {
-        Err(nom::Err::Error(Error {
-            input,
-            code: ErrorKind::Fail,
-        }))
-    } else {
-        let (input, ()) = it.finish()?;
+fn symbol(input: &str) -> Parsed<'_, Symbol> {
+    alt((name, map_res(grapheme, to_symbol)))(input)
+}
+
+fn single_instruction(input: &str) -> Parsed<'_, Instruction> {
+    map(symbol, Instruction::Symbol)(input)
+}
+
+fn separator(input: &str) -> Parsed<'_, &str> {
+    let (input, _) = multispace0(input)?;
+    tag(",")(input)
+}
+
+// what a word can refer to besides symbols: the parameters of its rule's head, in arguments,
+// and the words defined with `let`, longest name first
+#[derive(Clone, Copy)]
+struct Scope<'s> {
+    params: &'s [String],
+    macros: &'s [(String, Vec<Template>)],
+}
+
+const EMPTY_SCOPE: Scope = Scope {
+    params: &[],
+    macros: &[],
+};
+
+// `(x*0.5, 2)` after the symbol of a module
+fn arguments<'i>(input: &'i str, scope: Scope) -> Parsed<'i, Vec<Expr>> {
+    let (mut input, _) = tag("(")(input)?;
+    let mut args = vec![];
+    loop {
+        let (after, arg) = expr::parse(input, scope.params)?;
+        args.push(arg);
+        match separator(after) {
+            Ok((after, _)) => input = after,
+            Err(_) => {
+                let (after, _) = multispace0(after)?;
+                let (after, _) = expect_tag(")")(after)?;
+                return Ok((after, args));
+            }
+        }
+    }
+}
+
+fn module<'i>(input: &'i str, scope: Scope) -> Parsed<'i, Template> {
+    let (input, symbol) = symbol(input)?;
+    match arguments(input, scope) {
+        Ok((input, args)) => Ok((input, Template::Module(symbol, args))),
+        Err(nom::Err::Error(_)) => Ok((input, Template::Symbol(symbol))),
+        Err(e) => Err(e),
+    }
+}
 
-        Ok((input, parsed))
+/// The most symbols a `^` repetition or a `let` definition writes out, so a typo can't
+/// exhaust memory.
+pub const MAX_REPEAT: usize = 1 << 16;
+
+fn size(templates: &[Template]) -> usize {
+    templates
+        .iter()
+        .map(|template| match template {
+            Template::Branch(inner) => 1 + size(inner),
+            Template::Symbol(_) | Template::Module(..) => 1,
+        })
+        .sum()
+}
+
+fn too_large<T>(input: &str) -> Parsed<'_, T> {
+    Err(nom::Err::Failure(Expected::new(
+        input,
+        format!("at most {} symbols written out", MAX_REPEAT),
+    )))
+}
+
+// `^3` after a symbol, module, branch or defined word writes it three times
+fn repeat(input: &str, templates: Vec<Template>) -> Parsed<'_, Vec<Template>> {
+    let (after, count) = match tag("^")(input).and_then(|(input, _)| digit1(input)) {
+        Ok(parsed) => parsed,
+        Err(nom::Err::Error(_)) => return Ok((input, templates)),
+        Err(e) => return Err(e),
+    };
+    match count.parse::<usize>() {
+        Ok(count) if size(&templates).saturating_mul(count) <= MAX_REPEAT => Ok((
+            after,
+            templates
+                .iter()
+                .cycle()
+                .take(templates.len() * count)
+                .cloned()
+                .collect(),
+        )),
+        _ => too_large(input),
+    }
+}
+
+// a word defined with `let`
+fn reference<'i>(input: &'i str, scope: Scope) -> Parsed<'i, Vec<Template>> {
+    for (name, word) in scope.macros {
+        if let Some(rest) = input.strip_prefix(name.as_str()) {
+            return repeat(rest, word.clone());
+        }
+    }
+    Err(nom::Err::Error(Expected::new(input, "a defined word")))
+}
+
+fn simple_instructions<'i>(input: &'i str, scope: Scope) -> Parsed<'i, Vec<Template>> {
+    let mut it = iterator(
+        input,
+        alt((
+            |input| reference(input, scope),
+            |input| {
+                let (input, module) = module(input, scope)?;
+                repeat(input, vec![module])
+            },
+        )),
+    );
+
+    let parsed: Vec<Template> = it.flatten().collect();
+    // failures in arguments must come out even before the first symbol
+    let (rest, ()) = it.finish()?;
+    // `F^0` parses to nothing
+    if rest.len() == input.len() {
+        Err(nom::Err::Error(Expected::new(input, "a symbol")))
+    } else {
+        Ok((rest, parsed))
     }
 }
 
-fn branch(input: &str) -> IResult<&str, Instructions> {
+fn branch<'i>(input: &'i str, scope: Scope) -> Parsed<'i, Vec<Template>> {
     let (input, _) = tag("[")(input)?;
-    let (input, instrs) = instructions(input)?;
-    let (input, _) = tag("]")(input)?;
+    let (input, instrs) = templates(input, scope)?;
+    let (input, _) = expect_tag("]")(input)?;
 
-    Ok((input, vec![Instruction::Branch(instrs)]))
+    repeat(input, vec![Template::Branch(instrs)])
 }
 
-fn instructions(input: &str) -> IResult<&str, Instructions> {
+fn templates<'i>(input: &'i str, scope: Scope) -> Parsed<'i, Vec<Template>> {
     let (input, _) = opt(remove_whitespace)(input)?;
 
     let mut it = iterator(
         input,
-        alt((simple_instructions, branch, |input| {
-            remove_whitespace(input).map(|(input, _)| (input, vec![]))
-        })),
+        alt((
+            |input| simple_instructions(input, scope),
+            |input| branch(input, scope),
+            |input| remove_whitespace(input).map(|(input, _)| (input, vec![])),
+        )),
     );
 
     let parsed = it.flatten().collect();
@@ -85,197 +367,5669 @@ fn instructions(input: &str) -> IResult<&str, Instructions> {
     Ok((input, parsed))
 }
 
-fn remove_whitespace(input: &str) -> IResult<&str, ()> {
-    let mut it = iterator(input, alt((tag(" "), tag("\n"), tag("\t"))));
-    let l: Vec<_> = it.collect();
-    if l.is_empty() {
-        Err(nom::Err::Error(Error {
-            input,
-            code: ErrorKind::Fail,
-        }))
-    } else {
-        it.finish()
+// `templates` where a `;` must follow: ending early at a branch or arguments that aren't closed
+// fails where they do
+fn closed_templates<'i>(input: &'i str, scope: Scope) -> Parsed<'i, Vec<Template>> {
+    let (input, parsed) = templates(input, scope)?;
+    let unclosed = match input.chars().next() {
+        Some('[') => branch(input, scope).err(),
+        Some('(') => arguments(input, scope).err(),
+        _ => None,
+    };
+    match unclosed {
+        Some(e) => Err(e),
+        None => Ok((input, parsed)),
     }
 }
 
-type Rule = (Instruction, Instructions);
-
-fn rule(input: &str) -> IResult<&str, Rule> {
-    let (input, _) = opt(remove_whitespace)(input)?;
-
-    let (input, from) = single_instruction(input)?;
-    let (input, _) = opt(remove_whitespace)(input)?;
-    let (input, _) = tag("->")(input)?;
-    let (input, _) = opt(remove_whitespace)(input)?;
-    let (input, target) = instructions(input)?;
+#[cfg(test)]
+fn instructions(input: &str) -> Parsed<'_, Instructions> {
+    let (input, templates) = templates(input, EMPTY_SCOPE)?;
+    Ok((input, instantiate(&templates, &[])))
+}
 
-    Ok((input, (from, target)))
+fn remove_whitespace(input: &str) -> Parsed<'_, ()> {
+    map(take_while1(char::is_whitespace), |_| ())(input)
 }
 
-#[derive(Debug, PartialEq)]
-pub struct LSystem {
-    word: Instructions,
-    axiom: Instructions,
-    rules: Vec<Rule>,
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rule<S = Symbol> {
+    from: Instruction<S>,
+    to: Instructions<S>,
+    weight: f32,
+    priority: i32,
+    params: Vec<String>,
+    condition: Option<Expr>,
+    template: Option<Vec<Template<S>>>,
+    left: Vec<S>,
+    right: Vec<S>,
+    delay: usize,
 }
 
-fn terminate<F, G>(f: F) -> impl Fn(&str) -> IResult<&str, G>
-where
-    F: Fn(&str) -> IResult<&str, G>,
-{
-    move |input| {
-        let (input, res) = f(input)?;
-        let (input, _) = opt(remove_whitespace)(input)?;
-        let (input, _) = tag(";")(input)?;
-        Ok((input, res))
+impl Rule {
+    /// Parses rules written as in a grammar, each ending in `;`, e.g. to fill a table.
+    pub fn parse_all(input: &str) -> Result<Vec<Self>, LsysError> {
+        Self::parse_uncommented(&strip_comments(input))
+    }
+
+    // like `parse_all` for dialects where `#` and `//` are symbols rather than comments
+    pub(crate) fn parse_uncommented(input: &str) -> Result<Vec<Self>, LsysError> {
+        check_nesting(input)?;
+        let mut it = iterator(input, terminate(rules));
+        let parsed = (&mut it).flat_map(|(rules, _)| rules).collect();
+        let (rest, ()) = it.finish().map_err(|e| LsysError::expected(input, e))?;
+        if rest.trim_start().is_empty() {
+            return Ok(parsed);
+        }
+        // the iterator stops quietly at the first rule it can't read; reading it again says why
+        match terminate(rules)(rest) {
+            Err(e) => Err(LsysError::expected(input, e)),
+            Ok(_) => Err(LsysError::unexpected(input, rest.trim_start())),
+        }
     }
 }
 
-fn lsystem(input: &str) -> IResult<&str, LSystem> {
-    let (input, instr) = terminate(instructions)(input)?;
-    let mut it = iterator(input, terminate(rule));
-    let rules = it.collect();
-    let (input, ()) = it.finish()?;
+impl<S: Letter> Rule<S> {
+    pub fn new(from: Instruction<S>, to: Instructions<S>) -> Self {
+        Self {
+            from,
+            to,
+            weight: 1.0,
+            priority: 0,
+            params: vec![],
+            condition: None,
+            template: None,
+            left: vec![],
+            right: vec![],
+            delay: 0,
+        }
+    }
 
-    Ok((
-        input,
-        LSystem {
-            word: instr.clone(),
-            axiom: instr,
-            rules,
-        },
-    ))
-}
+    pub fn from(&self) -> &Instruction<S> {
+        &self.from
+    }
 
-impl LSystem {
-    pub fn from_str(input: &str) -> Result<Self, Box<dyn std::error::Error + '_>> {
-        let (_, lsystem) = lsystem(input)?;
-        Ok(lsystem)
+    /// The successor; the modules of a parametric rule appear as their plain symbols.
+    pub fn to(&self) -> &Instructions<S> {
+        &self.to
     }
 
-    fn step(&mut self) {
-        self.word = self
-            .word
-            .iter()
-            .flat_map(|instr| instr.apply(&self.rules))
-            .collect();
+    /// The names of the parameters in the head of a parametric rule, like `x` in `F(x)`.
+    pub fn params(&self) -> &[String] {
+        &self.params
     }
 
-    #[allow(dead_code)]
-    pub fn reset(&mut self) {
-        self.word = self.axiom.clone();
+    /// The guard of a conditional rule, like `x > 1` in `A(x) : x > 1 -> A(x-1)B`.
+    pub fn condition(&self) -> Option<&Expr> {
+        self.condition.as_ref()
     }
-}
 
-impl Instruction {
-    fn apply(&self, rules: &[Rule]) -> Instructions {
-        for rule in rules {
-            if &rule.0 == self {
-                return rule.1.clone();
+    // a rule with parameters only rewrites modules with as many values, and a guarded one only
+    // those its guard holds for
+    fn matches(&self, instr: &Instruction<S>) -> bool {
+        let values = match instr {
+            Instruction::Module(symbol, values)
+                if self.params.len() == values.0.len()
+                    && matches!(&self.from, Instruction::Symbol(from) if from == symbol) =>
+            {
+                &values.0[..]
             }
-        }
+            _ if self.params.is_empty() && &self.from == instr => &[],
+            _ => return false,
+        };
+        self.condition
+            .as_ref()
+            .is_none_or(|condition| condition.holds(values))
+    }
 
-        use Instruction::*;
-        match self {
-            Symbol(c) => vec![Symbol(*c)],
-            Branch(instrs) => vec![Branch(
-                instrs.iter().flat_map(|instr| instr.apply(rules)).collect(),
-            )],
+    /// The symbols that must come before the one rewritten, nearest last, and those that must
+    /// follow it, like `A` and `BC` in `A < X > BC`. Reading left goes down the branches the
+    /// symbol is in, reading right stays in its own; both skip branches on the way, and symbols
+    /// the system ignores.
+    pub fn context(&self) -> (&[S], &[S]) {
+        (&self.left, &self.right)
+    }
+
+    pub fn set_context(&mut self, left: Vec<S>, right: Vec<S>) {
+        self.left = left;
+        self.right = right;
+    }
+
+    fn has_context(&self) -> bool {
+        !self.left.is_empty() || !self.right.is_empty()
+    }
+
+    /// How many generations a symbol waits after it is produced before the rule rewrites it,
+    /// like 3 in `A@3 -> B`, for buds that take a while to develop; until then the other rules
+    /// for the symbol are tried.
+    pub fn delay(&self) -> usize {
+        self.delay
+    }
+
+    pub fn set_delay(&mut self, delay: usize) {
+        self.delay = delay;
+    }
+
+    // a rule with a context or a delay never matches where the context or the age isn't known
+    fn fits(&self, neighbors: Option<&Neighbors<S>>) -> bool {
+        let aged = |n: &Neighbors<S>| self.delay == 0 || n.age.is_some_and(|age| age >= self.delay);
+        (!self.has_context() || neighbors.is_some_and(|n| n.fit(&self.left, &self.right)))
+            && (self.delay == 0 || neighbors.is_some_and(aged))
+    }
+
+    fn successor(&self, instr: &Instruction<S>) -> Instructions<S> {
+        match (&self.template, instr) {
+            (Some(template), Instruction::Module(_, values)) => instantiate(template, &values.0),
+            (Some(template), _) => instantiate(template, &[]),
+            (None, _) => self.to.clone(),
         }
     }
+
+    pub fn weight(&self) -> f32 {
+        self.weight
+    }
+
+    /// Only used by `RuleSelection::Weighted`. Rules with a weight that isn't a positive number
+    /// are never picked.
+    pub fn set_weight(&mut self, weight: f32) {
+        self.weight = weight;
+    }
+
+    pub fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    /// Only used by `RuleSelection::Priority`; higher priorities win.
+    pub fn set_priority(&mut self, priority: i32) {
+        self.priority = priority;
+    }
 }
 
-impl Iterator for LSystem {
-    type Item = Instructions;
-    fn next(&mut self) -> Option<Self::Item> {
-        let res = self.word.clone();
+/// The symbols of a system, for `LSystem::alphabet`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Alphabet {
+    /// Symbols that are never rewritten, only drawn; the ones a turtle needs bindings for.
+    pub terminals: BTreeSet<Symbol>,
+    /// Symbols some rule rewrites.
+    pub non_terminals: BTreeSet<Symbol>,
+}
 
-        self.step();
+/// How large a system's word grows, from `LSystem::growth`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Growth {
+    /// How many times longer the word gets each generation in the long run; 1 for a word that
+    /// doesn't grow.
+    pub factor: f64,
+    /// The number of symbols and branches expected in the word.
+    pub symbols: f64,
+    /// The memory that many take, not counting the values of modules.
+    pub bytes: f64,
+}
 
-        Some(res)
+/// Something in a grammar that is probably a mistake, found by `LSystem::validate`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Issue {
+    /// There is nothing to rewrite or draw.
+    EmptyAxiom,
+    /// A symbol only successors produce, with no rule; it does nothing unless the turtle draws
+    /// it.
+    Undefined(Symbol),
+    /// The rule rewrites a symbol that neither the axiom nor any successor has.
+    Unreachable(RuleRef),
+    /// The rule erases its symbol.
+    EmptyProduction(RuleRef),
+    /// The rule rewrites a symbol declared a constant.
+    RewritesConstant(RuleRef),
+}
+
+/// Which rule an `Issue` is about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleRef {
+    /// The rule table, `None` for the grammar's own rules.
+    pub table: Option<String>,
+    /// The rule's place in the table.
+    pub rule: usize,
+}
+
+impl fmt::Display for RuleRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "rule {}", self.rule + 1)?;
+        match &self.table {
+            Some(table) => write!(f, " of table '{}'", table),
+            None => Ok(()),
+        }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    /// The system can't draw anything.
+    Error,
+}
 
-    #[test]
-    fn test_simple_instructions() {
-        use Instruction::*;
-        assert_eq!(
-            Ok(("", vec![Symbol('F'), Symbol('G')])),
-            simple_instructions("FG")
-        )
+impl Issue {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Issue::EmptyAxiom => Severity::Error,
+            _ => Severity::Warning,
+        }
     }
+}
 
-    #[test]
-    fn test_simple_instructions_branch_separated() {
-        use Instruction::*;
-        assert_eq!(
-            Ok(("[FGFGF]", vec![Symbol('F'), Symbol('G')])),
-            simple_instructions("FG[FGFGF]")
-        )
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Issue::EmptyAxiom => write!(f, "the axiom is empty"),
+            Issue::Undefined(symbol) => write!(f, "no rule rewrites '{}'", symbol),
+            Issue::Unreachable(rule) => write!(f, "{} never applies", rule),
+            Issue::EmptyProduction(rule) => write!(f, "{} erases its symbol", rule),
+            Issue::RewritesConstant(rule) => write!(f, "{} rewrites a constant", rule),
+        }
     }
+}
 
-    #[test]
-    fn test_branching() {
-        use Instruction::*;
-        assert_eq!(
-            Ok(("", vec![Branch(vec![Symbol('F'), Symbol('G')])])),
-            branch("[FG]")
-        )
+/// How to pick a rule when several rules rewrite the same symbol.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RuleSelection {
+    /// The rule declared first.
+    #[default]
+    First,
+    /// The rule declared last, so later rules override earlier ones.
+    Last,
+    /// The rule with the highest priority, the one declared first among equals.
+    Priority,
+    /// Any matching rule, with equal chance.
+    Uniform,
+    /// Any matching rule, with a chance proportional to its weight.
+    Weighted,
+}
+
+impl RuleSelection {
+    /// Whether the same symbol can be rewritten differently each time.
+    pub fn is_random(self) -> bool {
+        matches!(self, RuleSelection::Uniform | RuleSelection::Weighted)
     }
 
-    #[test]
-    fn test_symbols() {
-        use Instruction::*;
-        assert_eq!(
-            Ok((
-                "",
-                vec![
-                    Symbol('F'),
-                    Symbol('G'),
-                    Branch(vec![Symbol('F'), Symbol('G'), Symbol('F')]),
-                    Symbol('F'),
-                    Symbol('G')
-                ]
-            )),
-            instructions("FG[FGF]FG")
-        )
+    // the rule picked, and where it is in `rules`
+    fn select<'r, S: Letter, R: Rng>(
+        self,
+        rules: &'r [Rule<S>],
+        symbol: &Instruction<S>,
+        neighbors: Option<&Neighbors<S>>,
+        rng: &mut R,
+    ) -> Option<(usize, &'r Rule<S>)> {
+        let mut matching = rules
+            .iter()
+            .enumerate()
+            .filter(|(_, rule)| rule.matches(symbol) && rule.fits(neighbors));
+
+        match self {
+            RuleSelection::First => matching.next(),
+            RuleSelection::Last => matching.next_back(),
+            RuleSelection::Priority => {
+                matching.fold(None, |best: Option<(usize, &Rule<S>)>, rule| match best {
+                    Some(best) if best.1.priority >= rule.1.priority => Some(best),
+                    _ => Some(rule),
+                })
+            }
+            RuleSelection::Uniform => matching.collect::<Vec<_>>().choose(rng).copied(),
+            RuleSelection::Weighted => {
+                let matching: Vec<_> = matching.collect();
+                let weight = |(_, rule): &(usize, &Rule<S>)| {
+                    if rule.weight > 0.0 && rule.weight.is_finite() {
+                        rule.weight
+                    } else {
+                        0.0
+                    }
+                };
+                matching
+                    .choose_weighted(rng, weight)
+                    .ok()
+                    .or_else(|| matching.first())
+                    .copied()
+            }
+        }
     }
+}
 
-    #[test]
-    fn missing_branckets() {
-        assert_eq!(instructions("FGFGHA[DOAIJD").unwrap().0, "[DOAIJD")
+// `-0.4->` or `-(0.4)->` gives the rule a weight
+fn weighted_arrow(input: &str) -> Parsed<'_, f32> {
+    let (input, _) = tag("-")(input)?;
+    let (input, weight) = match tag::<_, _, Expected>("(")(input) {
+        Ok((input, _)) => {
+            let (input, _) = multispace0(input)?;
+            let (input, weight) = weight(input)?;
+            let (input, _) = multispace0(input)?;
+            let (input, _) = expect_tag(")")(input)?;
+            (input, weight)
+        }
+        Err(_) => weight(input)?,
+    };
+    let (input, _) = expect_tag("->")(input)?;
+    Ok((input, weight))
+}
+
+// a rule that could never be picked is an error where its weight is written
+fn weight(input: &str) -> Parsed<'_, f32> {
+    let (after, weight) = expecting("a weight", float)(input)?;
+    if weight > 0.0 && weight.is_finite() {
+        Ok((after, weight))
+    } else {
+        Err(nom::Err::Failure(Expected::new(
+            input,
+            "a positive, finite weight",
+        )))
     }
+}
 
-    #[test]
-    fn too_many_branckets() {
-        assert_eq!(instructions("FGFGHA[DOAIJD]]").unwrap().0, "]")
+// papers and other tools write `→`, `=>` or `=`; after a condition, `=` is more likely a
+// mistyped comparison than an arrow, so only the others are
+fn arrow(guarded: bool) -> impl FnMut(&str) -> Parsed<'_, Option<f32>> {
+    move |input| {
+        let equals = |input| match guarded {
+            true => Err(nom::Err::Error(Expected::new(input, "an arrow"))),
+            false => tag("=")(input),
+        };
+        let plain = alt((tag("->"), tag("→"), tag("=>"), equals));
+        alt((map(plain, |_| None), map(weighted_arrow, Some)))(input)
     }
+}
 
-    #[test]
-    fn single_rule() {
-        use Instruction::*;
-        assert_eq!(
-            Ok((
-                "",
-                (Symbol('A'), vec![Symbol('K'), Symbol('J'), Symbol('H')])
-            )),
-            rule("A->KJH")
-        )
+fn successor<'i>(
+    input: &'i str,
+    scope: Scope,
+    guarded: bool,
+) -> Parsed<'i, (Option<f32>, Vec<Template>)> {
+    let (input, _) = opt(remove_whitespace)(input)?;
+    let (input, weight) = expecting("`->` after the rule's head", arrow(guarded))(input)?;
+    let (input, _) = opt(remove_whitespace)(input)?;
+    let (input, target) = closed_templates(input, scope)?;
+    Ok((input, (weight, target)))
+}
+
+// `(x, y)` after the symbol a parametric rule rewrites
+fn parameters(input: &str) -> Parsed<'_, Vec<String>> {
+    let (mut input, _) = tag("(")(input)?;
+    let mut names = vec![];
+    loop {
+        let (after, _) = multispace0(input)?;
+        let (after, name) = expr::identifier(after)?;
+        names.push(name.to_string());
+        match separator(after) {
+            Ok((after, _)) => input = after,
+            Err(_) => {
+                let (after, _) = multispace0(after)?;
+                let (after, _) = expect_tag(")")(after)?;
+                return Ok((after, names));
+            }
+        }
     }
+}
 
-    #[test]
-    fn rule_whitespace_before() {
-        use Instruction::*;
-        assert_eq!(
-            Ok((
-                "",
-                (Symbol('A'), vec![Symbol('K'), Symbol('J'), Symbol('H')])
-            )),
-            rule("  \t\nA->KJH")
-        )
+// the symbols of a context up to where `ends` says it does; `*` alone is no context at all
+fn context(mut input: &str, ends: impl Fn(&str) -> bool) -> Parsed<'_, Vec<Symbol>> {
+    let mut symbols = vec![];
+    loop {
+        let (after, _) = multispace0(input)?;
+        if ends(after) {
+            break;
+        }
+        match symbol(after) {
+            Ok((after, symbol)) => {
+                symbols.push(symbol);
+                input = after;
+            }
+            Err(nom::Err::Error(_)) => break,
+            Err(e) => return Err(e),
+        }
+    }
+    match symbols.as_slice() {
+        [] => Err(nom::Err::Error(Expected::new(input, "a context"))),
+        [star] if *star == '*' => Ok((input, vec![])),
+        _ => Ok((input, symbols)),
+    }
+}
+
+// `AB <` before a rule's head, the symbols that must come before the one it rewrites
+fn left_context(input: &str) -> Parsed<'_, Vec<Symbol>> {
+    let (input, left) = context(input, |input| input.starts_with('<'))?;
+    let (input, _) = multispace0(input)?;
+    let (input, _) = tag("<")(input)?;
+    Ok((input, left))
+}
+
+// `> AB` after a rule's head, up to its guard or arrow
+fn right_context(input: &str) -> Parsed<'_, Vec<Symbol>> {
+    let (input, _) = multispace0(input)?;
+    let (input, _) = tag(">")(input)?;
+    let ends = |input: &str| {
+        input.starts_with(':') || !matches!(arrow(false)(input), Err(nom::Err::Error(_)))
+    };
+    context(input, ends)
+}
+
+// `@3` after a rule's head delays it
+fn delay(input: &str) -> Parsed<'_, usize> {
+    let (input, _) = tag("@")(input)?;
+    map_res(digit1, str::parse)(input)
+}
+
+// `: x > 1` between a rule's head and its arrow
+fn guard<'i>(input: &'i str, names: &[String]) -> Parsed<'i, Expr> {
+    let (input, _) = multispace0(input)?;
+    let (input, _) = tag(":")(input)?;
+    expr::parse(input, names)
+}
+
+// a rule, and whether it was given a weight
+fn rule(input: &str) -> Parsed<'_, (Rule, bool)> {
+    rule_in(input, &[])
+}
+
+// the same, where the successor may use the words defined with `let`
+fn rule_in<'i>(input: &'i str, macros: &[(String, Vec<Template>)]) -> Parsed<'i, (Rule, bool)> {
+    let (input, _) = opt(remove_whitespace)(input)?;
+
+    // `<` may as well be a symbol of the successor, so what comes before it is a left context
+    // only if a head and an arrow follow
+    let contextual = left_context(input).and_then(|(input, left)| {
+        let (input, head) = head(input)?;
+        let (at, _) = multispace0(input)?;
+        match arrow(head.condition.is_some())(at) {
+            Err(nom::Err::Error(e)) => Err(nom::Err::Error(e)),
+            _ => Ok((input, (left, head))),
+        }
+    });
+    let (input, (left, head)) = match contextual {
+        Ok(parsed) => parsed,
+        Err(_) => map(head, |head| (vec![], head))(input)?,
+    };
+    let scope = Scope {
+        params: &head.params,
+        macros,
+    };
+    let (input, (weight, target)) = successor(input, scope, head.condition.is_some())?;
+
+    let mut rule = build(head.from, head.params, weight, target);
+    rule.condition = head.condition;
+    rule.delay = head.delay;
+    rule.set_context(left, head.right);
+    Ok((input, (rule, weight.is_some())))
+}
+
+// everything of a rule between its left context and its arrow
+struct Head {
+    from: Instruction,
+    params: Vec<String>,
+    delay: usize,
+    right: Vec<Symbol>,
+    condition: Option<Expr>,
+}
+
+fn head(input: &str) -> Parsed<'_, Head> {
+    let (input, _) = multispace0(input)?;
+    let (input, from) = single_instruction(input)?;
+    let (input, params) = opt(parameters)(input)?;
+    let params = params.unwrap_or_default();
+    let (input, delay) = opt(delay)(input)?;
+    let (input, right) = opt(right_context)(input)?;
+    let (input, condition) = opt(|input| guard(input, &params))(input)?;
+    let head = Head {
+        from,
+        params,
+        delay: delay.unwrap_or(0),
+        right: right.unwrap_or_default(),
+        condition,
+    };
+    Ok((input, head))
+}
+
+fn build(from: Instruction, params: Vec<String>, weight: Option<f32>, to: Vec<Template>) -> Rule {
+    let mut rule = Rule::new(from, shape(&to));
+    if let Some(weight) = weight {
+        rule.set_weight(weight);
+    }
+    rule.params = params;
+    rule.template = has_modules(&to).then_some(to);
+    rule
+}
+
+// `[FG]` in rule head position is a class of symbols sharing one successor, not a branch
+fn symbol_class(input: &str) -> Parsed<'_, Instructions> {
+    let (input, _) = tag("[")(input)?;
+    let mut it = iterator(input, |input| {
+        let (input, _) = opt(remove_whitespace)(input)?;
+        single_instruction(input)
+    });
+
+    let mut symbols: Instructions = vec![];
+    for symbol in &mut it {
+        if !symbols.contains(&symbol) {
+            symbols.push(symbol);
+        }
+    }
+    let (input, ()) = it.finish()?;
+    let (input, _) = opt(remove_whitespace)(input)?;
+    let (input, _) = tag("]")(input)?;
+
+    if symbols.is_empty() {
+        Err(nom::Err::Error(Expected::new(
+            input,
+            "a symbol in the class",
+        )))
+    } else {
+        Ok((input, symbols))
+    }
+}
+
+fn class_rule(input: &str) -> Parsed<'_, (Vec<Rule>, bool)> {
+    class_rule_in(input, &[])
+}
+
+fn class_rule_in<'i>(
+    input: &'i str,
+    macros: &[(String, Vec<Template>)],
+) -> Parsed<'i, (Vec<Rule>, bool)> {
+    let (input, _) = opt(remove_whitespace)(input)?;
+
+    let (input, class) = symbol_class(input)?;
+    let scope = Scope {
+        params: &[],
+        macros,
+    };
+    let (input, (weight, target)) = successor(input, scope, false)?;
+
+    let rules = class
+        .into_iter()
+        .map(|from| build(from, vec![], weight, target.clone()))
+        .collect();
+    Ok((input, (rules, weight.is_some())))
+}
+
+// the rules of one statement, and whether they were given a weight
+fn rules(input: &str) -> Parsed<'_, (Vec<Rule>, bool)> {
+    alt((
+        class_rule,
+        map(rule, |(rule, weighted)| (vec![rule], weighted)),
+    ))(input)
+}
+
+fn rules_in<'i>(
+    input: &'i str,
+    macros: &[(String, Vec<Template>)],
+) -> Parsed<'i, (Vec<Rule>, bool)> {
+    alt((
+        |input| class_rule_in(input, macros),
+        map(
+            |input| rule_in(input, macros),
+            |(rule, weighted)| (vec![rule], weighted),
+        ),
+    ))(input)
+}
+
+/// Where random rule selection draws its randomness from.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RandomStreams {
+    /// One generator for the whole word, in order.
+    #[default]
+    Shared,
+    /// A generator per branch, seeded from the seed, the generation and a key the branch gets
+    /// when a rule produces it, so a branch rewrites the same way no matter what else is
+    /// rewritten, pruned, or in what order. The branches of a word the system didn't derive
+    /// itself, like the axiom, are keyed by their path.
+    PerBranch,
+}
+
+const ROOT: u64 = 0;
+
+// splitmix64 finalizer; stable everywhere, unlike `DefaultHasher`
+fn mix(a: u64, b: u64) -> u64 {
+    let mut z = a.rotate_left(5) ^ b.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+// where a run of instructions is in the word: a hash of the branches it is in, for per branch
+// streams, and what comes before each of them, for left contexts
+struct Place<'w, S = Symbol> {
+    hash: u64,
+    before: &'w [Instruction<S>],
+    outer: Option<&'w Place<'w, S>>,
+    // how many branches the place is in
+    depth: usize,
+}
+
+impl<S> Clone for Place<'_, S> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<S> Copy for Place<'_, S> {}
+
+impl<'w, S> Place<'w, S> {
+    const ROOT: Self = Place {
+        hash: ROOT,
+        before: &[],
+        outer: None,
+        depth: 0,
+    };
+
+    fn branch(&'w self, instrs: &'w [Instruction<S>], i: usize) -> Place<'w, S> {
+        Place {
+            hash: mix(self.hash, i as u64),
+            before: &instrs[..i],
+            outer: Some(self),
+            depth: self.depth + 1,
+        }
+    }
+}
+
+// what is around a symbol being rewritten
+struct Neighbors<'w, S = Symbol> {
+    before: &'w [Instruction<S>],
+    after: &'w [Instruction<S>],
+    place: Place<'w, S>,
+    ignore: &'w [S],
+    // generations since the symbol was produced, where they are kept
+    age: Option<usize>,
+}
+
+impl<S: Letter> Neighbors<'_, S> {
+    fn fit(&self, left: &[S], right: &[S]) -> bool {
+        let symbols = |instrs: &mut dyn Iterator<Item = &Instruction<S>>| {
+            instrs
+                .filter_map(Instruction::symbol)
+                .filter(|s| !self.ignore.contains(s))
+                .take(left.len().max(right.len()))
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        let places = std::iter::successors(Some(&self.place), |place| place.outer);
+        let mut before = self
+            .before
+            .iter()
+            .rev()
+            .chain(places.flat_map(|place| place.before.iter().rev()));
+        let before = symbols(&mut before);
+        let after = symbols(&mut self.after.iter());
+        before.len() >= left.len()
+            && before.iter().zip(left.iter().rev()).all(|(a, b)| a == b)
+            && after.len() >= right.len()
+            && after.iter().zip(right).all(|(a, b)| a == b)
+    }
+}
+
+struct Queries<'a> {
+    env: &'a Environment,
+    answers: std::slice::Iter<'a, bool>,
+    responses: std::slice::Iter<'a, Vec<f32>>,
+}
+
+impl Queries<'_> {
+    fn blocked<S: Letter>(&mut self, symbol: &S) -> bool {
+        symbol.as_symbol() == Some(self.env.query_symbol())
+            && self.answers.next().copied().unwrap_or(false)
+    }
+
+    // the module a symbol the environment responded to is rewritten as
+    fn respond<S: Letter>(&mut self, instr: &Instruction<S>) -> Option<Instruction<S>> {
+        let symbol = instr
+            .symbol()
+            .filter(|s| s.as_symbol().is_some_and(|s| self.env.responds_to(s)))?;
+        let values = self.responses.next()?;
+        Some(Instruction::Module(symbol.clone(), Params(values.clone())))
+    }
+
+    // keeps the answers lined up with query symbols that aren't rewritten
+    fn skip<S: Letter>(&mut self, instr: &Instruction<S>) {
+        match instr {
+            Instruction::Symbol(symbol) | Instruction::Module(symbol, _) => {
+                let Some(symbol) = symbol.as_symbol() else {
+                    return;
+                };
+                if symbol == self.env.query_symbol() {
+                    self.answers.next();
+                }
+                if self.env.responds_to(symbol) {
+                    self.responses.next();
+                }
+            }
+            Instruction::Branch(inner) => inner.iter().for_each(|instr| self.skip(instr)),
+        }
+    }
+}
+
+/// How `LSystem::mutate` edits a grammar: how many edits, how likely each kind of edit is, and
+/// how large successors may grow.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MutationConfig {
+    edits: usize,
+    swap: f32,
+    insert: f32,
+    delete: f32,
+    branch: f32,
+    max_length: usize,
+    max_depth: usize,
+}
+
+impl Default for MutationConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MutationConfig {
+    pub fn new() -> Self {
+        Self {
+            edits: 2,
+            swap: 1.0,
+            insert: 1.0,
+            delete: 1.0,
+            branch: 1.0,
+            max_length: 64,
+            max_depth: 8,
+        }
+    }
+
+    pub fn edits(self, edits: usize) -> Self {
+        Self { edits, ..self }
+    }
+
+    /// How likely an edit replaces a symbol with another, relative to the other kinds.
+    pub fn swap(self, swap: f32) -> Self {
+        Self { swap, ..self }
+    }
+
+    /// How likely an edit inserts a symbol.
+    pub fn insert(self, insert: f32) -> Self {
+        Self { insert, ..self }
+    }
+
+    /// How likely an edit deletes an instruction, never the last one of a sequence.
+    pub fn delete(self, delete: f32) -> Self {
+        Self { delete, ..self }
+    }
+
+    /// How likely an edit wraps an instruction in a branch with a new symbol, or unwraps a
+    /// branch.
+    pub fn branch(self, branch: f32) -> Self {
+        Self { branch, ..self }
+    }
+
+    /// The most symbols an edit leaves in a successor; edits past it are undone.
+    pub fn max_length(self, max_length: usize) -> Self {
+        Self { max_length, ..self }
+    }
+
+    /// The deepest an edit nests the branches of a successor; edits past it are undone.
+    pub fn max_depth(self, max_depth: usize) -> Self {
+        Self { max_depth, ..self }
+    }
+}
+
+/// Which rule table rewrites which generations of a table L-system, e.g. a season of growth
+/// followed by one of flowering.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Schedule(Vec<(Range<usize>, String)>);
+
+impl Schedule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rewrites the generations in `generations` with `table`: the rewrite of generation 0, the
+    /// axiom, into generation 1 is the first. Earlier entries win where ranges overlap.
+    pub fn during(mut self, generations: Range<usize>, table: impl Into<String>) -> Self {
+        self.0.push((generations, table.into()));
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(remote = "Self")
+)]
+pub struct LSystem<S = Symbol> {
+    word: Instructions<S>,
+    axiom: Instructions<S>,
+    rules: Vec<Rule<S>>,
+    tables: Vec<(String, Vec<Rule<S>>)>,
+    table: Option<usize>,
+    schedule: Vec<(Range<usize>, usize)>,
+    selection: RuleSelection,
+    streams: RandomStreams,
+    seed: u64,
+    generation: usize,
+    #[cfg_attr(feature = "serde", serde(with = "rng_state"))]
+    rng: ChaCha12Rng,
+    ignore: Vec<S>,
+    constants: Vec<S>,
+    // in degrees, as `angle 25.7;` declares it
+    angle: Option<f32>,
+    step: Option<f32>,
+    dropout: Option<Pruning>,
+    trace: Option<DerivationTrace>,
+    // the generation every symbol of the word was produced in, in reading order
+    births: Option<Vec<usize>>,
+    // the keys of the word's branches in reading order, for per branch streams
+    branch_keys: Option<Vec<u64>>,
+    stats: Option<DerivationStats>,
+    // symbols spliced with the word of another system, derived to its depth
+    bindings: Vec<(S, LSystem<S>, usize)>,
+    // the depth, and how many more rewrites symbols past it get
+    max_depth: Option<(usize, usize)>,
+    // how often every symbol of the word was rewritten past the depth, when that is counted
+    deep_rewrites: Option<Vec<usize>>,
+    // the parallel rewrite, set where the symbols are known to be `Send` and `Sync`
+    #[cfg(feature = "parallel")]
+    #[cfg_attr(feature = "serde", serde(skip, default = "Option::default"))]
+    parallel: Option<fn(&mut LSystem<S>)>,
+}
+
+impl<S: PartialEq> PartialEq for LSystem<S> {
+    fn eq(&self, other: &Self) -> bool {
+        self.word == other.word
+            && self.axiom == other.axiom
+            && self.rules == other.rules
+            && self.tables == other.tables
+            && self.table == other.table
+            && self.schedule == other.schedule
+            && self.selection == other.selection
+            && self.ignore == other.ignore
+            && self.constants == other.constants
+            && self.angle == other.angle
+            && self.step == other.step
+            && self.dropout == other.dropout
+            && self.bindings == other.bindings
+            && self.max_depth == other.max_depth
+            && self.streams == other.streams
+            && self.seed == other.seed
+    }
+}
+
+fn terminate<F, G>(f: F) -> impl Fn(&str) -> Parsed<'_, G>
+where
+    F: Fn(&str) -> Parsed<'_, G>,
+{
+    move |input| {
+        let (input, res) = f(input)?;
+        let (input, _) = opt(remove_whitespace)(input)?;
+        let (input, _) = expect_tag(";")(input)?;
+        Ok((input, res))
+    }
+}
+
+enum Statement {
+    Rules(Vec<Rule>, bool),
+    Ignore(Vec<Symbol>),
+    Const(Vec<Symbol>),
+}
+
+// `#ignore +-F`, the symbols contexts skip
+fn ignore(input: &str) -> Parsed<'_, Vec<Symbol>> {
+    let (input, _) = opt(remove_whitespace)(input)?;
+    let (input, _) = tag("#ignore")(input)?;
+    let mut it = iterator(input, |input| {
+        let (input, _) = opt(remove_whitespace)(input)?;
+        symbol(input)
+    });
+    let symbols = (&mut it).collect();
+    let (input, ()) = it.finish()?;
+    Ok((input, symbols))
+}
+
+// `#const +-[]`, the symbols no rule may rewrite; brackets are allowed, but never rewritten
+// anyway
+fn constants(input: &str) -> Parsed<'_, Vec<Symbol>> {
+    let (input, _) = opt(remove_whitespace)(input)?;
+    let (input, _) = tag("#const")(input)?;
+    constant_symbols(input)
+}
+
+// `const + - [ ]`, the same without the `#`; it is tried after rules, so a rule whose head
+// starts with `const` still reads as one
+fn bare_constants(input: &str) -> Parsed<'_, Vec<Symbol>> {
+    let (input, _) = opt(remove_whitespace)(input)?;
+    let (input, _) = terminated(tag("const"), peek(alt((multispace1, tag(";")))))(input)?;
+    constant_symbols(input)
+}
+
+fn constant_symbols(input: &str) -> Parsed<'_, Vec<Symbol>> {
+    let mut it = iterator(input, |input| {
+        let (input, _) = opt(remove_whitespace)(input)?;
+        alt((map(symbol, Some), map(alt((tag("["), tag("]"))), |_| None)))(input)
+    });
+    let symbols = (&mut it).flatten().collect();
+    let (input, ()) = it.finish()?;
+    Ok((input, symbols))
+}
+
+fn statement<'i>(input: &'i str, macros: &[(String, Vec<Template>)]) -> Parsed<'i, Statement> {
+    alt((
+        map(ignore, Statement::Ignore),
+        map(constants, Statement::Const),
+        map(
+            |input| rules_in(input, macros),
+            |(rules, weighted)| Statement::Rules(rules, weighted),
+        ),
+        map(bare_constants, Statement::Const),
+    ))(input)
+}
+
+// `let turn = +F-F`, a word the axiom and successors can use by name
+fn definition<'i>(
+    input: &'i str,
+    macros: &[(String, Vec<Template>)],
+) -> Parsed<'i, (String, Vec<Template>)> {
+    let (input, _) = opt(remove_whitespace)(input)?;
+    let (input, _) = tag("let")(input)?;
+    let (input, _) = remove_whitespace(input)?;
+    let (input, name) = expr::identifier(input)?;
+    let (input, _) = opt(remove_whitespace)(input)?;
+    let (input, _) = tag("=")(input)?;
+    let scope = Scope {
+        params: &[],
+        macros,
+    };
+    let (after, word) = closed_templates(input, scope)?;
+    if size(&word) > MAX_REPEAT {
+        return too_large(input);
+    }
+    Ok((after, (name.to_string(), word)))
+}
+
+// `angle 25.7;` or `step 4;`, how a grammar is meant to be drawn; past the keyword, it can't
+// be the axiom any more unless `let` defined a word by that name, and the value must be
+// positive and finite
+fn setting<'i>(
+    input: &'i str,
+    macros: &[(String, Vec<Template>)],
+) -> Parsed<'i, (&'static str, f32)> {
+    let (input, _) = opt(remove_whitespace)(input)?;
+    let named = |name| map(tag(name), move |_| name);
+    let (input, name) = alt((named("angle"), named("step")))(input)?;
+    let (input, _) = remove_whitespace(input)?;
+    if macros.iter().any(|(defined, _)| defined == name) && float::<_, Expected>(input).is_err() {
+        return Err(nom::Err::Error(Expected::new(input, "a number")));
+    }
+    let (after, value) = cut(expecting("a number", float))(input)?;
+    if !(value.is_finite() && value > 0.0) {
+        return Err(nom::Err::Failure(Expected::new(
+            input,
+            format!("a positive, finite {}", name),
+        )));
+    }
+    let (after, _) = opt(remove_whitespace)(after)?;
+    let (after, _) = cut(expect_tag(";"))(after)?;
+    Ok((after, (name, value)))
+}
+
+// `axiom: X rules: X -> FX;`, where the axiom ends at `rules:` without a `;` of its own
+fn sectioned_axiom<'i>(input: &'i str, scope: Scope) -> Parsed<'i, Vec<Template>> {
+    let end = [input.find(';'), input.find("rules:")]
+        .into_iter()
+        .flatten()
+        .min()
+        .unwrap_or(input.len());
+    let (word, rest) = input.split_at(end);
+    // failures are told where they are in all of `input`, not just in `word`
+    let (left, axiom) = closed_templates(word, scope)
+        .map_err(|e| e.map(|e| Expected::new(&input[end - e.input.len()..], e.what)))?;
+    if !left.trim().is_empty() {
+        let left = &input[end - left.len()..];
+        return Err(nom::Err::Error(Expected::new(
+            left,
+            "`;` or `rules:` after the axiom",
+        )));
+    }
+    let (rest, _) = opt(tag(";"))(rest)?;
+    Ok((rest, axiom))
+}
+
+fn rules_label(input: &str) -> Parsed<'_, &str> {
+    let (input, _) = opt(remove_whitespace)(input)?;
+    tag("rules:")(input)
+}
+
+// a strict reading fails at the first statement it can't read, a lenient one stops there
+fn lsystem(input: &str, strict: bool) -> Parsed<'_, LSystem> {
+    let mut input = input;
+    let mut macros: Vec<(String, Vec<Template>)> = vec![];
+    let (mut angle, mut step) = (None, None);
+    loop {
+        match setting(input, &macros) {
+            Ok((rest, (name, value))) => {
+                match name {
+                    "angle" => angle = Some(value),
+                    _ => step = Some(value),
+                }
+                input = rest;
+                continue;
+            }
+            Err(nom::Err::Error(_)) => {}
+            Err(e) => return Err(e),
+        }
+        let defined = terminate(|input| definition(input, &macros))(input);
+        match defined {
+            Ok((rest, (name, word))) => {
+                macros.retain(|(defined, _)| *defined != name);
+                macros.push((name, word));
+                macros.sort_by_key(|(name, _)| std::cmp::Reverse(name.len()));
+                input = rest;
+            }
+            Err(nom::Err::Error(_)) => break,
+            Err(e) => return Err(e),
+        }
+    }
+    let scope = Scope {
+        params: &[],
+        macros: &macros,
+    };
+
+    let (input, axiom) = match input.trim_start().strip_prefix("axiom:") {
+        Some(input) => sectioned_axiom(input, scope)?,
+        None => terminate(|input| closed_templates(input, scope))(input)?,
+    };
+    let (input, _) = opt(rules_label)(input)?;
+    let instr = instantiate(&axiom, &[]);
+    let mut it = iterator(input, terminate(|input| statement(input, &macros)));
+    let mut any_weighted = false;
+    let mut rules = vec![];
+    let mut ignored = vec![];
+    let mut constants = vec![];
+    for statement in &mut it {
+        match statement {
+            Statement::Rules(statement, weighted) => {
+                rules.extend(statement);
+                any_weighted |= weighted;
+            }
+            Statement::Ignore(symbols) => ignored.extend(symbols),
+            Statement::Const(symbols) => constants.extend(symbols),
+        }
+    }
+    let (input, ()) = it.finish()?;
+    if strict && !input.trim_start().is_empty() {
+        // the iterator stops quietly; reading the statement again says why
+        terminate(|input| statement(input, &macros))(input)?;
+    }
+
+    let mut lsystem = LSystem::new(instr, rules);
+    lsystem.set_ignored(ignored);
+    lsystem.set_constants(constants);
+    lsystem.angle = angle;
+    lsystem.step = step;
+    if any_weighted {
+        lsystem.selection = RuleSelection::Weighted;
+    }
+    Ok((input, lsystem))
+}
+
+// the derived impls, with what they read checked against itself
+#[cfg(feature = "serde")]
+impl<S: serde::Serialize> serde::Serialize for LSystem<S> {
+    fn serialize<W: serde::Serializer>(&self, serializer: W) -> Result<W::Ok, W::Error> {
+        LSystem::serialize(self, serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, S: serde::Deserialize<'de>> serde::Deserialize<'de> for LSystem<S> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let lsystem = LSystem::deserialize(deserializer)?;
+        let tables = lsystem.tables.len();
+        let symbols = count_symbols(&lsystem.word);
+        let error = if lsystem.table.is_some_and(|table| table >= tables) {
+            "the table in use isn't one of the system's tables"
+        } else if lsystem.schedule.iter().any(|(_, table)| *table >= tables) {
+            "the schedule uses a table the system doesn't have"
+        } else if lsystem.births.as_ref().is_some_and(|b| b.len() != symbols) {
+            "there isn't a birth for every symbol of the word"
+        } else if (lsystem.branch_keys.as_ref())
+            .is_some_and(|keys| keys.len() != count_branches(&lsystem.word))
+        {
+            "there isn't a key for every branch of the word"
+        } else if (lsystem.deep_rewrites.as_ref()).is_some_and(|r| r.len() != symbols) {
+            "there isn't a count of deep rewrites for every symbol of the word"
+        } else {
+            return Ok(lsystem);
+        };
+        Err(serde::de::Error::custom(error))
+    }
+}
+
+// where a generator is in its stream, in integers every format has
+#[cfg(feature = "serde")]
+mod rng_state {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha12Rng;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    struct State {
+        seed: [u8; 32],
+        stream: u64,
+        // the position in words, high half first
+        position: [u64; 2],
+    }
+
+    pub fn serialize<S: Serializer>(rng: &ChaCha12Rng, serializer: S) -> Result<S::Ok, S::Error> {
+        let position = rng.get_word_pos();
+        let state = State {
+            seed: rng.get_seed(),
+            stream: rng.get_stream(),
+            position: [(position >> 64) as u64, position as u64],
+        };
+        state.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<ChaCha12Rng, D::Error> {
+        let state = State::deserialize(deserializer)?;
+        let mut rng = ChaCha12Rng::from_seed(state.seed);
+        rng.set_stream(state.stream);
+        rng.set_word_pos(((state.position[0] as u128) << 64) | state.position[1] as u128);
+        Ok(rng)
+    }
+}
+
+/// Why a grammar, rules or a word don't parse, or a system can't do what it is asked. It owns
+/// all it says, so unlike most parse errors it can be kept, returned after the input is gone,
+/// or sent to another thread.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LsysError {
+    /// Something other than what the grammar allows, at `offset` bytes into the input.
+    ParseError {
+        message: String,
+        offset: usize,
+    },
+    /// A `[` or `(` that isn't closed, or a `]` or `)` that closes nothing.
+    UnbalancedBracket {
+        message: String,
+        offset: usize,
+    },
+    /// Brackets and parentheses nested deeper than `MAX_NESTING`, the first too deep at
+    /// `offset`.
+    TooDeep {
+        offset: usize,
+    },
+    EmptyAxiom,
+    /// A rule rewrites a symbol declared with `#const`.
+    RewritesConstant(Symbol),
+    /// Rules competing for a symbol whose weights can't be picked from as written, as
+    /// `LSystem::check_weights` finds them.
+    Weights(String),
+    /// No rule table has the name.
+    NoTable(String),
+    /// Several unguarded rules rewrite the symbol, as `LSystem::check_unambiguous` finds it.
+    Ambiguous(String),
+    /// A position past the `symbols` of the word.
+    BeyondWord {
+        position: usize,
+        symbols: usize,
+    },
+    /// A generation with more than the `max` symbols allowed.
+    TooManySymbols {
+        generation: usize,
+        symbols: usize,
+        max: usize,
+    },
+    /// A word that grows faster than allowed, as `LSystem::check_growth` finds it.
+    Growth(String),
+}
+
+impl LsysError {
+    // `message` about the place `rest` starts at in `input`, and that place
+    fn located(input: &str, rest: &str, message: &str) -> (String, usize) {
+        let offset = input.len() - rest.len();
+        match line_column(input, offset) {
+            Some((line, column)) => (
+                format!("{} at line {}, col {}", message, line, column),
+                offset,
+            ),
+            None => (message.to_string(), offset),
+        }
+    }
+
+    // what the parser expected where it failed in `input`, and what it found instead
+    fn expected(input: &str, error: nom::Err<Expected>) -> Self {
+        let error = match error {
+            nom::Err::Error(error) | nom::Err::Failure(error) => error,
+            nom::Err::Incomplete(_) => {
+                return LsysError::ParseError {
+                    message: String::from("the input ends early"),
+                    offset: input.len(),
+                }
+            }
+        };
+        let found = error.input.trim_start();
+        // a bracket closing nothing, where a statement or branch should have ended
+        let closing = matches!(&*error.what, "`;`" | "`]`" | "`)`");
+        if closing && found.starts_with([']', ')']) {
+            return Self::unexpected(input, found);
+        }
+        let (mut message, offset) =
+            Self::located(input, found, &format!("expected {}", error.what));
+        match found.split_whitespace().next() {
+            Some(found) => message += &format!(", found `{}`", found),
+            None => message += ", found the end",
+        }
+        match &*error.what {
+            "`]`" | "`)`" => LsysError::UnbalancedBracket { message, offset },
+            _ => LsysError::ParseError { message, offset },
+        }
+    }
+
+    // input left over at `rest` that nothing reads
+    fn unexpected(input: &str, rest: &str) -> Self {
+        let c = rest.chars().next().unwrap_or(' ');
+        let (message, offset) = Self::located(input, rest, &format!("unexpected `{}`", c));
+        match c {
+            ']' | ')' => LsysError::UnbalancedBracket { message, offset },
+            _ => LsysError::ParseError { message, offset },
+        }
+    }
+
+    /// Where in the input parsing failed, in bytes, if the error is about one place.
+    pub fn offset(&self) -> Option<usize> {
+        match self {
+            LsysError::ParseError { offset, .. }
+            | LsysError::UnbalancedBracket { offset, .. }
+            | LsysError::TooDeep { offset } => Some(*offset),
+            _ => None,
+        }
+    }
+
+    /// The line and column of `offset` in `input`, both counted from 1, columns in characters.
+    pub fn line_column(&self, input: &str) -> Option<(usize, usize)> {
+        line_column(input, self.offset()?)
+    }
+}
+
+fn line_column(input: &str, offset: usize) -> Option<(usize, usize)> {
+    let before = input.get(..offset)?;
+    let line_start = before.rfind('\n').map_or(0, |n| n + 1);
+    let line = before.matches('\n').count() + 1;
+    Some((line, before[line_start..].chars().count() + 1))
+}
+
+impl fmt::Display for LsysError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LsysError::ParseError { message, .. }
+            | LsysError::UnbalancedBracket { message, .. }
+            | LsysError::Weights(message)
+            | LsysError::Growth(message) => f.write_str(message),
+            LsysError::TooDeep { .. } => {
+                write!(f, "brackets are nested deeper than {} levels", MAX_NESTING)
+            }
+            LsysError::EmptyAxiom => f.write_str("the axiom is empty"),
+            LsysError::RewritesConstant(symbol) => {
+                write!(f, "'{}' is a constant, but a rule rewrites it", symbol)
+            }
+            LsysError::NoTable(name) => write!(f, "there is no rule table '{}'", name),
+            LsysError::Ambiguous(symbol) => write!(f, "several rules rewrite '{}'", symbol),
+            LsysError::BeyondWord { symbols, .. } => {
+                write!(f, "the word has only {} symbols", symbols)
+            }
+            LsysError::TooManySymbols {
+                generation,
+                symbols,
+                max,
+            } => write!(
+                f,
+                "generation {} has {} symbols, more than the {} allowed",
+                generation, symbols, max
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LsysError {}
+
+/// Why a grammar file couldn't be loaded by `LSystem::from_file`, naming the file and, for
+/// parse errors about one place, the line and column.
+#[derive(Debug)]
+pub enum LoadError {
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        location: Option<(usize, usize)>,
+        error: LsysError,
+    },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            LoadError::Parse { path, error, .. } => write!(f, "{}: {}", path.display(), error),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::Io { error, .. } => Some(error),
+            LoadError::Parse { error, .. } => Some(error),
+        }
+    }
+}
+
+impl std::str::FromStr for LSystem {
+    type Err = LsysError;
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        LSystem::from_str(input)
+    }
+}
+
+/// Deeper nesting of brackets and parentheses is rejected before parsing, since the parser and
+/// everything that walks a word recurses once per level.
+pub const MAX_NESTING: usize = 128;
+
+// a comment runs from `#` or `//` to the end of the line, and is blanked out byte for byte so
+// what follows stays where it was; `#ignore` and `#const` start statements instead
+fn strip_comments(input: &str) -> Cow<'_, str> {
+    let starts_comment = |(i, _): &(usize, &str)| {
+        let after = &input[i + 1..];
+        match input.as_bytes()[*i] {
+            b'#' => !after.starts_with("ignore") && !after.starts_with("const"),
+            _ => after.starts_with('/'),
+        }
+    };
+    let mut comments = input
+        .match_indices(['#', '/'])
+        .filter(starts_comment)
+        .peekable();
+    if comments.peek().is_none() {
+        return Cow::Borrowed(input);
+    }
+
+    let mut stripped = String::with_capacity(input.len());
+    let mut end = 0;
+    for (start, _) in comments {
+        if start < end {
+            continue;
+        }
+        stripped.push_str(&input[end..start]);
+        end = input[start..].find('\n').map_or(input.len(), |n| start + n);
+        stripped.extend(std::iter::repeat_n(' ', end - start));
+    }
+    stripped.push_str(&input[end..]);
+    Cow::Owned(stripped)
+}
+
+fn check_nesting(input: &str) -> Result<(), LsysError> {
+    let mut depth = 0usize;
+    for (offset, c) in input.char_indices() {
+        match c {
+            '[' | '(' => depth += 1,
+            ']' | ')' => depth = depth.saturating_sub(1),
+            _ => continue,
+        }
+        if depth > MAX_NESTING {
+            return Err(LsysError::TooDeep { offset });
+        }
+    }
+    Ok(())
+}
+
+/// An already expanded word that can be drawn without a grammar, e.g. one written out earlier
+/// or produced by another program. It is indexed and sliced by its top level instructions, a
+/// branch counting as one.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Word<S = Symbol>(Instructions<S>);
+
+impl<S> Default for Word<S> {
+    fn default() -> Self {
+        Self(vec![])
+    }
+}
+
+impl Word {
+    /// Parses symbols and branches the way an axiom is written, without the terminating `;`.
+    pub fn parse(input: &str) -> Result<Self, LsysError> {
+        check_nesting(input)?;
+        let (rest, templates) =
+            closed_templates(input, EMPTY_SCOPE).map_err(|e| LsysError::expected(input, e))?;
+        match rest.chars().next() {
+            Some(_) => Err(LsysError::unexpected(input, rest)),
+            None => Ok(Self(instantiate(&templates, &[]))),
+        }
+    }
+
+    pub fn stats(&self) -> WordStats {
+        WordStats::of(&self.0)
+    }
+}
+
+impl<S: Clone> Word<S> {
+    pub fn instructions(&self) -> &[Instruction<S>] {
+        &self.0
+    }
+
+    pub fn into_instructions(self) -> Instructions<S> {
+        self.0
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The top level instructions in `range` as a word of their own, e.g. to draw part of a
+    /// word.
+    pub fn sub_word(&self, range: Range<usize>) -> Word<S> {
+        Self(self.0[range].to_vec())
+    }
+
+    /// Every symbol and module in reading order, through branches, each with the number of
+    /// branches it is in.
+    pub fn symbols(&self) -> Flattened<'_, S> {
+        Flattened {
+            stack: vec![self.0.iter()],
+        }
+    }
+}
+
+impl<S> From<Instructions<S>> for Word<S> {
+    fn from(instructions: Instructions<S>) -> Self {
+        Self(instructions)
+    }
+}
+
+impl<S> From<Word<S>> for Instructions<S> {
+    fn from(word: Word<S>) -> Self {
+        word.0
+    }
+}
+
+impl<S> AsRef<[Instruction<S>]> for Word<S> {
+    fn as_ref(&self) -> &[Instruction<S>] {
+        &self.0
+    }
+}
+
+impl<S, I: SliceIndex<[Instruction<S>]>> Index<I> for Word<S> {
+    type Output = I::Output;
+
+    fn index(&self, index: I) -> &Self::Output {
+        &self.0[index]
+    }
+}
+
+impl<'w, S> IntoIterator for &'w Word<S> {
+    type Item = &'w Instruction<S>;
+    type IntoIter = std::slice::Iter<'w, Instruction<S>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+/// A symbol of a word, with where and when it grew.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AnnotatedSymbol<'w, S = Symbol> {
+    pub instruction: &'w Instruction<S>,
+    /// How many branches the symbol is in.
+    pub depth: usize,
+    /// The generation the symbol was produced in.
+    pub generation: usize,
+}
+
+/// The symbols of a system's word in reading order, created by `LSystem::annotated`.
+#[derive(Debug, Clone)]
+pub struct Annotated<'w, S = Symbol> {
+    symbols: Flattened<'w, S>,
+    births: std::slice::Iter<'w, usize>,
+}
+
+impl<'w, S> Iterator for Annotated<'w, S> {
+    type Item = AnnotatedSymbol<'w, S>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (instruction, depth) = self.symbols.next()?;
+        Some(AnnotatedSymbol {
+            instruction,
+            depth,
+            generation: *self.births.next()?,
+        })
+    }
+}
+
+/// The symbols of a word with their branch depths, created by `Word::symbols`.
+#[derive(Debug, Clone)]
+pub struct Flattened<'w, S = Symbol> {
+    stack: Vec<std::slice::Iter<'w, Instruction<S>>>,
+}
+
+impl<'w, S> Iterator for Flattened<'w, S> {
+    type Item = (&'w Instruction<S>, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let next = self.stack.last_mut()?.next();
+            let depth = self.stack.len() - 1;
+            match next {
+                Some(Instruction::Branch(inner)) => self.stack.push(inner.iter()),
+                Some(instr) => return Some((instr, depth)),
+                None => {
+                    self.stack.pop();
+                }
+            }
+        }
+    }
+}
+
+impl<S: fmt::Display> fmt::Display for Word<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_instructions(f, &self.0)
+    }
+}
+
+impl<S: Letter> LSystem<S> {
+    /// A system that rewrites `axiom` with the first matching rule and seed 0, as a grammar
+    /// without weights does.
+    pub fn new(axiom: Instructions<S>, rules: Vec<Rule<S>>) -> Self {
+        Self {
+            word: axiom.clone(),
+            axiom,
+            rules,
+            tables: vec![],
+            table: None,
+            schedule: vec![],
+            selection: RuleSelection::default(),
+            streams: RandomStreams::default(),
+            seed: 0,
+            generation: 0,
+            rng: ChaCha12Rng::seed_from_u64(0),
+            ignore: vec![],
+            constants: vec![],
+            angle: None,
+            step: None,
+            dropout: None,
+            trace: None,
+            births: None,
+            branch_keys: None,
+            stats: None,
+            bindings: vec![],
+            max_depth: None,
+            deep_rewrites: None,
+            #[cfg(feature = "parallel")]
+            parallel: None,
+        }
+    }
+
+    fn step(&mut self) {
+        self.step_while(None);
+    }
+
+    // with `should_continue`, gives up in the middle of the word once it says no, and leaves the
+    // system as it was
+    fn step_while(&mut self, should_continue: Option<&mut dyn FnMut() -> bool>) {
+        #[cfg(feature = "parallel")]
+        if let Some(step_parallel) = self.parallel.filter(|_| {
+            self.trace.is_none()
+                && self.births.is_none()
+                && self.stats.is_none()
+                && !self.selection.is_random()
+                && self.dropout.is_none()
+                && !self.needs_whole_word()
+        }) {
+            return step_parallel(self);
+        }
+        self.rewrite_word(None, &|_| true, false, should_continue);
+    }
+}
+
+#[cfg(feature = "parallel")]
+impl<S: Letter + Send + Sync> LSystem<S> {
+    /// Rewrites the top level of the word in chunks on rayon's threads from now on, when rules
+    /// are selected without randomness or context and neither a trace nor counts are kept; the
+    /// words derived are the same either way. A system read back with serde rewrites on one
+    /// thread until this is set again.
+    pub fn set_parallel(&mut self, parallel: bool) {
+        self.parallel = parallel.then_some(Self::step_parallel);
+    }
+
+    // no randomness is drawn, so chunks rewrite exactly as the whole word would
+    fn step_parallel(&mut self) {
+        use rayon::prelude::*;
+
+        const MIN_CHUNK: usize = 4096;
+
+        let mut word = std::mem::take(&mut self.word);
+        if let Some(cut) = word.iter().position(Instruction::is_cut) {
+            word.truncate(cut);
+        }
+        let chunk = (word.len() / rayon::current_num_threads()).max(MIN_CHUNK);
+        let chunks: Vec<Instructions<S>> = word
+            .par_chunks(chunk)
+            .map(|chunk| {
+                let mut rng = self.rng.clone();
+                self.rewrite(chunk, Place::ROOT, &mut rng, None, &|_| true, None)
+            })
+            .collect();
+        self.word = chunks.into_iter().flatten().collect();
+        self.branch_keys = None;
+        self.generation += 1;
+    }
+}
+
+impl<S: Letter> LSystem<S> {
+    /// The word the next call to `next` will return.
+    pub fn word(&self) -> &Instructions<S> {
+        &self.word
+    }
+
+    /// The generation of the current word; the axiom is generation 0.
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+
+    /// Rewrites the current word `n` times and returns it, like `nth(n)`, unless a generation on
+    /// the way has more than `max_symbols` symbols: then it is an error, and the system starts
+    /// over from its axiom. Every generation is counted once it is derived, so the first one
+    /// over the budget is still built, but never the ones after it.
+    pub fn nth_bounded(
+        &mut self,
+        n: usize,
+        max_symbols: usize,
+    ) -> Result<Instructions<S>, LsysError> {
+        for i in 0..=n {
+            let symbols = count_symbols(&self.word);
+            if symbols > max_symbols {
+                let generation = self.generation;
+                self.reset();
+                return Err(LsysError::TooManySymbols {
+                    generation,
+                    symbols,
+                    max: max_symbols,
+                });
+            }
+            if i < n {
+                self.step();
+            }
+        }
+        Ok(self.word.clone())
+    }
+
+    /// Rewrites the current word `n` times and returns it, like `nth_bounded`, but asks
+    /// `should_continue` before every rewrite, and now and then during one, and gives up as soon
+    /// as it says no, e.g. when a request is abandoned. Then it is `None`, and the system stays
+    /// at the last generation it derived in full, so asking for more generations goes on from
+    /// there.
+    pub fn nth_while(
+        &mut self,
+        n: usize,
+        mut should_continue: impl FnMut() -> bool,
+    ) -> Option<Instructions<S>> {
+        for _ in 0..n {
+            let generation = self.generation;
+            if !should_continue() {
+                return None;
+            }
+            self.step_while(Some(&mut should_continue));
+            if self.generation == generation {
+                return None;
+            }
+        }
+        Some(self.word.clone())
+    }
+
+    /// Whether `word` is derived from the axiom in exactly `steps` rewrites, with any of the
+    /// rules that could be picked wherever they are picked at random. `None` if some generation
+    /// on the way has more than `max_words` different words to try, branches drop out, rules
+    /// are delayed or deep symbols are rewritten a few more times.
+    pub fn derives(&self, word: &[Instruction<S>], steps: usize, max_words: usize) -> Option<bool> {
+        let further = self.max_depth.is_some_and(|(_, further)| further > 0);
+        if self.dropout.is_some() || self.has_delays() || further {
+            return None;
+        }
+        let mut words = HashSet::from([self.axiom.clone()]);
+        for generation in 0..steps {
+            let rules = self.rules_at(generation);
+            let mut next = HashSet::new();
+            for instrs in &words {
+                next.extend(self.every_rewrite(instrs, Place::ROOT, rules, max_words)?);
+                if next.len() > max_words {
+                    return None;
+                }
+            }
+            words = next;
+        }
+        Some(words.contains(word))
+    }
+
+    fn every_rewrite(
+        &self,
+        instrs: &[Instruction<S>],
+        place: Place<S>,
+        rules: &[Rule<S>],
+        max_words: usize,
+    ) -> Option<Vec<Instructions<S>>> {
+        let mut words = vec![vec![]];
+        for (i, instr) in instrs.iter().enumerate() {
+            let options: Vec<Instructions<S>> = match instr {
+                _ if instr.is_cut() => break,
+                Instruction::Branch(inner) => self
+                    .every_rewrite(inner, place.branch(instrs, i), rules, max_words)?
+                    .into_iter()
+                    .map(|inner| vec![Instruction::Branch(inner)])
+                    .collect(),
+                Instruction::Symbol(_) | Instruction::Module(..) if self.too_deep(&place, 0) => {
+                    vec![vec![instr.clone()]]
+                }
+                Instruction::Symbol(_) | Instruction::Module(..) => {
+                    let neighbors = Neighbors {
+                        before: &instrs[..i],
+                        after: &instrs[i + 1..],
+                        place,
+                        ignore: &self.ignore,
+                        age: None,
+                    };
+                    self.options(instr, &neighbors, rules)
+                }
+            };
+            if words.len() * options.len() > max_words {
+                return None;
+            }
+            words = words
+                .iter()
+                .flat_map(|word| {
+                    options
+                        .iter()
+                        .map(move |option| [&word[..], option].concat())
+                })
+                .collect();
+        }
+        Some(words)
+    }
+
+    // every successor the selection can give `instr`
+    fn options(
+        &self,
+        instr: &Instruction<S>,
+        neighbors: &Neighbors<S>,
+        rules: &[Rule<S>],
+    ) -> Vec<Instructions<S>> {
+        let matching = rules
+            .iter()
+            .filter(|rule| rule.matches(instr) && rule.fits(Some(neighbors)));
+        let picked: Vec<&Rule<S>> = match self.selection {
+            RuleSelection::Uniform => matching.collect(),
+            RuleSelection::Weighted => {
+                let matching: Vec<_> = matching.collect();
+                let weighted: Vec<_> = matching
+                    .iter()
+                    .copied()
+                    .filter(|rule| rule.weight > 0.0 && rule.weight.is_finite())
+                    .collect();
+                match weighted.is_empty() {
+                    true => matching.into_iter().take(1).collect(),
+                    false => weighted,
+                }
+            }
+            // nothing random is drawn
+            _ => {
+                let mut rng = ChaCha12Rng::seed_from_u64(0);
+                let picked = self
+                    .selection
+                    .select(rules, instr, Some(neighbors), &mut rng);
+                picked.map(|(_, rule)| rule).into_iter().collect()
+            }
+        };
+
+        let mut options: Vec<Instructions<S>> = vec![];
+        for successor in picked.iter().map(|rule| rule.successor(instr)) {
+            if !options.contains(&successor) {
+                options.push(successor);
+            }
+        }
+        if options.is_empty() {
+            options.push(vec![instr.clone()]);
+        }
+        options
+    }
+
+    /// The words from the axiom on, derived one at a time as they are asked for, so earlier
+    /// generations can be looked at on the way to a deep one.
+    pub fn derivations(&self) -> Derivations<S> {
+        let mut lsystem = self.clone();
+        lsystem.reset();
+        Derivations(lsystem)
+    }
+
+    /// The words from the current one on, like the system itself, but ending with the first
+    /// word a rewrite leaves as it is: a grammar that has stabilized is not rewritten again.
+    /// Words picked at random, dropped out or scheduled in tables can still change after such a
+    /// word; those systems stop at it all the same.
+    pub fn until_fixed(self) -> UntilFixed<S> {
+        UntilFixed {
+            lsystem: self,
+            fixed: false,
+        }
+    }
+
+    /// The rewrites from the current word on, each with where rules fired in it and what they
+    /// produced, e.g. to animate a word growing production by production.
+    pub fn replay(self) -> Replay<S> {
+        Replay { lsystem: self }
+    }
+
+    /// Rewrites the branch of the current word at `path` (the index of a branch in the word,
+    /// then of a branch within that one, and so on) on its own. With per branch streams this is
+    /// exactly what the next step turns that branch into; otherwise `None`.
+    pub fn rewrite_subtree(&self, path: &[usize]) -> Option<Instructions<S>> {
+        if self.streams != RandomStreams::PerBranch {
+            return None;
+        }
+
+        let keys = self.keys_of(&self.word);
+        self.rewrite_in(&self.word, path, Place::ROOT, &keys, 0)
+    }
+
+    // `first` is the place in `keys` of the first branch in `instrs`
+    fn rewrite_in(
+        &self,
+        instrs: &[Instruction<S>],
+        path: &[usize],
+        place: Place<S>,
+        keys: &[u64],
+        first: usize,
+    ) -> Option<Instructions<S>> {
+        match path.split_first() {
+            Some((&i, rest)) => match instrs.get(i)? {
+                Instruction::Branch(inner) => {
+                    let at = first + count_branches(&instrs[..i]);
+                    let mut place = place.branch(instrs, i);
+                    place.hash = *keys.get(at)?;
+                    self.rewrite_in(inner, rest, place, keys, at + 1)
+                }
+                Instruction::Symbol(_) | Instruction::Module(..) => None,
+            },
+            None => {
+                let mut rng = self.stream(place.hash);
+                let mut tracer = Tracer {
+                    keys: Some(keys.to_vec()),
+                    branch: first,
+                    ..Tracer::default()
+                };
+                let expand = &|_: &S| true;
+                Some(self.rewrite(instrs, place, &mut rng, None, expand, Some(&mut tracer)))
+            }
+        }
+    }
+
+    // the keys the system gave the branches of `word`, or their paths if it didn't
+    fn keys_of(&self, word: &[Instruction<S>]) -> Vec<u64> {
+        match &self.branch_keys {
+            Some(keys) if keys.len() == count_branches(word) => keys.clone(),
+            _ => {
+                let mut keys = vec![];
+                path_keys(word, ROOT, &mut keys);
+                keys
+            }
+        }
+    }
+
+    /// Rewrites the current word once, but only the symbols `expand` picks; the others stay as
+    /// they are, e.g. so that a finished trunk stops growing while the apices go on.
+    pub fn step_only(&mut self, expand: impl Fn(&S) -> bool) {
+        self.rewrite_word(None, &expand, false, None);
+    }
+
+    // the productions are only kept when `replaying`
+    fn rewrite_word(
+        &mut self,
+        queries: Option<&mut Queries>,
+        expand: &dyn Fn(&S) -> bool,
+        replaying: bool,
+        should_continue: Option<&mut dyn FnMut() -> bool>,
+    ) -> Vec<Production<S>> {
+        let word = std::mem::take(&mut self.word);
+        let mut rng = match self.streams {
+            RandomStreams::Shared => self.rng.clone(),
+            RandomStreams::PerBranch => self.stream(ROOT),
+        };
+
+        if self.births.is_none() && self.has_delays() {
+            self.births = Some(vec![self.generation; count_symbols(&word)]);
+        }
+        let symbols = count_symbols(&word);
+        self.deep_rewrites = match self.max_depth {
+            Some((_, further)) if further > 0 => match self.deep_rewrites.take() {
+                Some(rewrites) if rewrites.len() == symbols => Some(rewrites),
+                _ => Some(vec![0; symbols]),
+            },
+            _ => None,
+        };
+        let tracing = self.trace.is_some()
+            || self.births.is_some()
+            || self.stats.is_some()
+            || self.deep_rewrites.is_some();
+        let keyed = self.streams == RandomStreams::PerBranch;
+        let cancellable = should_continue.is_some();
+        let mut tracer = (tracing || replaying || keyed || cancellable).then(|| Tracer {
+            births: self.births.take(),
+            rewrites: self.deep_rewrites.take(),
+            generation: self.generation + 1,
+            productions: replaying.then(Vec::new),
+            keys: keyed.then(|| self.keys_of(&word)),
+            should_continue,
+            ..Tracer::default()
+        });
+        let rewritten = self.rewrite(
+            &word,
+            Place::ROOT,
+            &mut rng,
+            queries,
+            expand,
+            tracer.as_mut(),
+        );
+        if let Some(tracer) = tracer.take_if(|tracer| tracer.cancelled) {
+            self.word = word;
+            self.births = tracer.births;
+            self.deep_rewrites = tracer.rewrites;
+            return vec![];
+        }
+        self.word = rewritten;
+
+        if self.streams == RandomStreams::Shared {
+            self.rng = rng;
+        }
+        let mut productions = vec![];
+        self.branch_keys = None;
+        if let Some(tracer) = tracer {
+            if tracer.births.is_some() {
+                self.births = Some(tracer.born);
+            }
+            if tracer.rewrites.is_some() {
+                self.deep_rewrites = Some(tracer.counted);
+            }
+            if tracer.keys.is_some() {
+                self.branch_keys = Some(tracer.keyed);
+            }
+            productions = tracer.productions.unwrap_or_default();
+            let step = TraceStep {
+                generation: self.generation,
+                table: self
+                    .table_at(self.generation)
+                    .map(|i| self.tables[i].0.clone()),
+                applications: tracer.applications,
+            };
+            let rules = self.active_rules().len();
+            if let Some(stats) = &mut self.stats {
+                stats.record(step.table.as_deref(), rules, &step.applications);
+            }
+            if let Some(trace) = &mut self.trace {
+                trace.steps.push(step);
+            }
+        }
+        self.generation += 1;
+        productions
+    }
+
+    /// Records which rule rewrites which symbol from now on, or stops and drops the record.
+    pub fn set_tracing(&mut self, tracing: bool) {
+        self.trace = match tracing {
+            true => Some(self.trace.take().unwrap_or_default()),
+            false => None,
+        };
+    }
+
+    /// The rewrites since tracing was turned on, if it is.
+    pub fn trace(&self) -> Option<&DerivationTrace> {
+        self.trace.as_ref()
+    }
+
+    /// Counts how many times every rule fires from now on, or stops and drops the counts.
+    pub fn set_counting(&mut self, counting: bool) {
+        self.stats = match counting {
+            true => Some(
+                self.stats
+                    .take()
+                    .unwrap_or_else(|| DerivationStats::new(self.rules.len())),
+            ),
+            false => None,
+        };
+    }
+
+    /// How often the rules fired since counting was turned on, if it is.
+    pub fn derivation_stats(&self) -> Option<&DerivationStats> {
+        self.stats.as_ref()
+    }
+
+    /// Keeps the generation every symbol of the word was produced in from now on, for
+    /// `annotated`, or stops. Symbols already in the word count as produced in the current
+    /// generation, so it is best turned on before deriving. Systems with delayed rules keep
+    /// them all the same, from their first rewrite on.
+    pub fn set_annotating(&mut self, annotating: bool) {
+        self.births = match annotating || self.has_delays() {
+            true => Some(
+                self.births
+                    .take()
+                    .unwrap_or_else(|| vec![self.generation; count_symbols(&self.word)]),
+            ),
+            false => None,
+        };
+    }
+
+    /// Every symbol of the current word with its branch depth and the generation it was
+    /// produced in, the axiom's being 0, if annotating or rules are delayed; e.g. to color or
+    /// thin a plant by age.
+    pub fn annotated(&self) -> Option<Annotated<'_, S>> {
+        let births = self.births.as_ref()?;
+        Some(Annotated {
+            symbols: Flattened {
+                stack: vec![self.word.iter()],
+            },
+            births: births.iter(),
+        })
+    }
+
+    /// Drops branches as rules produce them from now on, with `dropout`'s probabilities and
+    /// depths counted from the successor, so a dense grammar grows a sparser, more natural tree.
+    /// Branches are dropped with the system's own random streams and seed, not `dropout`'s.
+    pub fn set_dropout(&mut self, dropout: Option<Pruning>) {
+        self.dropout = dropout;
+    }
+
+    pub fn dropout(&self) -> Option<&Pruning> {
+        self.dropout.as_ref()
+    }
+
+    fn stream(&self, path: u64) -> ChaCha12Rng {
+        ChaCha12Rng::seed_from_u64(mix(mix(self.seed, self.generation as u64), path))
+    }
+
+    fn rewrite(
+        &self,
+        instrs: &[Instruction<S>],
+        place: Place<S>,
+        rng: &mut ChaCha12Rng,
+        mut queries: Option<&mut Queries>,
+        expand: &dyn Fn(&S) -> bool,
+        mut tracer: Option<&mut Tracer<S>>,
+    ) -> Instructions<S> {
+        let mut rewritten = vec![];
+        let mut blocked = false;
+        // branches rules produced here so far, counted before dropout, for their keys
+        let mut produced = 0;
+        for (i, instr) in instrs.iter().enumerate() {
+            if tracer.as_deref_mut().is_some_and(Tracer::cancelled) {
+                break;
+            }
+            if blocked {
+                if let Some(queries) = queries.as_deref_mut() {
+                    queries.skip(instr);
+                }
+                if let Some(tracer) = tracer.as_deref_mut() {
+                    tracer.keep(count_symbols(std::slice::from_ref(instr)));
+                    tracer.keep_branches(count_branches(std::slice::from_ref(instr)));
+                }
+                rewritten.push(instr.clone());
+                continue;
+            }
+            let answered;
+            let instr = match queries.as_deref_mut().and_then(|q| q.respond(instr)) {
+                Some(answer) => {
+                    answered = answer;
+                    &answered
+                }
+                None => instr,
+            };
+
+            match instr {
+                _ if instr.is_cut() => {
+                    if let Some(tracer) = tracer.as_deref_mut() {
+                        tracer.position += count_symbols(&instrs[i..]);
+                        tracer.drop_branches(count_branches(&instrs[i..]));
+                    }
+                    break;
+                }
+                Instruction::Branch(inner) => {
+                    let mut place = place.branch(instrs, i);
+                    if let Some(key) = tracer.as_deref_mut().and_then(Tracer::enter_branch) {
+                        place.hash = key;
+                    }
+                    let queries = queries.as_deref_mut();
+                    let tracer = tracer.as_deref_mut();
+                    let inner = match self.streams {
+                        RandomStreams::Shared => {
+                            self.rewrite(inner, place, rng, queries, expand, tracer)
+                        }
+                        RandomStreams::PerBranch => {
+                            let mut rng = self.stream(place.hash);
+                            self.rewrite(inner, place, &mut rng, queries, expand, tracer)
+                        }
+                    };
+                    rewritten.push(Instruction::Branch(inner));
+                }
+                Instruction::Symbol(symbol) | Instruction::Module(symbol, _)
+                    if queries
+                        .as_deref_mut()
+                        .is_some_and(|queries| queries.blocked(symbol)) =>
+                {
+                    blocked = true;
+                    if let Some(tracer) = tracer.as_deref_mut() {
+                        tracer.keep(1);
+                    }
+                    rewritten.push(instr.clone());
+                }
+                Instruction::Symbol(symbol) | Instruction::Module(symbol, _)
+                    if !expand(symbol)
+                        || self.too_deep(&place, tracer.as_deref().map_or(0, Tracer::rewrites)) =>
+                {
+                    if let Some(tracer) = tracer.as_deref_mut() {
+                        tracer.keep(1);
+                    }
+                    rewritten.push(instr.clone())
+                }
+                Instruction::Symbol(_) | Instruction::Module(..) => {
+                    let age = tracer.as_deref().and_then(|tracer| {
+                        let births = tracer.births.as_ref()?;
+                        Some(self.generation - births[tracer.position])
+                    });
+                    let neighbors = Neighbors {
+                        before: &instrs[..i],
+                        after: &instrs[i + 1..],
+                        place,
+                        ignore: &self.ignore,
+                        age,
+                    };
+                    let (rule, successor) = instr.apply_traced(
+                        self.active_rules(),
+                        self.selection,
+                        Some(&neighbors),
+                        rng,
+                    );
+                    let branches = count_branches(&successor);
+                    let (successor, kept) = match &self.dropout {
+                        Some(dropout) => dropout.apply_marked(&successor, rng),
+                        None => (successor, vec![true; branches]),
+                    };
+                    if let Some(tracer) = tracer.as_deref_mut() {
+                        let generation = mix(place.hash, self.generation as u64);
+                        let keys = (produced..produced + branches)
+                            .zip(kept)
+                            .filter(|(_, kept)| *kept)
+                            .map(|(n, _)| mix(generation, n as u64));
+                        tracer.produce_branches(keys);
+                    }
+                    produced += branches;
+                    if let Some(tracer) = tracer.as_deref_mut() {
+                        match rule {
+                            Some(rule) => {
+                                let deep = self.max_depth.is_some_and(|(max, _)| place.depth > max);
+                                tracer.rewrite(rule, instr, &successor, deep)
+                            }
+                            None => tracer.keep(1),
+                        }
+                    }
+                    rewritten.extend(successor)
+                }
+            }
+        }
+        rewritten
+    }
+
+    pub fn rules(&self) -> &[Rule<S>] {
+        &self.rules
+    }
+
+    pub fn rules_mut(&mut self) -> &mut [Rule<S>] {
+        &mut self.rules
+    }
+
+    /// The symbols rules skip when matching their context, like the turtle's in `#ignore +-F;`.
+    pub fn ignored(&self) -> &[S] {
+        &self.ignore
+    }
+
+    pub fn set_ignored(&mut self, symbols: impl IntoIterator<Item = S>) {
+        self.ignore.clear();
+        for symbol in symbols {
+            if !self.ignore.contains(&symbol) {
+                self.ignore.push(symbol);
+            }
+        }
+    }
+
+    /// Puts `signal` into the current word before the symbol at `position`, counted like
+    /// `RuleApplication::position`, in that symbol's branch, or at the end of the word if
+    /// `position` is its number of symbols. Rules with a context then carry it on from the next
+    /// rewrite: one with the signal on its left moves it towards the tips, acropetally, and one
+    /// with it on its right back down to the base, basipetally. Symbols put in count as
+    /// produced in the current generation.
+    pub fn inject(&mut self, position: usize, signal: Instruction<S>) -> Result<(), LsysError> {
+        let symbols = count_symbols(&self.word);
+        if position > symbols {
+            return Err(LsysError::BeyondWord { position, symbols });
+        }
+        if let Some(births) = &mut self.births {
+            let produced = count_symbols(std::slice::from_ref(&signal));
+            births.splice(
+                position..position,
+                std::iter::repeat_n(self.generation, produced),
+            );
+        }
+        let mut signal = Some(signal);
+        insert_at(&mut self.word, &mut position.clone(), &mut signal);
+        self.word.extend(signal);
+        Ok(())
+    }
+
+    /// Rewrites symbols nested in more than `depth` branches only `further` more times from now
+    /// on, counting from when they got that deep, so the outer structure of a word goes on
+    /// being refined while deep twigs stop at the detail they have; or, with `None`, rewrites
+    /// every symbol again. Symbols already that deep count as not rewritten yet.
+    pub fn set_max_depth(&mut self, depth: Option<usize>, further: usize) {
+        self.max_depth = depth.map(|depth| (depth, further));
+        self.deep_rewrites = None;
+    }
+
+    /// The depth past which symbols are rewritten just so many more times, and how many.
+    pub fn max_depth(&self) -> Option<(usize, usize)> {
+        self.max_depth
+    }
+
+    fn too_deep(&self, place: &Place<S>, rewrites: usize) -> bool {
+        self.max_depth
+            .is_some_and(|(max, further)| place.depth > max && rewrites >= further)
+    }
+
+    /// Binds `symbol` to `system`, in place of any system it was bound to: `spliced` puts the
+    /// word `system` derives from its axiom in `depth` rewrites where the symbol is, so leaves,
+    /// flowers and trunks can each be grown by a grammar of their own. The symbol itself is
+    /// rewritten as usual; one without rules of its own stays in the word until it is spliced.
+    pub fn bind(&mut self, symbol: S, system: LSystem<S>, depth: usize) {
+        self.unbind(&symbol);
+        self.bindings.push((symbol, system, depth));
+    }
+
+    pub fn unbind(&mut self, symbol: &S) {
+        self.bindings.retain(|(bound, ..)| bound != symbol);
+    }
+
+    /// The current word with every bound symbol, and every module of one, replaced by its
+    /// system's word, which has the symbols bound in that system spliced in turn. Each system
+    /// is derived once, so every occurrence gets the same word; it is spliced in as it is, so a
+    /// symbol bound in a branch, like `[L]`, leaves the turtle where it was.
+    pub fn spliced(&self) -> Instructions<S> {
+        if self.bindings.is_empty() {
+            return self.word.clone();
+        }
+        let words: Vec<(&S, Instructions<S>)> = self
+            .bindings
+            .iter()
+            .map(|(symbol, system, depth)| {
+                let mut system = system.clone();
+                system.reset();
+                for _ in 0..*depth {
+                    system.step();
+                }
+                (symbol, system.spliced())
+            })
+            .collect();
+        splice(&self.word, &words)
+    }
+
+    /// The symbols declared never to be rewritten, like `+-` in `#const +-[];`, e.g. the ones
+    /// a turtle is to be given meanings for. Successors may have them, but a grammar with a
+    /// rule for one doesn't parse, and `validate` reports such rules.
+    pub fn constants(&self) -> &[S] {
+        &self.constants
+    }
+
+    pub fn set_constants(&mut self, symbols: impl IntoIterator<Item = S>) {
+        self.constants.clear();
+        for symbol in symbols {
+            if !self.constants.contains(&symbol) {
+                self.constants.push(symbol);
+            }
+        }
+    }
+
+    /// The turning angle in degrees an `angle 25.7;` line before the axiom declares, for
+    /// drawing the system without being told it again.
+    pub fn angle(&self) -> Option<f32> {
+        self.angle
+    }
+
+    pub fn set_angle(&mut self, degrees: Option<f32>) {
+        self.angle = degrees;
+    }
+
+    /// The length of a step a `step 4;` line before the axiom declares.
+    pub fn step_size(&self) -> Option<f32> {
+        self.step
+    }
+
+    pub fn set_step_size(&mut self, step: Option<f32>) {
+        self.step = step;
+    }
+
+    // contexts, ages and depths can't be known but in the whole word
+    fn needs_whole_word(&self) -> bool {
+        self.max_depth.is_some()
+            || self
+                .all_rules()
+                .any(|rule| rule.has_context() || rule.delay > 0)
+    }
+
+    fn has_delays(&self) -> bool {
+        self.all_rules().any(|rule| rule.delay > 0)
+    }
+
+    fn all_rules(&self) -> impl Iterator<Item = &Rule<S>> {
+        let tables = self.tables.iter().map(|(_, rules)| rules);
+        std::iter::once(&self.rules).chain(tables).flatten()
+    }
+
+    /// Adds a rule after the others; it rewrites from the next generation on.
+    pub fn add_rule(&mut self, rule: Rule<S>) {
+        self.rules.push(rule);
+    }
+
+    /// Removes the rule at `index`, or returns `None` if there is none.
+    pub fn remove_rule(&mut self, index: usize) -> Option<Rule<S>> {
+        (index < self.rules.len()).then(|| self.rules.remove(index))
+    }
+
+    pub fn axiom(&self) -> &Instructions<S> {
+        &self.axiom
+    }
+
+    /// Adds a table of rules to rewrite with instead of the grammar's own, replacing the table
+    /// called `name` if there is one.
+    pub fn add_table(&mut self, name: impl Into<String>, rules: Vec<Rule<S>>) {
+        let name = name.into();
+        match self.tables.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, table)) => *table = rules,
+            None => self.tables.push((name, rules)),
+        }
+    }
+
+    fn table_index(&self, name: &str) -> Result<usize, LsysError> {
+        self.tables
+            .iter()
+            .position(|(n, _)| n == name)
+            .ok_or_else(|| LsysError::NoTable(name.to_string()))
+    }
+
+    /// Rewrites with the table called `name` from now on, or with the grammar's own rules for
+    /// `None`. Generations the schedule covers use its table instead.
+    pub fn set_table(&mut self, name: Option<&str>) -> Result<(), LsysError> {
+        self.table = name.map(|name| self.table_index(name)).transpose()?;
+        Ok(())
+    }
+
+    /// The table set with `set_table`, if any.
+    pub fn table(&self) -> Option<&str> {
+        self.table.map(|i| &self.tables[i].0[..])
+    }
+
+    /// Every table in `schedule` must have been added already.
+    pub fn set_schedule(&mut self, schedule: &Schedule) -> Result<(), LsysError> {
+        self.schedule = schedule
+            .0
+            .iter()
+            .map(|(generations, name)| Ok((generations.clone(), self.table_index(name)?)))
+            .collect::<Result<_, LsysError>>()?;
+        Ok(())
+    }
+
+    // the rules the current word is rewritten with
+    fn active_rules(&self) -> &[Rule<S>] {
+        self.rules_at(self.generation)
+    }
+
+    fn table_at(&self, generation: usize) -> Option<usize> {
+        let scheduled = self
+            .schedule
+            .iter()
+            .find(|(generations, _)| generations.contains(&generation))
+            .map(|&(_, table)| table);
+        scheduled.or(self.table)
+    }
+
+    fn rules_at(&self, generation: usize) -> &[Rule<S>] {
+        match self.table_at(generation) {
+            Some(table) => &self.tables[table].1,
+            None => &self.rules,
+        }
+    }
+
+    pub fn selection(&self) -> RuleSelection {
+        self.selection
+    }
+
+    pub fn set_selection(&mut self, selection: RuleSelection) {
+        self.selection = selection;
+    }
+
+    /// The rule that rewrites `instr` in the next generation, or `None` if it is left as it is
+    /// or the rule is picked at random. A symbol on its own has no neighbours and no age, so
+    /// rules with contexts or delays are never the one; `production_at` looks at the word.
+    pub fn production(&self, instr: &Instruction<S>) -> Option<&Rule<S>> {
+        if self.selection.is_random() {
+            return None;
+        }
+        let mut rng = ChaCha12Rng::seed_from_u64(0);
+        let rules = self.rules_at(self.generation);
+        self.selection
+            .select(rules, instr, None, &mut rng)
+            .map(|(_, rule)| rule)
+    }
+
+    /// Like `production`, for the symbol at `path` in the current word, with its contexts, age
+    /// and depth: `[2, 0]` is the first symbol of the branch that is the word's third
+    /// instruction. `None` too if there is no symbol there, or a `%` cuts it off.
+    pub fn production_at(&self, path: &[usize]) -> Option<&Rule<S>> {
+        if self.selection.is_random() {
+            return None;
+        }
+        self.production_in(&self.word, path, Place::ROOT, 0)
+    }
+
+    // `position` is of the first symbol of `instrs` in the word
+    fn production_in(
+        &self,
+        instrs: &[Instruction<S>],
+        path: &[usize],
+        place: Place<S>,
+        position: usize,
+    ) -> Option<&Rule<S>> {
+        let (&i, rest) = path.split_first()?;
+        if instrs.get(..i)?.iter().any(Instruction::is_cut) {
+            return None;
+        }
+        let position = position + count_symbols(&instrs[..i]);
+        match (instrs.get(i)?, rest) {
+            (Instruction::Branch(inner), _) => {
+                self.production_in(inner, rest, place.branch(instrs, i), position)
+            }
+            (instr @ (Instruction::Symbol(_) | Instruction::Module(..)), []) => {
+                let rewrites = self.deep_rewrites.as_ref().map_or(0, |r| r[position]);
+                if instr.is_cut() || self.too_deep(&place, rewrites) {
+                    return None;
+                }
+                let neighbors = Neighbors {
+                    before: &instrs[..i],
+                    after: &instrs[i + 1..],
+                    place,
+                    ignore: &self.ignore,
+                    age: (self.births.as_ref()).map(|births| self.generation - births[position]),
+                };
+                let mut rng = ChaCha12Rng::seed_from_u64(0);
+                self.selection
+                    .select(self.active_rules(), instr, Some(&neighbors), &mut rng)
+                    .map(|(_, rule)| rule)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn streams(&self) -> RandomStreams {
+        self.streams
+    }
+
+    pub fn set_streams(&mut self, streams: RandomStreams) {
+        self.streams = streams;
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Seeds the random rule selection; the system starts over from its axiom. Rules are picked
+    /// with ChaCha, so a seed derives the same words on every platform and version.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.reset();
+    }
+
+    /// Seeds the system from `rng`, e.g. to derive a batch of variants from one seeded
+    /// generator.
+    pub fn seed_from(&mut self, rng: &mut impl Rng) {
+        self.set_seed(rng.gen());
+    }
+
+    /// Starts over from the axiom, with the random rule selection back at its seed.
+    pub fn reset(&mut self) {
+        self.word = self.axiom.clone();
+        self.branch_keys = None;
+        self.deep_rewrites = None;
+        self.generation = 0;
+        if let Some(births) = &mut self.births {
+            *births = vec![0; count_symbols(&self.axiom)];
+        }
+        self.rng = ChaCha12Rng::seed_from_u64(self.seed);
+        if let Some(trace) = &mut self.trace {
+            trace.steps.clear();
+        }
+        if let Some(stats) = &mut self.stats {
+            *stats = DerivationStats::new(self.rules.len());
+        }
+    }
+}
+
+impl LSystem {
+    /// Never panics, whatever the input; anything that isn't a grammar is an error. The same
+    /// as `str::parse`.
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(input: &str) -> Result<Self, LsysError> {
+        Self::parse(input, true)
+    }
+
+    /// Like `from_str`, but stops quietly at the first statement after the axiom it can't
+    /// read, keeping the rules before it, the way grammars were read before `from_str` got
+    /// strict.
+    pub fn from_str_lenient(input: &str) -> Result<Self, LsysError> {
+        Self::parse(input, false)
+    }
+
+    fn parse(input: &str, strict: bool) -> Result<Self, LsysError> {
+        let input = &*strip_comments(input);
+        check_nesting(input)?;
+        let (_, lsystem) = lsystem(input, strict).map_err(|e| LsysError::expected(input, e))?;
+        if lsystem.axiom.is_empty() {
+            return Err(LsysError::EmptyAxiom);
+        }
+        lsystem.check_weights()?;
+        let mut rewritten = lsystem.rules.iter().filter_map(|rule| rule.from.symbol());
+        if let Some(symbol) = rewritten.find(|s| lsystem.constants.contains(s)) {
+            return Err(LsysError::RewritesConstant(symbol.clone()));
+        }
+        Ok(lsystem)
+    }
+
+    /// Reads and parses the grammar in the file at `path`.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, LoadError> {
+        let path = path.as_ref();
+        let input = std::fs::read_to_string(path).map_err(|error| LoadError::Io {
+            path: path.to_owned(),
+            error,
+        })?;
+        Self::from_str(&input).map_err(|error| LoadError::Parse {
+            path: path.to_owned(),
+            location: error.line_column(&input),
+            error,
+        })
+    }
+
+    /// The word `generations` rewrites from now, expanded lazily as it is read, e.g. by
+    /// `Turtle::draw_events`, for words too large to hold in memory.
+    pub fn expand_lazily(&self, generations: usize) -> Expansion<'_> {
+        let (instructions, depth) = match self.needs_whole_word() {
+            true => (self.expand_memoized(generations), 0),
+            false => (self.word.clone(), generations),
+        };
+        Expansion {
+            lsystem: self,
+            generations,
+            rng: self.rng.clone(),
+            stack: vec![Frame {
+                instructions,
+                next: 0,
+                depth,
+                branch: false,
+            }],
+        }
+    }
+
+    /// Draws the word `n` rewrites from now with `turtle`, feeding it every symbol as soon as
+    /// it is derived so neither that word nor the ones before it are ever built. It is derived
+    /// like `expand_lazily` derives it, so systems with contexts are rewritten in full first.
+    pub fn interpret_nth<G: Graphics<R>, R>(
+        &self,
+        n: usize,
+        turtle: Turtle<'_, '_, '_, '_, '_>,
+        graphics: &mut G,
+    ) -> Result<(), R> {
+        turtle.draw_events(graphics, self.expand_lazily(n))
+    }
+
+    /// Like `interpret_nth`, but asks `should_continue` before every symbol and stops deriving
+    /// and drawing as soon as it says no. Whether the whole word was drawn.
+    pub fn interpret_nth_while<G: Graphics<R>, R>(
+        &self,
+        n: usize,
+        turtle: Turtle<'_, '_, '_, '_, '_>,
+        graphics: &mut G,
+        mut should_continue: impl FnMut() -> bool,
+    ) -> Result<bool, R> {
+        let mut stopped = false;
+        let events = self.expand_lazily(n).take_while(|_| {
+            stopped = !should_continue();
+            !stopped
+        });
+        turtle.draw_events(graphics, events)?;
+        Ok(!stopped)
+    }
+
+    /// The word `generations` rewrites from now, like that many calls to `next` but expanding
+    /// every symbol only once per generation: deep words of a few symbols repeated many times,
+    /// as most are, take a fraction of the work. Rules picked at random or by context can't be
+    /// reused, so systems with those are rewritten as usual.
+    pub fn expand_memoized(&self, generations: usize) -> Instructions {
+        if self.selection.is_random() || self.dropout.is_some() || self.needs_whole_word() {
+            let mut lsystem = self.clone();
+            for _ in 0..generations {
+                lsystem.step();
+            }
+            return lsystem.word;
+        }
+
+        let mut memo = Memo::new();
+        self.expand_sequence(&self.word, generations, generations, &mut memo)
+            .instructions
+    }
+
+    fn expand_sequence(
+        &self,
+        instrs: &[Instruction],
+        depth: usize,
+        generations: usize,
+        memo: &mut Memo,
+    ) -> Expanded {
+        let mut expanded = Expanded::default();
+        for instr in instrs {
+            match instr {
+                // the next rewrite drops the rest of the branch, and what it would turn into
+                Instruction::Symbol(s) if *s == '%' && depth > 0 => {
+                    expanded.cut = true;
+                    break;
+                }
+                Instruction::Branch(inner) => {
+                    let inner = self.expand_sequence(inner, depth, generations, memo);
+                    expanded
+                        .instructions
+                        .push(Instruction::Branch(inner.instructions));
+                }
+                _ if depth == 0 => expanded.instructions.push(instr.clone()),
+                _ => {
+                    let symbol = self.expand_symbol(instr, depth, generations, memo);
+                    expanded
+                        .instructions
+                        .extend_from_slice(&symbol.instructions);
+                    if symbol.cut {
+                        expanded.cut = true;
+                        break;
+                    }
+                }
+            }
+        }
+        expanded
+    }
+
+    fn expand_symbol(
+        &self,
+        instr: &Instruction,
+        depth: usize,
+        generations: usize,
+        memo: &mut Memo,
+    ) -> Rc<Expanded> {
+        let key = (instr.clone(), depth);
+        if let Some(expanded) = memo.get(&key) {
+            return expanded.clone();
+        }
+        // selections without randomness never draw from it
+        let mut rng = self.rng.clone();
+        let rules = self.rules_at(self.generation + generations - depth);
+        let successor = instr.apply(rules, self.selection, &mut rng);
+        let expanded = Rc::new(self.expand_sequence(&successor, depth - 1, generations, memo));
+        memo.insert(key, expanded.clone());
+        expanded
+    }
+
+    /// The number of symbols in the word `n` rewrites from now, found from how many of each
+    /// symbol every rule produces instead of by rewriting, so it takes no longer for a word of
+    /// billions of symbols. `None` if that can't be known without rewriting, because rules are
+    /// picked at random, are parametric, guarded or have a context, or cut branches, or if the
+    /// count overflows.
+    pub fn predicted_length(&self, n: usize) -> Option<usize> {
+        if self.selection.is_random() || self.dropout.is_some() || self.needs_whole_word() {
+            return None;
+        }
+
+        let mut counts = HashMap::new();
+        tally(&self.word, 1, &mut counts)?;
+        // selections without randomness never draw from it
+        let mut rng = self.rng.clone();
+        for step in 0..n {
+            let rules = self.rules_at(self.generation + step);
+            if rules
+                .iter()
+                .any(|rule| !rule.params.is_empty() || rule.condition.is_some())
+            {
+                return None;
+            }
+
+            let mut next = HashMap::new();
+            for (instr, count) in counts {
+                if matches!(&instr, Instruction::Symbol(s) if *s == '%') {
+                    return None;
+                }
+                let successor = instr.apply(rules, self.selection, &mut rng);
+                tally(&successor, count, &mut next)?;
+            }
+            counts = next;
+        }
+        counts
+            .values()
+            .try_fold(0usize, |total, &count| total.checked_add(count))
+    }
+
+    /// How fast the word grows on average, and about how large it is `generations` rewrites
+    /// from now, from how many of each symbol the rules produce, weighted by how likely each is
+    /// picked. Guards and contexts are taken to always hold, so for grammars with those it is
+    /// only a guess.
+    pub fn growth(&self, generations: usize) -> Growth {
+        const STEPS: usize = 256;
+        const AVERAGED: usize = 32;
+
+        let mut counts = HashMap::new();
+        let mut branches = 0.0;
+        expect(&self.word, 1.0, &mut counts, &mut branches);
+
+        // the total of normalized counts after each step, averaged over the last steps so
+        // periodic grammars settle too
+        let mut normalized = counts.clone();
+        let mut logs = 0.0;
+        for step in 0..STEPS {
+            let mut ignored = 0.0;
+            normalized = self.expect_step(self.active_rules(), &normalized, &mut ignored);
+            let total: f64 = normalized.values().sum();
+            if total == 0.0 || !total.is_finite() {
+                logs = if total == 0.0 {
+                    f64::NEG_INFINITY
+                } else {
+                    f64::INFINITY
+                };
+                break;
+            }
+            normalized.values_mut().for_each(|count| *count /= total);
+            if step >= STEPS - AVERAGED {
+                logs += total.ln();
+            }
+        }
+
+        for step in 0..generations {
+            let rules = self.rules_at(self.generation + step);
+            counts = self.expect_step(rules, &counts, &mut branches);
+        }
+        let instructions = counts.values().sum::<f64>() + branches;
+        Growth {
+            factor: (logs / AVERAGED as f64).exp(),
+            symbols: instructions,
+            bytes: instructions * std::mem::size_of::<Instruction>() as f64,
+        }
+    }
+
+    /// Fails if the word grows more than `max_factor` times a generation, with how much memory
+    /// it would take `generations` rewrites from now; otherwise the growth.
+    pub fn check_growth(&self, generations: usize, max_factor: f64) -> Result<Growth, LsysError> {
+        let growth = self.growth(generations);
+        if growth.factor > max_factor {
+            return Err(LsysError::Growth(format!(
+                "the word grows about {:.2} times a generation, to some {:.0} MB in {} rewrites",
+                growth.factor,
+                growth.bytes / 1e6,
+                generations
+            )));
+        }
+        Ok(growth)
+    }
+
+    // the expected counts after rewriting `counts` once, and the branches the rewrite adds
+    fn expect_step(
+        &self,
+        rules: &[Rule],
+        counts: &HashMap<Symbol, f64>,
+        branches: &mut f64,
+    ) -> HashMap<Symbol, f64> {
+        let mut next = HashMap::new();
+        for (symbol, &count) in counts {
+            let candidates: Vec<&Rule> = rules
+                .iter()
+                .filter(|rule| rule.from.symbol() == Some(symbol))
+                .collect();
+            let picked: Vec<(&Rule, f64)> = match self.selection {
+                _ if candidates.is_empty() => {
+                    *next.entry(symbol.clone()).or_insert(0.0) += count;
+                    continue;
+                }
+                RuleSelection::First => vec![(candidates[0], 1.0)],
+                RuleSelection::Last => vec![(candidates[candidates.len() - 1], 1.0)],
+                RuleSelection::Priority => {
+                    let top = candidates.iter().map(|rule| rule.priority).max();
+                    let first = candidates.iter().find(|rule| Some(rule.priority) == top);
+                    vec![(first.unwrap(), 1.0)]
+                }
+                RuleSelection::Uniform => candidates.iter().map(|&rule| (rule, 1.0)).collect(),
+                RuleSelection::Weighted => {
+                    let weighted: Vec<_> = candidates
+                        .iter()
+                        .filter(|rule| rule.weight > 0.0 && rule.weight.is_finite())
+                        .map(|&rule| (rule, rule.weight as f64))
+                        .collect();
+                    match weighted.is_empty() {
+                        true => vec![(candidates[0], 1.0)],
+                        false => weighted,
+                    }
+                }
+            };
+            let total: f64 = picked.iter().map(|(_, weight)| weight).sum();
+            for (rule, weight) in picked {
+                expect(&rule.to, count * weight / total, &mut next, branches);
+            }
+        }
+        next
+    }
+
+    /// Rewrites the current word once, leaving the rest of every branch after a blocked query of
+    /// `env` as it is, so growth into occupied space stops, and giving the symbols `env`
+    /// responds to their responses as values. `env` must have been filled by drawing the
+    /// current word with `Turtle::draw_in`.
+    pub fn step_in(&mut self, env: &Environment) {
+        let mut queries = Queries {
+            env,
+            answers: env.queries().iter(),
+            responses: env.responses().iter(),
+        };
+        self.rewrite_word(Some(&mut queries), &|_| true, false, None);
+    }
+
+    /// Replaces the axiom; the system starts over from it.
+    pub fn set_axiom(&mut self, axiom: impl Into<Word>) {
+        self.axiom = axiom.into().into_instructions();
+        self.reset();
+    }
+
+    /// Fails naming the first symbol with several unguarded rules of the same arity, for
+    /// grammars meant to have one rule per symbol whatever the selection.
+    pub fn check_unambiguous(&self) -> Result<(), LsysError> {
+        for table in std::iter::once(&self.rules).chain(self.tables.iter().map(|(_, t)| t)) {
+            let unguarded: Vec<_> = table
+                .iter()
+                .filter(|r| r.condition.is_none() && !r.has_context())
+                .collect();
+            for (i, rule) in unguarded.iter().enumerate() {
+                let duplicate = unguarded[..i]
+                    .iter()
+                    .any(|r| r.from == rule.from && r.params.len() == rule.params.len());
+                if duplicate {
+                    return Err(LsysError::Ambiguous(rule.from.to_string()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The successor of every symbol with a rule, each chosen once. Random selections are
+    /// seeded like the system's own. Parametric rules are left out.
+    pub(crate) fn successors(&self) -> HashMap<Symbol, Instructions> {
+        let mut rng = ChaCha12Rng::seed_from_u64(self.seed);
+        let mut successors = HashMap::new();
+        let rules = self.active_rules();
+        for rule in rules.iter().filter(|rule| rule.params.is_empty()) {
+            if let Instruction::Symbol(symbol) = &rule.from {
+                if !successors.contains_key(symbol) {
+                    successors.insert(
+                        symbol.clone(),
+                        rule.from.apply(rules, self.selection, &mut rng),
+                    );
+                }
+            }
+        }
+        successors
+    }
+
+    /// Where the expansion is, to `resume` from later.
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            generation: self.generation,
+            word: Word::from(self.word.clone()),
+            rng: self.rng.clone(),
+            births: self.births.clone(),
+            deep_rewrites: self.deep_rewrites.clone(),
+        }
+    }
+
+    /// Carries on from `checkpoint`, which must have been taken from a system with the same rules,
+    /// selection, seed and maximum depth. With per branch streams, the word's branches are keyed
+    /// by their path again from here. Symbols the checkpoint has no ages for count as born at its
+    /// generation.
+    pub fn resume(&mut self, checkpoint: Checkpoint) {
+        self.generation = checkpoint.generation;
+        self.word = checkpoint.word.into_instructions();
+        self.branch_keys = None;
+        self.deep_rewrites = checkpoint.deep_rewrites;
+        let symbols = count_symbols(&self.word);
+        self.births = match checkpoint.births {
+            Some(births) if self.births.is_some() || self.has_delays() => Some(births),
+            _ => self.births.as_ref().map(|_| vec![self.generation; symbols]),
+        };
+        self.rng = checkpoint.rng;
+    }
+
+    /// Renames `old` to `new` in the axiom, the current word and every rule.
+    pub fn rename_symbol(&mut self, old: impl Into<Symbol>, new: impl Into<Symbol>) {
+        self.rename_symbols(&HashMap::from([(old.into(), new.into())]));
+    }
+
+    /// Renames every symbol in `aliases` at once, so two symbols can trade names.
+    pub fn rename_symbols(&mut self, aliases: &HashMap<Symbol, Symbol>) {
+        fn rename_all<'s>(
+            symbols: impl IntoIterator<Item = &'s mut Symbol>,
+            aliases: &HashMap<Symbol, Symbol>,
+        ) {
+            for s in symbols {
+                if let Some(new) = aliases.get(s) {
+                    *s = new.clone();
+                }
+            }
+        }
+
+        fn rename(instrs: &mut [Instruction], aliases: &HashMap<Symbol, Symbol>) {
+            for instr in instrs {
+                match instr {
+                    Instruction::Symbol(s) | Instruction::Module(s, _) => {
+                        if let Some(new) = aliases.get(s) {
+                            *s = new.clone();
+                        }
+                    }
+                    Instruction::Branch(inner) => rename(inner, aliases),
+                }
+            }
+        }
+
+        fn rename_template(templates: &mut [Template], aliases: &HashMap<Symbol, Symbol>) {
+            for template in templates {
+                match template {
+                    Template::Symbol(s) | Template::Module(s, _) => {
+                        if let Some(new) = aliases.get(s) {
+                            *s = new.clone();
+                        }
+                    }
+                    Template::Branch(inner) => rename_template(inner, aliases),
+                }
+            }
+        }
+
+        rename(&mut self.axiom, aliases);
+        rename(&mut self.word, aliases);
+        rename_all(&mut self.ignore, aliases);
+        rename_all(&mut self.constants, aliases);
+        let tables = self.tables.iter_mut().map(|(_, rules)| rules);
+        for rule in std::iter::once(&mut self.rules).chain(tables).flatten() {
+            rename(std::slice::from_mut(&mut rule.from), aliases);
+            rename(&mut rule.to, aliases);
+            rename_all(rule.left.iter_mut().chain(&mut rule.right), aliases);
+            if let Some(template) = &mut rule.template {
+                rename_template(template, aliases);
+            }
+        }
+    }
+
+    /// This system with the rules of `other` added after its own, e.g. to grow leaves where a
+    /// trunk grammar leaves an `L`: symbols only `other` rewrites pick up its rules. Symbols
+    /// both rewrite are renamed in `other` first, to letters neither uses, so each keeps its
+    /// own rules. Tables of the same name are merged; `other`'s axiom is left out.
+    pub fn compose(&self, other: &LSystem) -> LSystem {
+        let ours = self.alphabet();
+        let theirs = other.alphabet();
+        let mut used: BTreeSet<_> = ours.terminals.iter().chain(&ours.non_terminals).collect();
+        used.extend(theirs.terminals.iter().chain(&theirs.non_terminals));
+        let mut fresh = ('A'..='Z')
+            .chain('a'..='z')
+            .chain('\u{100}'..)
+            .filter(|c| c.is_alphabetic())
+            .map(Symbol::from)
+            .filter(|s| !used.contains(s));
+        let aliases: HashMap<_, _> = ours
+            .non_terminals
+            .intersection(&theirs.non_terminals)
+            .map(|s| (s.clone(), fresh.next().unwrap()))
+            .collect();
+
+        let mut other = other.clone();
+        other.rename_symbols(&aliases);
+        let mut composed = self.clone();
+        composed.reset();
+        composed.rules.extend(other.rules);
+        for (name, rules) in other.tables {
+            match composed.tables.iter_mut().find(|(n, _)| *n == name) {
+                Some((_, table)) => table.extend(rules),
+                None => composed.tables.push((name, rules)),
+            }
+        }
+        composed
+    }
+
+    /// Every symbol in the axiom and the rules of every table.
+    pub fn alphabet(&self) -> Alphabet {
+        let mut symbols: BTreeSet<_> = symbols_in(&self.axiom).cloned().collect();
+        let mut non_terminals = BTreeSet::new();
+        let tables = self.tables.iter().map(|(_, rules)| rules);
+        for rule in std::iter::once(&self.rules).chain(tables).flatten() {
+            non_terminals.extend(rule.from.symbol().cloned());
+            symbols.extend(symbols_in(&rule.to).cloned());
+        }
+        Alphabet {
+            terminals: symbols.difference(&non_terminals).cloned().collect(),
+            non_terminals,
+        }
+    }
+
+    /// Looks for the usual mistakes in a grammar, the errors first. Symbols the turtle gives a
+    /// meaning of its own, `+`, `-`, `'` and the cut `%`, are never undefined.
+    pub fn validate(&self) -> Vec<Issue> {
+        let mut issues = vec![];
+        if self.axiom.is_empty() {
+            issues.push(Issue::EmptyAxiom);
+        }
+
+        let axiom: BTreeSet<_> = symbols_in(&self.axiom).collect();
+        let tables = self.tables.iter().map(|(_, rules)| rules);
+        let produced: BTreeSet<_> = std::iter::once(&self.rules)
+            .chain(tables)
+            .flatten()
+            .flat_map(|rule| symbols_in(&rule.to))
+            .collect();
+        let special = ['+', '-', '\'', '%'];
+        for symbol in self.alphabet().terminals {
+            if !axiom.contains(&symbol)
+                && !special.iter().any(|&c| symbol == c)
+                && !self.constants.contains(&symbol)
+            {
+                issues.push(Issue::Undefined(symbol));
+            }
+        }
+
+        let tables = self.tables.iter().map(|(name, rules)| (Some(name), rules));
+        for (table, rules) in std::iter::once((None, &self.rules)).chain(tables) {
+            for (i, rule) in rules.iter().enumerate() {
+                let at = || RuleRef {
+                    table: table.cloned(),
+                    rule: i,
+                };
+                let reached = |from| axiom.contains(&from) || produced.contains(&from);
+                if !rule.from.symbol().is_some_and(reached) {
+                    issues.push(Issue::Unreachable(at()));
+                }
+                if rule.to.is_empty() {
+                    issues.push(Issue::EmptyProduction(at()));
+                }
+                if rule
+                    .from
+                    .symbol()
+                    .is_some_and(|s| self.constants.contains(s))
+                {
+                    issues.push(Issue::RewritesConstant(at()));
+                }
+            }
+        }
+        issues
+    }
+
+    /// With weighted selection, makes sure every rule could be picked: each weight must be a
+    /// positive number, and the weights of rules that compete for a symbol, or for modules of it
+    /// with as many parameters, with the same contexts and condition, must not add up to more
+    /// than 1 when they are all below 1 and so read as probabilities. Probabilities adding up to
+    /// less are picked in proportion, as `normalize_weights` would scale them. Grammars are
+    /// checked as they are parsed.
+    pub fn check_weights(&self) -> Result<(), LsysError> {
+        if self.selection != RuleSelection::Weighted {
+            return Ok(());
+        }
+        let tables = self.tables.iter().map(|(_, rules)| rules);
+        for rules in std::iter::once(&self.rules).chain(tables) {
+            for group in competing(rules) {
+                let rule = &rules[group[0]];
+                let symbol = rule.from.symbol().map_or(String::new(), Symbol::to_string);
+                if let Some(bad) = group
+                    .iter()
+                    .map(|&i| rules[i].weight)
+                    .find(|w| !(*w > 0.0 && w.is_finite()))
+                {
+                    return Err(LsysError::Weights(format!(
+                        "a rule for '{}' has weight {}, so it is never picked",
+                        symbol, bad
+                    )));
+                }
+                let weights = group.iter().map(|&i| rules[i].weight);
+                let sum: f32 = weights.clone().sum();
+                if weights.clone().all(|w| w < 1.0) && sum > 1.0 + 1e-4 {
+                    return Err(LsysError::Weights(format!(
+                        "the rules for '{}' have probabilities adding up to {}, more than 1",
+                        symbol, sum
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Scales the weights of rules that compete for a symbol so they add up to 1, leaving the
+    /// chances they are picked with as they are; rules with a weight that is never picked are
+    /// left out of the sum.
+    pub fn normalize_weights(&mut self) {
+        let tables = self.tables.iter_mut().map(|(_, rules)| rules);
+        for rules in std::iter::once(&mut self.rules).chain(tables) {
+            for group in competing(rules) {
+                let picked = |w: &f32| *w > 0.0 && w.is_finite();
+                let sum: f32 = group.iter().map(|&i| rules[i].weight).filter(picked).sum();
+                for &i in &group {
+                    if picked(&rules[i].weight) {
+                        rules[i].weight /= sum;
+                    }
+                }
+            }
+        }
+    }
+
+    /// A smaller system with the same rules in effect: rules that never apply, because no
+    /// word ever has their symbol or another rule always wins over them, are dropped, and so
+    /// are rules that rewrite a symbol to itself when rules are picked without chance. A symbol
+    /// that only one successor produces, with a single plain rule of its own, is replaced by
+    /// that rule's successor, unless `turtle` does something with it, or contexts mention or
+    /// skip it. This changes the generation numbering: what it grows into arrives a generation
+    /// sooner, so the nth word of the result is not the nth word of the original. Parametric
+    /// rules are kept as they are. With rule tables nothing is inlined, and only the grammar's
+    /// own rules are dropped; the tables are kept whole. The result starts from its axiom.
+    pub fn simplified(&self, turtle: &TurtleConfig) -> LSystem {
+        let mut simple = self.clone();
+        simple.reset();
+        let rules = std::mem::take(&mut simple.rules);
+
+        let plain = |rule: &Rule| {
+            rule.condition.is_none()
+                && rule.template.is_none()
+                && !rule.has_context()
+                && rule.delay == 0
+        };
+        let shadows = |a: (usize, &Rule), b: (usize, &Rule)| {
+            plain(a.1)
+                && a.1.from == b.1.from
+                && a.1.params.len() == b.1.params.len()
+                && match self.selection {
+                    RuleSelection::First => a.0 < b.0,
+                    RuleSelection::Last => a.0 > b.0,
+                    RuleSelection::Priority => (a.1.priority, b.0) > (b.1.priority, a.0),
+                    RuleSelection::Uniform | RuleSelection::Weighted => false,
+                }
+        };
+        let identity =
+            |rule: &Rule| plain(rule) && rule.params.is_empty() && rule.to == [rule.from.clone()];
+        simple.rules = rules
+            .iter()
+            .enumerate()
+            .filter(|&b| !rules.iter().enumerate().any(|a| shadows(a, b)))
+            .filter(|(_, rule)| self.selection.is_random() || !identity(rule))
+            .map(|(_, rule)| rule.clone())
+            .collect();
+
+        if simple.tables.is_empty() {
+            while simple.inline_once(turtle) {}
+        }
+
+        let reachable = simple.reachable();
+        simple
+            .rules
+            .retain(|rule| rule.from.symbol().is_some_and(|s| reachable.contains(s)));
+        simple
+    }
+
+    // replaces the one occurrence of a single-use symbol by its rule's successor
+    fn inline_once(&mut self, turtle: &TurtleConfig) -> bool {
+        let occurrences =
+            |instrs: &[Instruction], symbol| symbols_in(instrs).filter(|&s| s == symbol).count();
+
+        for (i, rule) in self.rules.iter().enumerate() {
+            let symbol = match &rule.from {
+                Instruction::Symbol(symbol) => symbol,
+                _ => continue,
+            };
+            let single_rule = self
+                .rules
+                .iter()
+                .filter(|r| r.from.symbol() == Some(symbol));
+            let seen = turtle.acts_on(symbol)
+                || self.ignore.contains(symbol)
+                || self
+                    .rules
+                    .iter()
+                    .any(|r| r.left.contains(symbol) || r.right.contains(symbol));
+            if single_rule.count() != 1
+                || seen
+                || rule.condition.is_some()
+                || rule.has_context()
+                || rule.delay > 0
+                || occurrences(&self.axiom, symbol) > 0
+                || occurrences(&rule.to, symbol) > 0
+            {
+                continue;
+            }
+            let uses: Vec<_> = (0..self.rules.len())
+                .filter(|&j| occurrences(&self.rules[j].to, symbol) > 0)
+                .collect();
+            let user = match uses[..] {
+                [j] if occurrences(&self.rules[j].to, symbol) == 1 => j,
+                _ => continue,
+            };
+            if self.rules[user].template.is_some() {
+                continue;
+            }
+
+            let spliced = splice(&self.rules[user].to, &[(symbol, rule.to.clone())]);
+            self.rules[user].to = spliced;
+            self.rules.remove(i);
+            return true;
+        }
+        false
+    }
+
+    // the symbols some word derived from the axiom can have, with any table
+    fn reachable(&self) -> BTreeSet<Symbol> {
+        let mut pending: Vec<_> = symbols_in(&self.axiom).cloned().collect();
+        let mut reachable = BTreeSet::new();
+        let tables = self.tables.iter().map(|(_, rules)| rules);
+        let all: Vec<&Rule> = std::iter::once(&self.rules)
+            .chain(tables)
+            .flatten()
+            .collect();
+        while let Some(symbol) = pending.pop() {
+            if !reachable.insert(symbol.clone()) {
+                continue;
+            }
+            for rule in all.iter().filter(|r| r.from.symbol() == Some(&symbol)) {
+                pending.extend(symbols_in(&rule.to).cloned());
+            }
+        }
+        reachable
+    }
+
+    // the symbols mutations draw from, in a fixed order
+    fn edit_symbols(&self) -> Vec<Symbol> {
+        let mut symbols: Vec<Symbol> = vec!['+'.into(), '-'.into()];
+        let rules = self.rules.iter();
+        let words = rules.flat_map(|rule| [std::slice::from_ref(&rule.from), &rule.to[..]]);
+        for s in std::iter::once(&self.axiom[..])
+            .chain(words)
+            .flat_map(symbols_in)
+        {
+            if !symbols.contains(s) {
+                symbols.push(s.clone());
+            }
+        }
+        symbols
+    }
+
+    /// Applies `config`'s edits to the successors of randomly chosen rules, leaving parametric
+    /// successors alone, for evolving grammars. The result always parses again, keeps within
+    /// the config's bounds and starts from its axiom.
+    pub fn mutate<R: Rng>(&self, rng: &mut R, config: &MutationConfig) -> Self {
+        let alphabet = self.edit_symbols();
+        let mut mutated = self.clone();
+        mutated.reset();
+
+        let editable: Vec<usize> = (0..mutated.rules.len())
+            .filter(|&i| mutated.rules[i].template.is_none())
+            .collect();
+        // swaps, inserts, deletes and branch edits, in that order
+        let kinds = [config.swap, config.insert, config.delete, config.branch].map(|w| {
+            if w > 0.0 && w.is_finite() {
+                w
+            } else {
+                0.0
+            }
+        });
+        let kinds = match WeightedIndex::new(kinds) {
+            Ok(kinds) if !editable.is_empty() => kinds,
+            _ => return mutated,
+        };
+
+        for _ in 0..config.edits {
+            let successor = &mut mutated.rules[*editable.choose(rng).unwrap()].to;
+            let before = successor.clone();
+            let mut n = rng.gen_range(0..count_sequences(successor));
+            let sequence = nth_sequence(successor, &mut n).unwrap();
+            let symbol = Instruction::Symbol(alphabet.choose(rng).unwrap().clone());
+            let kind = kinds.sample(rng);
+
+            if sequence.is_empty() {
+                sequence.push(symbol);
+            } else {
+                let i = rng.gen_range(0..sequence.len());
+                match (kind, &sequence[i]) {
+                    (0, _) => sequence[i] = symbol,
+                    (1, _) => sequence.insert(i, symbol),
+                    (2, _) if sequence.len() > 1 => {
+                        sequence.remove(i);
+                    }
+                    (2, _) => {}
+                    (_, Instruction::Branch(inner)) => {
+                        let inner = inner.clone();
+                        sequence.splice(i..=i, inner);
+                    }
+                    _ => {
+                        let wrapped = sequence[i].clone();
+                        sequence[i] = Instruction::Branch(vec![symbol, wrapped]);
+                    }
+                }
+            }
+
+            if count_symbols(successor) > config.max_length || nesting(successor) > config.max_depth
+            {
+                *successor = before;
+            }
+        }
+
+        mutated
+    }
+
+    /// An offspring of this system and `other`, for evolving grammars along with `mutate`: each
+    /// rule of this system whose symbol `other` also rewrites gets a successor that starts like
+    /// its own and ends like `other`'s, split where branches begin or end so brackets stay
+    /// balanced. Rules only `other` has are each taken or left with even chance. Parametric
+    /// successors are left alone. Everything else comes from this system, and the offspring
+    /// starts from its axiom.
+    pub fn crossover<R: Rng>(&self, other: &LSystem, rng: &mut R) -> Self {
+        // where a successor can be split without breaking a branch apart
+        fn cuts(instrs: &[Instruction]) -> Vec<usize> {
+            (0..=instrs.len())
+                .filter(|&i| {
+                    i == 0
+                        || i == instrs.len()
+                        || matches!(instrs[i], Instruction::Branch(_))
+                        || matches!(instrs[i - 1], Instruction::Branch(_))
+                })
+                .collect()
+        }
+
+        let mut child = self.clone();
+        child.reset();
+        let plain = |rule: &&Rule| rule.template.is_none();
+        for rule in child
+            .rules
+            .iter_mut()
+            .filter(|rule| rule.template.is_none())
+        {
+            let partners: Vec<&Rule> = other
+                .rules
+                .iter()
+                .filter(plain)
+                .filter(|r| r.from == rule.from)
+                .collect();
+            if let Some(partner) = partners.choose(rng) {
+                let head = *cuts(&rule.to).choose(rng).unwrap();
+                let tail = *cuts(&partner.to).choose(rng).unwrap();
+                rule.to = [&rule.to[..head], &partner.to[tail..]].concat();
+            }
+        }
+        for rule in &other.rules {
+            let known = self.rules.iter().any(|r| r.from == rule.from);
+            if !known && rng.gen_bool(0.5) {
+                child.rules.push(rule.clone());
+            }
+        }
+        child
+    }
+}
+
+// like `tally`, with expected counts, and the number of branches separately
+fn expect(
+    instrs: &[Instruction],
+    times: f64,
+    counts: &mut HashMap<Symbol, f64>,
+    branches: &mut f64,
+) {
+    for instr in instrs {
+        match instr {
+            Instruction::Symbol(s) | Instruction::Module(s, _) => {
+                *counts.entry(s.clone()).or_insert(0.0) += times;
+            }
+            Instruction::Branch(inner) => {
+                *branches += times;
+                expect(inner, times, counts, branches);
+            }
+        }
+    }
+}
+
+// adds `times` for every symbol in `instrs` to its count
+fn tally(
+    instrs: &[Instruction],
+    times: usize,
+    counts: &mut HashMap<Instruction, usize>,
+) -> Option<()> {
+    for instr in instrs {
+        match instr {
+            Instruction::Branch(inner) => tally(inner, times, counts)?,
+            _ => {
+                let count = counts.entry(instr.clone()).or_insert(0);
+                *count = count.checked_add(times)?;
+            }
+        }
+    }
+    Some(())
+}
+
+// puts `signal` before the symbol `position` symbols on, counting through branches
+fn insert_at<S>(
+    instrs: &mut Instructions<S>,
+    position: &mut usize,
+    signal: &mut Option<Instruction<S>>,
+) {
+    for i in 0..instrs.len() {
+        match &mut instrs[i] {
+            Instruction::Branch(inner) => insert_at(inner, position, signal),
+            _ if *position == 0 => {
+                if let Some(signal) = signal.take() {
+                    instrs.insert(i, signal);
+                }
+            }
+            _ => *position -= 1,
+        }
+        if signal.is_none() {
+            return;
+        }
+    }
+}
+
+fn splice<S: Letter>(
+    instrs: &[Instruction<S>],
+    words: &[(&S, Instructions<S>)],
+) -> Instructions<S> {
+    let mut spliced = vec![];
+    for instr in instrs {
+        match instr {
+            Instruction::Branch(inner) => spliced.push(Instruction::Branch(splice(inner, words))),
+            Instruction::Symbol(s) | Instruction::Module(s, _) => {
+                match words.iter().find(|(bound, _)| *bound == s) {
+                    Some((_, word)) => spliced.extend_from_slice(word),
+                    None => spliced.push(instr.clone()),
+                }
+            }
+        }
+    }
+    spliced
+}
+
+// the rules, by index, that a weighted pick chooses among: those rewriting the same symbol, or
+// modules of it with as many parameters, with the same contexts, condition and delay
+fn competing<S: Letter>(rules: &[Rule<S>]) -> Vec<Vec<usize>> {
+    let mut groups: Vec<Vec<usize>> = vec![];
+    for (i, rule) in rules.iter().enumerate() {
+        let same = |other: &Rule<S>| {
+            other.from == rule.from
+                && other.params.len() == rule.params.len()
+                && other.left == rule.left
+                && other.right == rule.right
+                && other.condition == rule.condition
+                && other.delay == rule.delay
+        };
+        match groups.iter_mut().find(|group| same(&rules[group[0]])) {
+            Some(group) => group.push(i),
+            None => groups.push(vec![i]),
+        }
+    }
+    groups
+}
+
+pub(crate) fn count_branches<S>(instrs: &[Instruction<S>]) -> usize {
+    instrs
+        .iter()
+        .map(|instr| match instr {
+            Instruction::Branch(inner) => 1 + count_branches(inner),
+            Instruction::Symbol(_) | Instruction::Module(..) => 0,
+        })
+        .sum()
+}
+
+// the keys of branches by their path, as `Place::branch` makes them
+fn path_keys<S>(instrs: &[Instruction<S>], hash: u64, keys: &mut Vec<u64>) {
+    for (i, instr) in instrs.iter().enumerate() {
+        if let Instruction::Branch(inner) = instr {
+            let key = mix(hash, i as u64);
+            keys.push(key);
+            path_keys(inner, key, keys);
+        }
+    }
+}
+
+// the symbols of `instrs` and their modules in reading order, through branches
+pub(crate) fn symbols_in<S: Letter>(instrs: &[Instruction<S>]) -> impl Iterator<Item = &S> {
+    let flattened = Flattened {
+        stack: vec![instrs.iter()],
+    };
+    flattened.filter_map(|(instr, _)| instr.symbol())
+}
+
+pub(crate) fn count_symbols<S>(instrs: &[Instruction<S>]) -> usize {
+    instrs
+        .iter()
+        .map(|instr| match instr {
+            Instruction::Branch(inner) => count_symbols(inner),
+            Instruction::Symbol(_) | Instruction::Module(..) => 1,
+        })
+        .sum()
+}
+
+// how deep branches are nested
+fn nesting(instrs: &[Instruction]) -> usize {
+    instrs
+        .iter()
+        .map(|instr| match instr {
+            Instruction::Branch(inner) => 1 + nesting(inner),
+            Instruction::Symbol(_) | Instruction::Module(..) => 0,
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+fn count_sequences(instrs: &Instructions) -> usize {
+    1 + instrs
+        .iter()
+        .map(|instr| match instr {
+            Instruction::Branch(inner) => count_sequences(inner),
+            Instruction::Symbol(_) | Instruction::Module(..) => 0,
+        })
+        .sum::<usize>()
+}
+
+fn nth_sequence<'a>(instrs: &'a mut Instructions, n: &mut usize) -> Option<&'a mut Instructions> {
+    if *n == 0 {
+        return Some(instrs);
+    }
+    *n -= 1;
+
+    for instr in instrs.iter_mut() {
+        if let Instruction::Branch(inner) = instr {
+            if let Some(found) = nth_sequence(inner, n) {
+                return Some(found);
+            }
+        }
+    }
+
+    None
+}
+
+fn write_instructions<S: fmt::Display>(
+    f: &mut fmt::Formatter<'_>,
+    instrs: &[Instruction<S>],
+) -> fmt::Result {
+    let mut f = Spaced::new(f);
+    for instr in instrs {
+        f.item(instr, matches!(instr, Instruction::Symbol(_)))?;
+    }
+    Ok(())
+}
+
+// writes symbols one after another, with a space wherever a name, or a `$` of its own, would
+// otherwise run into what follows it
+struct Spaced<'a, 'f> {
+    f: &'a mut fmt::Formatter<'f>,
+    // what was last written is a bare `$...`
+    open: bool,
+    starting: bool,
+    sigil: bool,
+}
+
+impl<'a, 'f> Spaced<'a, 'f> {
+    fn new(f: &'a mut fmt::Formatter<'f>) -> Self {
+        Self {
+            f,
+            open: false,
+            starting: false,
+            sigil: false,
+        }
+    }
+
+    // `bare` if nothing, like the arguments of a module, closes the item
+    fn item(&mut self, item: impl fmt::Display, bare: bool) -> fmt::Result {
+        use fmt::Write;
+
+        self.starting = true;
+        write!(self, "{}", item)?;
+        self.open = bare && self.sigil;
+        Ok(())
+    }
+
+    // for what never runs into a name
+    fn raw(&mut self) -> &mut fmt::Formatter<'f> {
+        self.open = false;
+        self.f
+    }
+}
+
+impl fmt::Write for Spaced<'_, '_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if self.starting && !s.is_empty() {
+            self.starting = false;
+            self.sigil = s.starts_with('$');
+            if self.open && s.starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_') {
+                self.f.write_str(" ")?;
+            }
+        }
+        self.f.write_str(s)
+    }
+}
+
+impl<S: fmt::Display> fmt::Display for Instruction<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::Symbol(c) => write!(f, "{}", c),
+            Instruction::Module(c, values) => {
+                write!(f, "{}(", c)?;
+                write_list(f, &values.0)?;
+                write!(f, ")")
+            }
+            Instruction::Branch(instrs) => {
+                write!(f, "[")?;
+                write_instructions(f, instrs)?;
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+fn write_list(f: &mut fmt::Formatter<'_>, items: &[impl fmt::Display]) -> fmt::Result {
+    for (i, item) in items.iter().enumerate() {
+        match i {
+            0 => write!(f, "{}", item)?,
+            _ => write!(f, ",{}", item)?,
+        }
+    }
+    Ok(())
+}
+
+fn write_templates(
+    f: &mut fmt::Formatter<'_>,
+    templates: &[Template],
+    names: &[String],
+) -> fmt::Result {
+    let mut f = Spaced::new(f);
+    for template in templates {
+        match template {
+            Template::Symbol(c) => f.item(c, true)?,
+            Template::Module(c, args) => {
+                f.item(c, false)?;
+                let f = f.raw();
+                write!(f, "(")?;
+                let args: Vec<_> = args.iter().map(|arg| arg.display(names)).collect();
+                write_list(f, &args)?;
+                write!(f, ")")?;
+            }
+            Template::Branch(inner) => {
+                let f = f.raw();
+                write!(f, "[")?;
+                write_templates(f, inner, names)?;
+                write!(f, "]")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_symbols(f: &mut fmt::Formatter<'_>, symbols: &[Symbol]) -> fmt::Result {
+    let mut f = Spaced::new(f);
+    symbols.iter().try_for_each(|symbol| f.item(symbol, true))
+}
+
+impl fmt::Display for LSystem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(angle) = self.angle {
+            write!(f, "angle {}; ", angle)?;
+        }
+        if let Some(step) = self.step {
+            write!(f, "step {}; ", step)?;
+        }
+        write_instructions(f, &self.axiom)?;
+        write!(f, ";")?;
+        if !self.ignore.is_empty() {
+            write!(f, " #ignore ")?;
+            write_symbols(f, &self.ignore)?;
+            write!(f, ";")?;
+        }
+        if !self.constants.is_empty() {
+            write!(f, " #const ")?;
+            write_symbols(f, &self.constants)?;
+            write!(f, ";")?;
+        }
+        for rule in &self.rules {
+            write!(f, " ")?;
+            write_symbols(f, &rule.left)?;
+            if !rule.left.is_empty() {
+                write!(f, " < ")?;
+            }
+            write!(f, "{}", rule.from)?;
+            if !rule.params.is_empty() {
+                write!(f, "(")?;
+                write_list(f, &rule.params)?;
+                write!(f, ")")?;
+            }
+            if rule.delay > 0 {
+                write!(f, "@{}", rule.delay)?;
+            }
+            if !rule.right.is_empty() {
+                write!(f, " > ")?;
+            }
+            write_symbols(f, &rule.right)?;
+            if let Some(condition) = &rule.condition {
+                write!(f, " : {}", condition.display(&rule.params))?;
+            }
+            match self.selection {
+                RuleSelection::Weighted => write!(f, " -{}-> ", rule.weight)?,
+                _ => write!(f, " -> ")?,
+            }
+            match &rule.template {
+                Some(template) => write_templates(f, template, &rule.params)?,
+                None => write_instructions(f, &rule.to)?,
+            }
+            write!(f, ";")?;
+        }
+        Ok(())
+    }
+}
+
+impl<S: Letter> Instruction<S> {
+    /// The symbol of a symbol or module, `None` for a branch.
+    pub fn symbol(&self) -> Option<&S> {
+        match self {
+            Instruction::Symbol(s) | Instruction::Module(s, _) => Some(s),
+            Instruction::Branch(_) => None,
+        }
+    }
+
+    // `%`, which cuts off the rest of its branch
+    fn is_cut(&self) -> bool {
+        matches!(self, Instruction::Symbol(s) if s.as_symbol().is_some_and(|s| *s == '%'))
+    }
+
+    // branches are never matched by rules; `LSystem::rewrite` descends into them itself
+    fn apply<R: Rng>(
+        &self,
+        rules: &[Rule<S>],
+        selection: RuleSelection,
+        rng: &mut R,
+    ) -> Instructions<S> {
+        self.apply_traced(rules, selection, None, rng).1
+    }
+
+    // also gives the index of the rule applied
+    fn apply_traced<R: Rng>(
+        &self,
+        rules: &[Rule<S>],
+        selection: RuleSelection,
+        neighbors: Option<&Neighbors<S>>,
+        rng: &mut R,
+    ) -> (Option<usize>, Instructions<S>) {
+        match selection.select(rules, self, neighbors, rng) {
+            Some((i, rule)) => (Some(i), rule.successor(self)),
+            None => (None, vec![self.clone()]),
+        }
+    }
+}
+
+/// Every word of a system from its axiom on, each derived only once it is asked for. Created by
+/// `LSystem::derivations`.
+#[derive(Debug, Clone)]
+pub struct Derivations<S = Symbol>(LSystem<S>);
+
+impl<S> Derivations<S> {
+    /// The generation of the word `next` returns; the axiom is generation 0.
+    pub fn generation(&self) -> usize {
+        self.0.generation
+    }
+}
+
+impl<S: Letter> Iterator for Derivations<S> {
+    type Item = Instructions<S>;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
+    }
+}
+
+/// The words of a system up to the first one it rewrites into itself. Created by
+/// `LSystem::until_fixed`.
+#[derive(Debug, Clone)]
+pub struct UntilFixed<S = Symbol> {
+    lsystem: LSystem<S>,
+    fixed: bool,
+}
+
+impl<S> UntilFixed<S> {
+    /// Whether the last word returned is a fixed point, after which there are no more.
+    pub fn fixed(&self) -> bool {
+        self.fixed
+    }
+
+    /// The generation of the word `next` returns, or of the fixed point once it is reached.
+    pub fn generation(&self) -> usize {
+        self.lsystem.generation - self.fixed as usize
+    }
+}
+
+impl<S: Letter> Iterator for UntilFixed<S> {
+    type Item = Instructions<S>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.fixed {
+            return None;
+        }
+        let word = self.lsystem.next()?;
+        self.fixed = self.lsystem.word == word;
+        Some(word)
+    }
+}
+
+/// Every rewrite of a system, with its productions. Created by `LSystem::replay`.
+#[derive(Debug, Clone)]
+pub struct Replay<S = Symbol> {
+    lsystem: LSystem<S>,
+}
+
+impl<S> Replay<S> {
+    /// The generation of the word the next step rewrites.
+    pub fn generation(&self) -> usize {
+        self.lsystem.generation
+    }
+}
+
+impl<S: Letter> Iterator for Replay<S> {
+    type Item = ReplayStep<S>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let generation = self.lsystem.generation;
+        let before = self.lsystem.word.clone();
+        let productions = self.lsystem.rewrite_word(None, &|_| true, true, None);
+        Some(ReplayStep {
+            generation,
+            before,
+            after: self.lsystem.word.clone(),
+            productions,
+        })
+    }
+}
+
+/// The word `generations` rewrites after a system's current one, as a stream of events that is
+/// expanded depth first as it is read: only the symbols on the way from the current word down
+/// to the symbol being read are ever in memory. Created by `LSystem::expand_lazily`.
+///
+/// Deterministic systems give the same word as rewriting. Random selections are made in the
+/// order of the stream, so they differ from those of rewriting, but are just as repeatable.
+/// Systems with rules that have a context or a delay, or with a depth limit, are rewritten in
+/// full first, since contexts, ages and depths are only known in the whole word. Environment
+/// queries aren't answered, and a cut leaves in what it cuts off; drawing the stream still
+/// skips it.
+#[derive(Debug, Clone)]
+pub struct Expansion<'l> {
+    lsystem: &'l LSystem,
+    generations: usize,
+    rng: ChaCha12Rng,
+    stack: Vec<Frame>,
+}
+
+#[derive(Debug, Clone)]
+struct Frame {
+    instructions: Instructions,
+    next: usize,
+    // rewrites still to apply to the instructions
+    depth: usize,
+    branch: bool,
+}
+
+impl Iterator for Expansion<'_> {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Event> {
+        loop {
+            let frame = self.stack.last_mut()?;
+            let instr = match frame.instructions.get(frame.next) {
+                Some(instr) => instr.clone(),
+                None => {
+                    let frame = self.stack.pop().unwrap();
+                    match frame.branch {
+                        true => return Some(Event::Pop),
+                        false => continue,
+                    }
+                }
+            };
+            frame.next += 1;
+
+            let depth = frame.depth;
+            match instr {
+                Instruction::Branch(inner) => {
+                    self.stack.push(Frame {
+                        instructions: inner,
+                        next: 0,
+                        depth,
+                        branch: true,
+                    });
+                    return Some(Event::Push);
+                }
+                Instruction::Symbol(s) if depth == 0 => return Some(Event::Symbol(s)),
+                Instruction::Module(s, values) if depth == 0 => {
+                    return Some(Event::Module(s, values))
+                }
+                instr => {
+                    let generation = self.lsystem.generation + self.generations - depth;
+                    let rules = self.lsystem.rules_at(generation);
+                    let mut successor = instr.apply(rules, self.lsystem.selection, &mut self.rng);
+                    if let Some(dropout) = &self.lsystem.dropout {
+                        successor = dropout.apply_with(&successor, &mut self.rng);
+                    }
+                    self.stack.push(Frame {
+                        instructions: successor,
+                        next: 0,
+                        depth: depth - 1,
+                        branch: false,
+                    });
+                }
+            }
+        }
+    }
+}
+
+// a symbol's expansion, and whether a cut in it also drops the rest of its branch
+#[derive(Debug, Default)]
+struct Expanded {
+    instructions: Instructions,
+    cut: bool,
+}
+
+type Memo = HashMap<(Instruction, usize), Rc<Expanded>>;
+
+/// A system is itself the iterator over its words, from the current one on; `derivations`
+/// starts from the axiom without touching the system.
+impl<S: Letter> Iterator for LSystem<S> {
+    type Item = Instructions<S>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let res = self.word.clone();
+
+        self.step();
+
+        Some(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn only_chosen_symbols_grow() {
+        let mut lsystem = LSystem::from_str("T[A]; T -> TT; A -> T[A]A;").unwrap();
+        lsystem.step_only(|s| *s == 'A');
+        lsystem.step_only(|s| *s == 'A');
+        assert_eq!(
+            Word::from(lsystem.word().clone()).to_string(),
+            "T[T[T[A]A]T[A]A]"
+        );
+        lsystem.step_only(|s| *s == 'T');
+        assert_eq!(
+            Word::from(lsystem.word().clone()).to_string(),
+            "TT[TT[TT[A]A]TT[A]A]"
+        );
+    }
+
+    #[test]
+    fn renaming_reaches_axiom_and_rules() {
+        let mut lsystem = LSystem::from_str("X[A]; X -> F[+X]A; A -> AA;").unwrap();
+        lsystem.rename_symbol('F', 'G');
+        assert_eq!(lsystem.to_string(), "X[A]; X -> G[+X]A; A -> AA;");
+
+        let aliases = HashMap::from([('X'.into(), 'A'.into()), ('A'.into(), 'X'.into())]);
+        lsystem.rename_symbols(&aliases);
+        assert_eq!(lsystem.to_string(), "A[X]; A -> G[+A]X; X -> XX;");
+    }
+
+    #[test]
+    fn rules_and_axiom_are_edited_in_place() {
+        let mut lsystem = LSystem::from_str("A; A -> AB;").unwrap();
+        lsystem.nth(1);
+        lsystem.set_axiom(Word::parse("B").unwrap());
+        assert_eq!(lsystem.generation(), 0);
+        assert_eq!(lsystem.word(), lsystem.axiom());
+        lsystem.add_rule(Rule::parse_all("B -> A;").unwrap().remove(0));
+        assert!(lsystem.remove_rule(2).is_none());
+        assert!(lsystem.remove_rule(0).is_some());
+        assert_eq!(lsystem.to_string(), "B; B -> A;");
+    }
+
+    #[test]
+    fn validation_finds_dead_rules_and_symbols() {
+        let mut lsystem = LSystem::from_str("X; X -> F[+Y]X; Z -> X; Y -> ;").unwrap();
+        let own = |rule| RuleRef { table: None, rule };
+        let issues = lsystem.validate();
+        assert_eq!(
+            issues,
+            [
+                Issue::Undefined('F'.into()),
+                Issue::Unreachable(own(1)),
+                Issue::EmptyProduction(own(2))
+            ]
+        );
+        assert!(issues.iter().all(|i| i.severity() == Severity::Warning));
+        assert_eq!(issues[1].to_string(), "rule 2 never applies");
+
+        lsystem.add_table("winter", Rule::parse_all("X -> X; W -> ;").unwrap());
+        let winter = RuleRef {
+            table: Some("winter".to_string()),
+            rule: 1,
+        };
+        let issues = lsystem.validate();
+        assert_eq!(
+            issues[3..],
+            [
+                Issue::Unreachable(winter.clone()),
+                Issue::EmptyProduction(winter)
+            ]
+        );
+        assert_eq!(
+            issues[4].to_string(),
+            "rule 2 of table 'winter' erases its symbol"
+        );
+
+        let lsystem = LSystem::new(vec![], vec![]);
+        assert_eq!(lsystem.validate(), [Issue::EmptyAxiom]);
+    }
+
+    #[test]
+    fn simplification_drops_and_inlines_rules() {
+        let lsystem =
+            LSystem::from_str("X; X -> F[+Y]X; Y -> FF; X -> F; F -> F; Z -> Z; W(x) -> W(x);")
+                .unwrap();
+        let turtle = TurtleConfig::default();
+        assert_eq!(lsystem.simplified(&turtle).to_string(), "X; X -> F[+FF]X;");
+
+        // a symbol the turtle draws, or that contexts look for, is left where it is
+        let drawn = LSystem::from_str("X; X -> F[+f]X; f -> FF;").unwrap();
+        assert_eq!(
+            drawn.simplified(&turtle).to_string(),
+            "X; X -> F[+f]X; f -> FF;"
+        );
+        let matched = LSystem::from_str("X; X -> F[+Y]X; Y -> FF; Y < F -> G;").unwrap();
+        assert_eq!(matched.simplified(&turtle).rules().len(), 3);
+        let grown = LSystem::from_str("X; X -> G[+H]X; G -> FF; H -> X;").unwrap();
+        let rebound = TurtleConfig::default().draw_forward("G").home('H');
+        assert_eq!(grown.simplified(&rebound), grown);
+        assert_eq!(grown.simplified(&turtle).to_string(), "X; X -> FF[+X]X;");
+
+        // every rule can be picked at random, even one that changes nothing
+        let mut random = LSystem::from_str("X; X -> FX; X -> X;").unwrap();
+        random.set_selection(RuleSelection::Uniform);
+        assert_eq!(random.simplified(&turtle).rules(), random.rules());
+    }
+
+    #[test]
+    fn composition_renames_clashing_symbols() {
+        let trunk = LSystem::from_str("X; X -> F[+L]X; F -> FF;").unwrap();
+        let leaf = LSystem::from_str("L; L -> XF; X -> -X;").unwrap();
+        let composed = trunk.compose(&leaf);
+        assert_eq!(
+            composed.to_string(),
+            "X; X -> F[+L]X; F -> FF; L -> AF; A -> -A;"
+        );
+    }
+
+    #[test]
+    fn contexts_skip_ignored_symbols_and_branches() {
+        let signal = |ignore: &str| {
+            let grammar = format!("B+A[-A]A; {} A -> B;", ignore);
+            let mut lsystem = LSystem::from_str(&grammar).unwrap();
+            lsystem.rules_mut()[0].set_context(vec!['B'.into()], vec![]);
+            lsystem.nth(2).unwrap()
+        };
+        assert_eq!(signal("#ignore +-;"), Word::parse("B+B[-B]B").unwrap().0);
+        assert_eq!(signal(""), Word::parse("B+A[-A]A").unwrap().0);
+
+        let mut lsystem = LSystem::from_str("A[C]C; #ignore +; A -> D;").unwrap();
+        lsystem.rules_mut()[0].set_context(vec![], vec!['C'.into()]);
+        assert_eq!(lsystem.to_string(), "A[C]C; #ignore +; A > C -> D;");
+        assert_eq!(lsystem.nth(1).unwrap(), Word::parse("D[C]C").unwrap().0);
+        lsystem.reset();
+        assert_eq!(lsystem.expand_memoized(1), Word::parse("D[C]C").unwrap().0);
+    }
+
+    #[test]
+    fn alphabets_split_rewritten_symbols() {
+        let mut lsystem = LSystem::from_str("X[A]; X -> F(1)[+X]A; A -> AA;").unwrap();
+        lsystem.add_table("other", Rule::parse_all("B -> -;").unwrap());
+        let symbols = |s: &str| s.chars().map(Symbol::from).collect::<BTreeSet<_>>();
+        let alphabet = lsystem.alphabet();
+        assert_eq!(alphabet.terminals, symbols("+-F"));
+        assert_eq!(alphabet.non_terminals, symbols("ABX"));
+    }
+
+    #[test]
+    fn words_parse_without_a_grammar() {
+        let word = Word::parse("F[+F] F[-F[F]]").unwrap();
+        let lsystem = LSystem::from_str("F[+F]F[-F[F]];").unwrap();
+        assert_eq!(word.instructions(), &lsystem.word()[..]);
+        assert_eq!(word.to_string(), "F[+F]F[-F[F]]");
+
+        assert!(Word::parse("F]F").is_err());
+        assert!(Word::parse("F[F").is_err());
+        assert!(Word::parse("F;").is_err());
+        assert_eq!(Word::parse("").unwrap(), Word::default());
+    }
+
+    #[test]
+    fn words_are_indexed_and_flattened() {
+        let word = Word::parse("F[+G[H]]J(2)").unwrap();
+        assert_eq!(word.len(), 3);
+        assert_eq!(word[2], Instruction::Module('J'.into(), Params(vec![2.0])));
+        assert_eq!(word.sub_word(0..2).to_string(), "F[+G[H]]");
+        assert_eq!(word[1..].len(), 2);
+
+        let depths: Vec<_> = word
+            .symbols()
+            .map(|(instr, depth)| (instr.to_string(), depth))
+            .collect();
+        let expected = [("F", 0), ("+", 1), ("G", 1), ("H", 2), ("J(2)", 0)];
+        assert_eq!(depths, expected.map(|(s, depth)| (s.to_string(), depth)));
+        assert_eq!(Word::<Symbol>::default().symbols().next(), None);
+    }
+
+    #[test]
+    fn test_simple_instructions() {
+        use Template::*;
+        assert_eq!(
+            Ok(("", vec![Symbol('F'.into()), Symbol('G'.into())])),
+            simple_instructions("FG", EMPTY_SCOPE)
+        )
+    }
+
+    #[test]
+    fn test_simple_instructions_branch_separated() {
+        use Template::*;
+        assert_eq!(
+            Ok(("[FGFGF]", vec![Symbol('F'.into()), Symbol('G'.into())])),
+            simple_instructions("FG[FGFGF]", EMPTY_SCOPE)
+        )
+    }
+
+    #[test]
+    fn test_branching() {
+        use Template::*;
+        assert_eq!(
+            Ok((
+                "",
+                vec![Branch(vec![Symbol('F'.into()), Symbol('G'.into())])]
+            )),
+            branch("[FG]", EMPTY_SCOPE)
+        )
+    }
+
+    #[test]
+    fn test_symbols() {
+        use Instruction::*;
+        assert_eq!(
+            Ok((
+                "",
+                vec![
+                    Symbol('F'.into()),
+                    Symbol('G'.into()),
+                    Branch(vec![
+                        Symbol('F'.into()),
+                        Symbol('G'.into()),
+                        Symbol('F'.into())
+                    ]),
+                    Symbol('F'.into()),
+                    Symbol('G'.into())
+                ]
+            )),
+            instructions("FG[FGF]FG")
+        )
+    }
+
+    #[test]
+    fn missing_branckets() {
+        assert_eq!(instructions("FGFGHA[DOAIJD").unwrap().0, "[DOAIJD")
+    }
+
+    #[test]
+    fn too_many_branckets() {
+        assert_eq!(instructions("FGFGHA[DOAIJD]]").unwrap().0, "]")
+    }
+
+    #[test]
+    fn single_rule() {
+        use Instruction::*;
+        assert_eq!(
+            Ok((
+                "",
+                (
+                    Rule::new(
+                        Symbol('A'.into()),
+                        vec![Symbol('K'.into()), Symbol('J'.into()), Symbol('H'.into())]
+                    ),
+                    false
+                )
+            )),
+            rule("A->KJH")
+        )
+    }
+
+    #[test]
+    fn rule_whitespace_before() {
+        use Instruction::*;
+        assert_eq!(
+            Ok((
+                "",
+                (
+                    Rule::new(
+                        Symbol('A'.into()),
+                        vec![Symbol('K'.into()), Symbol('J'.into()), Symbol('H'.into())]
+                    ),
+                    false
+                )
+            )),
+            rule("  \t\nA->KJH")
+        )
+    }
+
+    #[test]
+    fn symbol_class_rule() {
+        use Instruction::*;
+        assert_eq!(
+            Ok((
+                "",
+                (
+                    vec![
+                        Rule::new(
+                            Symbol('F'.into()),
+                            vec![Symbol('F'.into()), Symbol('F'.into())]
+                        ),
+                        Rule::new(
+                            Symbol('G'.into()),
+                            vec![Symbol('F'.into()), Symbol('F'.into())]
+                        )
+                    ],
+                    false
+                )
+            )),
+            rules("[F G F] -> FF")
+        );
+        assert!(class_rule("[] -> FF").is_err());
+    }
+
+    #[test]
+    fn symbol_class_in_lsystem() {
+        let lsys = LSystem::from_str("FG; [FG] -> F+G; H -> H;").unwrap();
+        assert_eq!(lsys.to_string(), "FG; F -> F+G; G -> F+G; H -> H;");
+    }
+
+    #[test]
+    fn weights_in_the_grammar() {
+        let lsys = LSystem::from_str("F; F -0.4-> F[+F]; F -.6-> FF; [GH] -2-> G;").unwrap();
+        assert_eq!(lsys.selection(), RuleSelection::Weighted);
+        let weights: Vec<_> = lsys.rules().iter().map(Rule::weight).collect();
+        assert_eq!(weights, [0.4, 0.6, 2.0, 2.0]);
+        assert_eq!(
+            lsys.to_string(),
+            "F; F -0.4-> F[+F]; F -0.6-> FF; G -2-> G; H -2-> G;"
+        );
+        assert_eq!(
+            LSystem::from_str(&lsys.to_string()).unwrap().rules(),
+            lsys.rules()
+        );
+
+        let grown: std::collections::HashSet<_> = (0..20)
+            .map(|seed| {
+                let mut lsys = lsys.clone();
+                lsys.set_seed(seed);
+                lsys.nth(1).unwrap()
+            })
+            .collect();
+        assert_eq!(grown.len(), 2);
+    }
+
+    #[test]
+    fn parameters_are_evaluated_while_rewriting() {
+        let mut lsys = LSystem::from_str("F(1); F(x) -> F(x*0.5)[+F(x/4)];").unwrap();
+        assert_eq!(lsys.rules()[0].params(), ["x"]);
+        assert_eq!(
+            Word::from(lsys.nth(2).unwrap()).to_string(),
+            "F(0.25)[+F(0.125)][+F(0.125)[+F(0.0625)]]"
+        );
+        assert_eq!(lsys.to_string(), "F(1); F(x) -> F(x*0.5)[+F(x/4)];");
+        lsys.reset();
+        assert_eq!(LSystem::from_str(&lsys.to_string()).unwrap(), lsys);
+
+        // only modules with as many values as the rule has parameters are rewritten
+        let mut lsys = LSystem::from_str("FF(2)F(1,2); F(x) -> G(x+1); F -> H;").unwrap();
+        assert_eq!(Word::from(lsys.nth(1).unwrap()).to_string(), "HG(3)F(1,2)");
+
+        // two parameters, spaced the way papers write them
+        let mut lsys =
+            LSystem::from_str("F(1, 2); F(x, y) -> F(x*0.7+1, y)[+F(x * 0.5)];").unwrap();
+        assert_eq!(lsys.rules()[0].params(), ["x", "y"]);
+        assert_eq!(
+            Word::from(lsys.nth(1).unwrap()).to_string(),
+            "F(1.7,2)[+F(0.5)]"
+        );
+
+        assert!(LSystem::from_str("F(x);").is_err());
+        assert!(LSystem::from_str("F; F(x) -> F(y);").is_err());
+    }
+
+    #[test]
+    fn guards_fall_through_to_later_rules() {
+        let mut lsys = LSystem::from_str("A(3); A(x) : x > 1 -> A(x-1)B; A(x) -> C;").unwrap();
+        let words: Vec<_> = lsys
+            .by_ref()
+            .take(4)
+            .map(|word| Word::from(word).to_string())
+            .collect();
+        assert_eq!(words, ["A(3)", "A(2)B", "A(1)BB", "CBB"]);
+
+        lsys.reset();
+        assert_eq!(
+            lsys.to_string(),
+            "A(3); A(x) : x > 1 -> A(x-1)B; A(x) -> C;"
+        );
+        assert_eq!(LSystem::from_str(&lsys.to_string()).unwrap(), lsys);
+
+        let weighted = LSystem::from_str("A(3); A(x) : x > 1 -0.5-> B;").unwrap();
+        assert_eq!(weighted.rules()[0].weight(), 0.5);
+    }
+
+    #[test]
+    fn tables_switch_by_schedule_and_by_hand() {
+        let mut lsys = LSystem::from_str("A; A -> AB;").unwrap();
+        lsys.add_table("flower", Rule::parse_all("A -> F; B -> BB;").unwrap());
+        lsys.set_schedule(&Schedule::new().during(2..3, "flower"))
+            .unwrap();
+        let words: Vec<_> = lsys
+            .by_ref()
+            .take(5)
+            .map(|word| Word::from(word).to_string())
+            .collect();
+        assert_eq!(words, ["A", "AB", "ABB", "FBBBB", "FBBBB"]);
+
+        lsys.reset();
+        lsys.set_table(Some("flower")).unwrap();
+        assert_eq!(lsys.table(), Some("flower"));
+        assert_eq!(Word::from(lsys.nth(3).unwrap()).to_string(), "F");
+
+        assert!(lsys.set_table(Some("winter")).is_err());
+        assert!(lsys
+            .set_schedule(&Schedule::new().during(0..1, "winter"))
+            .is_err());
+        assert!(Rule::parse_all("A -> B; C").is_err());
+    }
+
+    #[test]
+    fn cuts_drop_the_rest_of_their_branch() {
+        let mut lsys = LSystem::from_str("F[AF[F]F]FA; A -> %;").unwrap();
+        let words: Vec<_> = lsys
+            .by_ref()
+            .take(3)
+            .map(|word| Word::from(word).to_string())
+            .collect();
+        assert_eq!(words, ["F[AF[F]F]FA", "F[%F[F]F]F%", "F[]F"]);
+    }
+
+    #[test]
+    fn derivations_start_from_the_axiom() {
+        let mut lsys = LSystem::from_str("A; A -> AB; B -> A;").unwrap();
+        lsys.nth(2);
+        let mut derivations = lsys.derivations();
+        let words: Vec<_> = derivations
+            .by_ref()
+            .take(4)
+            .map(|word| Word::from(word).to_string())
+            .collect();
+        assert_eq!(words, ["A", "AB", "ABA", "ABAAB"]);
+        assert_eq!(derivations.generation(), 4);
+        assert_eq!(lsys.generation(), 3);
+    }
+
+    #[test]
+    fn rewriting_stops_at_fixed_points() {
+        let lsys = LSystem::from_str("A; A -> B; B -> C[D]; D -> D;").unwrap();
+        let mut words = lsys.clone().until_fixed();
+        let derived: Vec<_> = (&mut words).map(|w| Word::from(w).to_string()).collect();
+        assert_eq!(derived, ["A", "B", "C[D]"]);
+        assert!(words.fixed());
+        assert_eq!(words.generation(), 2);
+
+        let growing = LSystem::from_str("A; A -> AB;").unwrap().until_fixed();
+        assert_eq!(growing.take(10).count(), 10);
+    }
+
+    #[test]
+    fn symbols_are_annotated_with_their_generation() {
+        let mut lsys = LSystem::from_str("A; A -> B[A]; B -> BC;").unwrap();
+        assert!(lsys.annotated().is_none());
+        lsys.set_annotating(true);
+        lsys.nth(2);
+        // B[A], then BC[B[A]], then BCC[BC[B[A]]], whose second C is left from before
+        let annotated: Vec<_> = lsys
+            .annotated()
+            .unwrap()
+            .map(|a| (a.instruction.to_string(), a.depth, a.generation))
+            .collect();
+        let expected = [
+            ("B", 0, 3),
+            ("C", 0, 3),
+            ("C", 0, 2),
+            ("B", 1, 3),
+            ("C", 1, 3),
+            ("B", 2, 3),
+            ("A", 3, 3),
+        ];
+        assert_eq!(annotated, expected.map(|(s, d, g)| (s.to_string(), d, g)));
+
+        lsys.reset();
+        assert_eq!(lsys.annotated().unwrap().count(), 1);
+
+        let mut cut = LSystem::from_str("F[%G]F; F -> FF;").unwrap();
+        cut.set_annotating(true);
+        cut.next();
+        let generations: Vec<_> = cut.annotated().unwrap().map(|a| a.generation).collect();
+        assert_eq!(generations, [1; 4]);
+    }
+
+    #[test]
+    fn lazy_expansion_matches_rewriting() {
+        use crate::geometry::Recorder;
+        use crate::graphics::TurtleConfig;
+
+        let lsys =
+            LSystem::from_str("X; X -> F[+X][-X(2)]FX; F -> FF; X(a) -> F(a*2)X(a);").unwrap();
+        for generations in 0..5 {
+            let expanded = crate::compress::assemble(lsys.expand_lazily(generations));
+            assert_eq!(expanded, lsys.clone().nth(generations).unwrap());
+        }
+
+        // what a cut cuts off stays in the stream, but isn't drawn
+        let cut = LSystem::from_str("X; X -> F[+X]F%X; F -> FF;").unwrap();
+        let turtle = TurtleConfig::default();
+        let mut streamed = Recorder::new();
+        turtle
+            .create_turtle()
+            .draw_events(&mut streamed, cut.expand_lazily(4))
+            .unwrap();
+        let mut rewritten = Recorder::new();
+        turtle
+            .create_turtle()
+            .draw(&mut rewritten, cut.clone().nth(4).unwrap())
+            .unwrap();
+        assert_eq!(streamed.segments(), rewritten.segments());
+
+        let deep = LSystem::from_str("F; F -> FF;").unwrap();
+        assert_eq!(deep.expand_lazily(24).take(1000).count(), 1000);
+    }
+
+    #[test]
+    fn nth_derivation_is_interpreted_as_derived() {
+        use crate::geometry::Recorder;
+        use crate::graphics::TurtleConfig;
+
+        let lsys = LSystem::from_str("X; X -> F[+X]F[-X]+X; F -> FF;").unwrap();
+        let turtle = TurtleConfig::default();
+        for n in 0..5 {
+            let mut interpreted = Recorder::new();
+            lsys.interpret_nth(n, turtle.create_turtle(), &mut interpreted)
+                .unwrap();
+            let mut drawn = Recorder::new();
+            turtle
+                .create_turtle()
+                .draw(&mut drawn, lsys.clone().nth(n).unwrap())
+                .unwrap();
+            assert_eq!(interpreted.segments(), drawn.segments());
+        }
+        assert_eq!(lsys.generation(), 0);
+
+        let deep = LSystem::from_str("F; F -> F+F;").unwrap();
+        let mut asked = 0;
+        let mut cut_short = Recorder::new();
+        let finished = deep
+            .interpret_nth_while(40, turtle.create_turtle(), &mut cut_short, || {
+                asked += 1;
+                asked <= 100
+            })
+            .unwrap();
+        assert!(!finished);
+        assert_eq!(cut_short.segments().len(), 50);
+    }
+
+    #[test]
+    fn derivations_stop_when_asked() {
+        let mut lsys = LSystem::from_str("A; A -> AB; B -> A;").unwrap();
+        let mut asked = 0;
+        let stopped = lsys.nth_while(10, || {
+            asked += 1;
+            asked <= 3
+        });
+        assert_eq!(stopped, None);
+        assert_eq!(lsys.generation(), 3);
+        assert_eq!(
+            lsys.nth_while(2, || true),
+            LSystem::from_str("A; A -> AB; B -> A;").unwrap().nth(5)
+        );
+        assert_eq!(lsys.generation(), 5);
+
+        // a long rewrite is given up part of the way through
+        let mut lsys = LSystem::from_str("A; A -> AB; B -> A;").unwrap();
+        lsys.nth(20);
+        let (word, generation) = (lsys.word().clone(), lsys.generation());
+        let mut asked = 0;
+        let stopped = lsys.nth_while(1, || {
+            asked += 1;
+            asked <= 3
+        });
+        assert!(stopped.is_none() && lsys.word() == &word);
+        assert_eq!((lsys.generation(), asked), (generation, 4));
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn parallel_rewriting_matches() {
+        let lsys = LSystem::from_str("F%F; F -> F+G-F[F]; G -> GG;").unwrap();
+        let mut parallel = lsys.clone();
+        parallel.set_parallel(true);
+        assert_eq!(parallel.nth(9), lsys.clone().nth(9));
+
+        let lsys = LSystem::from_str("FFFF; F -> F+G-F[F]; G -> GG;").unwrap();
+        let mut parallel = lsys.clone();
+        parallel.set_parallel(true);
+        assert_eq!(parallel.nth(8), lsys.clone().nth(8));
+    }
+
+    #[test]
+    fn memoized_expansion_matches_rewriting() {
+        for grammar in [
+            "X; X -> F[+X][-X]FX; F -> FF;",
+            "X; X -> F[+X]F%X; F -> FF;",
+            "A; A -> B%C; B -> BA; C -> CC;",
+            "F(1); F(x) : x < 4 -> F(x+1)[+F(x)]; F(x) -> G;",
+        ] {
+            let mut lsys = LSystem::from_str(grammar).unwrap();
+            assert_eq!(lsys.expand_memoized(6), lsys.clone().nth(6).unwrap());
+            lsys.nth(1);
+            assert_eq!(lsys.expand_memoized(3), lsys.clone().nth(3).unwrap());
+        }
+
+        let mut random = LSystem::from_str("AAAA; A -> AB; A -> BA;").unwrap();
+        random.set_selection(RuleSelection::Uniform);
+        assert_eq!(random.expand_memoized(4), random.clone().nth(4).unwrap());
+    }
+
+    #[test]
+    fn budgets_stop_explosive_growth() {
+        let mut lsys = LSystem::from_str("F; F -> F[F]F;").unwrap();
+        let expected = lsys.clone().nth(3).unwrap();
+        assert_eq!(lsys.nth_bounded(3, 27).unwrap(), expected);
+        assert_eq!(lsys.generation(), 3);
+
+        lsys.reset();
+        let error = lsys.nth_bounded(20, 1000).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "generation 7 has 2187 symbols, more than the 1000 allowed"
+        );
+        assert_eq!(lsys.generation(), 0);
+    }
+
+    #[test]
+    fn lengths_are_predicted_without_rewriting() {
+        let lsys = LSystem::from_str("X; X -> F[+X][-X]FX; F -> FF;").unwrap();
+        for n in 0..8 {
+            let word = lsys.clone().nth(n).unwrap();
+            assert_eq!(lsys.predicted_length(n), Some(count_symbols(&word)));
+        }
+        assert_eq!(
+            LSystem::from_str("F; F -> FF;")
+                .unwrap()
+                .predicted_length(40),
+            Some(1 << 40)
+        );
+        assert_eq!(
+            LSystem::from_str("F; F -> FF;")
+                .unwrap()
+                .predicted_length(200),
+            None
+        );
+        assert_eq!(
+            LSystem::from_str("A(1); A(x) -> A(x)A(x);")
+                .unwrap()
+                .predicted_length(2),
+            None
+        );
+        assert_eq!(
+            LSystem::from_str("F%F; F -> FF;")
+                .unwrap()
+                .predicted_length(1),
+            None
+        );
+    }
+
+    #[test]
+    fn growth_is_estimated_from_the_rules() {
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-6 * b.max(1.0);
+        let koch = LSystem::from_str("F; F -> F+F--F+F;").unwrap();
+        let growth = koch.growth(3);
+        // the turns stay, so in the long run only the four `F`s count
+        assert!(close(growth.factor, 4.0) && close(growth.symbols, 64.0 + 84.0));
+        assert!(koch.check_growth(10, 3.0).is_err());
+        assert!(koch.check_growth(10, 5.0).is_ok());
+
+        let fibonacci = LSystem::from_str("A; A -> AB; B -> A;").unwrap();
+        assert!(close(fibonacci.growth(0).factor, (1.0 + 5f64.sqrt()) / 2.0));
+        let coin = LSystem::from_str("F; F -0.5-> FF; F -0.5-> F;").unwrap();
+        assert!(close(coin.growth(2).symbols, 2.25));
+        let branching = LSystem::from_str("F; F -> F[F];").unwrap().growth(2);
+        assert!(close(branching.symbols, 7.0));
+        let still = LSystem::from_str("F+F; F -> F;").unwrap().growth(5);
+        assert!(close(still.factor, 1.0) && close(still.symbols, 3.0));
+    }
+
+    #[test]
+    fn first_rule_wins_by_default() {
+        let mut lsys = LSystem::from_str("A; A -> B; A -> C;").unwrap();
+        assert_eq!(lsys.nth(1).unwrap(), vec![Instruction::Symbol('B'.into())]);
+    }
+
+    #[test]
+    fn highest_priority_wins() {
+        let mut lsys = LSystem::from_str("A; A -> B; A -> C; A -> D;").unwrap();
+        lsys.rules_mut()[1].set_priority(2);
+        lsys.rules_mut()[2].set_priority(2);
+        lsys.set_selection(RuleSelection::Priority);
+        assert_eq!(lsys.nth(1).unwrap(), vec![Instruction::Symbol('C'.into())]);
+    }
+
+    #[test]
+    fn duplicate_rules_follow_the_selection() {
+        let mut lsys = LSystem::from_str("A; A -> B; A -> C; B -> B;").unwrap();
+        let a = Instruction::Symbol('A'.into());
+        assert_eq!(lsys.production(&a), Some(&lsys.rules()[0]));
+        lsys.set_selection(RuleSelection::Last);
+        assert_eq!(lsys.production(&a), Some(&lsys.rules()[1]));
+        assert_eq!(lsys.nth(1).unwrap(), vec![Instruction::Symbol('C'.into())]);
+        assert_eq!(lsys.production(&Instruction::Symbol('C'.into())), None);
+        lsys.set_selection(RuleSelection::Uniform);
+        assert_eq!(lsys.production(&a), None);
+
+        // only the word tells whether a context fits
+        let contexts = LSystem::from_str("A+[B]B%B; #ignore +; A < B -> X; B -> Y;").unwrap();
+        let b = Instruction::Symbol('B'.into());
+        assert_eq!(contexts.production(&b), Some(&contexts.rules()[1]));
+        assert_eq!(contexts.production_at(&[2, 0]), Some(&contexts.rules()[0]));
+        assert_eq!(contexts.production_at(&[3]), Some(&contexts.rules()[0]));
+        assert_eq!(contexts.production_at(&[5]), None);
+        assert_eq!(contexts.production_at(&[2]), None);
+        assert_eq!(contexts.production_at(&[9]), None);
+
+        assert!(lsys.check_unambiguous().is_err());
+        let lsys = LSystem::from_str("A; A -> B; B -> B; A : 1 -> C; A(x) -> A;").unwrap();
+        assert!(lsys.check_unambiguous().is_ok());
+    }
+
+    #[test]
+    fn derivable_words_are_found() {
+        let mut lsys = LSystem::from_str("A; A -> AB; A -> BA;").unwrap();
+        let word = |w| Word::parse(w).unwrap().into_instructions();
+        assert_eq!(lsys.derives(&word("AB"), 1, 100), Some(true));
+        assert_eq!(lsys.derives(&word("BA"), 1, 100), Some(false));
+        lsys.set_selection(RuleSelection::Uniform);
+        assert_eq!(lsys.derives(&word("BBA"), 2, 100), Some(true));
+        assert_eq!(lsys.derives(&word("AAB"), 2, 100), Some(false));
+        assert_eq!(lsys.derives(&word("A"), 0, 100), Some(true));
+
+        let lsys = LSystem::from_str("AAAAAAAAAAAA; A -> B; A -> C;").unwrap();
+        let mut random = lsys.clone();
+        random.set_selection(RuleSelection::Uniform);
+        assert_eq!(random.derives(&word("BBBBBBBBBBBB"), 1, 100), None);
+        assert_eq!(lsys.derives(&word("BBBBBBBBBBBB"), 1, 100), Some(true));
+    }
+
+    #[test]
+    fn definitions_are_expanded() {
+        let lsys = LSystem::from_str(
+            "let turn = +F-F; let turns = turn^2 [turn]; X turns; X -> F turnsX; A(x) -> A(x)turn;",
+        )
+        .unwrap();
+        assert_eq!(
+            lsys.to_string(),
+            "X+F-F+F-F[+F-F]; X -> F+F-F+F-F[+F-F]X; A(x) -> A(x)+F-F;"
+        );
+        assert!(LSystem::from_str("let turn = F(x); F;").is_err());
+        let doubling: String = (0..64)
+            .map(|i| format!("let a{} = a{}a{};", i + 1, i, i))
+            .collect();
+        assert!(LSystem::from_str(&format!("let a0 = F; {} F;", doubling)).is_err());
+    }
+
+    #[test]
+    fn repetitions_are_written_out() {
+        let lsys = LSystem::from_str("F^3; F -> F(1)^2[+F]^2G^0^;").unwrap();
+        assert_eq!(lsys.to_string(), "FFF; F -> F(1)F(1)[+F][+F]^;");
+        assert_eq!(
+            LSystem::from_str("F; F -> G^0;").unwrap().to_string(),
+            "F; F -> ;"
+        );
+        assert!(LSystem::from_str(&format!("F^{};", MAX_REPEAT + 1)).is_err());
+        assert!(LSystem::from_str("F^99999999999999999999999;").is_err());
+    }
+
+    #[test]
+    fn random_selection_uses_every_rule() {
+        let mut lsys =
+            LSystem::from_str("AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA; A -> B; A -> C;").unwrap();
+        lsys.set_selection(RuleSelection::Uniform);
+        let word = lsys.nth(1).unwrap();
+        assert!(word.contains(&Instruction::Symbol('B'.into())));
+        assert!(word.contains(&Instruction::Symbol('C'.into())));
+    }
+
+    #[test]
+    fn probabilities_are_checked() {
+        let error = LSystem::from_str("F; F -0.6-> F+F; F -0.6-> F-F;").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "the rules for 'F' have probabilities adding up to 1.2, more than 1"
+        );
+        let error = LSystem::from_str("F; F -0-> F+F; F -> F-F;").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "expected a positive, finite weight at line 1, col 7, found `0->`"
+        );
+        // relative weights, and rules that never compete
+        assert!(LSystem::from_str("F; F -2-> F+F; F -3-> F-F;").is_ok());
+        assert!(LSystem::from_str("A(1); A(x) : x > 0 -0.6-> B; A(x) : x < 0 -0.6-> C;").is_ok());
+        assert!(LSystem::from_str("A(1); A -0.6-> B; A(x) -0.6-> C; A(x, y) -0.6-> D;").is_ok());
+        // a module's rules compete among themselves, not with the plain symbol's
+        assert!(LSystem::from_str("A(1); A -2-> B; A(x) -0.6-> C; A(x) -0.6-> D;").is_err());
+
+        let mut lsys = LSystem::from_str("F; F -0.2-> F+F; F -0.3-> F-F; G -4-> G;").unwrap();
+        lsys.normalize_weights();
+        let weights: Vec<_> = lsys.rules().iter().map(Rule::weight).collect();
+        assert_eq!(weights, [0.4, 0.6, 1.0]);
+    }
+
+    #[test]
+    fn weights_may_be_parenthesized() {
+        let plain = LSystem::from_str("F; F -0.33-> FF; F -0.67-> F[+F];").unwrap();
+        let parenthesized = LSystem::from_str("F; F -(0.33)-> FF; F -( 0.67 )-> F[+F];");
+        assert_eq!(parenthesized.unwrap(), plain);
+        assert_eq!(plain.selection(), RuleSelection::Weighted);
+
+        let error = |input: &str| LSystem::from_str(input).unwrap_err().to_string();
+        assert_eq!(
+            error("F; F -(-1)-> FF;"),
+            "expected a positive, finite weight at line 1, col 8, found `-1)->`"
+        );
+        assert_eq!(
+            error("F; F -(1e40)-> FF;"),
+            "expected a positive, finite weight at line 1, col 8, found `1e40)->`"
+        );
+        assert_eq!(
+            error("F; F -(x)-> FF;"),
+            "expected a weight at line 1, col 8, found `x)->`"
+        );
+        assert_eq!(
+            error("F; F -(0.5-> FF;"),
+            "expected `)` at line 1, col 11, found `->`"
+        );
+    }
+
+    #[test]
+    fn delayed_rules_wait_for_their_symbols_to_age() {
+        let mut lsys = LSystem::from_str("A; A@2 -> B[A]A;").unwrap();
+        assert_eq!(lsys.rules()[0].delay(), 2);
+        assert_eq!(lsys.to_string(), "A; A@2 -> B[A]A;");
+        let words: Vec<_> = (&mut lsys).take(6).map(Word::from).collect();
+        let words: Vec<_> = words.iter().map(Word::to_string).collect();
+        assert_eq!(words, ["A", "A", "A", "B[A]A", "B[A]A", "B[A]A"]);
+        // a symbol a rule rewrites into itself is produced anew
+        let mut reborn = LSystem::from_str("A; A@2 -> B; A -> A;").unwrap();
+        assert_eq!(reborn.nth(5), Some(vec![Instruction::Symbol('A'.into())]));
+        assert_eq!(lsys.derives(&[], 1, 100), None);
+
+        // an undelayed system rewrites the same way without keeping ages
+        let mut plain = LSystem::from_str("A; A -> B[A]A;").unwrap();
+        plain.nth(3);
+        assert!(plain.annotated().is_none());
+    }
+
+    #[test]
+    fn bound_symbols_are_spliced_with_their_systems() {
+        let mut trunk = LSystem::from_str("T; T -> F[+L]T;").unwrap();
+        let mut leaf = LSystem::from_str("X; X -> F[-X]+X;").unwrap();
+        leaf.bind('X'.into(), LSystem::from_str("G;").unwrap(), 0);
+        trunk.bind('L'.into(), leaf, 2);
+        trunk.nth(1);
+
+        let word = Word::from(trunk.spliced()).to_string();
+        assert_eq!(word, "F[+F[-F[-G]+G]+F[-G]+G]F[+F[-F[-G]+G]+F[-G]+G]T");
+        assert_eq!(trunk.word().len(), 5);
+
+        trunk.unbind(&'L'.into());
+        assert_eq!(&trunk.spliced(), trunk.word());
+    }
+
+    #[test]
+    fn deep_branches_stop_growing() {
+        let mut lsys = LSystem::from_str("X; X -> F[X]X; F -> FF;").unwrap();
+        lsys.set_max_depth(Some(1), 0);
+        let word = Word::from(lsys.nth(3).unwrap()).to_string();
+        assert_eq!(word, "FFFF[FF[X]F[X]X]FF[F[X]X]F[X]X");
+        assert_eq!(lsys.max_depth(), Some((1, 0)));
+        let mut whole = LSystem::from_str("X; X -> F[X]X; F -> FF;").unwrap();
+        assert_ne!(whole.nth(3).map(Word::from).unwrap().to_string(), word);
+        assert_eq!(lsys.derives(&[], 1, 100), Some(false));
+
+        // twigs past the depth grow a little further
+        let mut lsys = LSystem::from_str("X; X -> F[X]X; F -> FF;").unwrap();
+        lsys.set_max_depth(Some(1), 1);
+        let word = Word::from(lsys.nth(3).unwrap()).to_string();
+        assert_eq!(word, "FFFF[FF[F[X]X]F[X]X]FF[F[X]X]F[X]X");
+        let word = Word::from(lsys.next().unwrap()).to_string();
+        assert!(
+            word.starts_with("FFFFFFFF[FFFF[F[X]X]FF[F[X]X]F[X]X]"),
+            "{word}"
+        );
+        assert_eq!(lsys.derives(&[], 1, 100), None);
+    }
+
+    #[test]
+    fn injected_signals_travel_along_the_word() {
+        let signal = || Instruction::Symbol(Symbol::from('S'));
+        let carry = |left: &str, right: &str| {
+            let mut rule = Rule::parse_all("F -> S;").unwrap().remove(0);
+            rule.set_context(
+                left.chars().map(Symbol::from).collect(),
+                right.chars().map(Symbol::from).collect(),
+            );
+            rule
+        };
+        let words = |lsys: &mut LSystem| -> Vec<String> {
+            lsys.take(3)
+                .map(|word| Word::from(word).to_string())
+                .collect()
+        };
+
+        let mut acropetal = LSystem::from_str("F[F]F; S -> F;").unwrap();
+        acropetal.add_rule(carry("S", ""));
+        acropetal.inject(0, signal()).unwrap();
+        assert_eq!(words(&mut acropetal), ["SF[F]F", "FS[F]F", "FF[S]S"]);
+
+        let mut basipetal = LSystem::from_str("FF[F]F; S -> F;").unwrap();
+        basipetal.add_rule(carry("", "S"));
+        basipetal.set_annotating(true);
+        basipetal.inject(4, signal()).unwrap();
+        assert_eq!(words(&mut basipetal), ["FF[F]FS", "FF[F]SF", "FS[F]FF"]);
+        assert_eq!(
+            basipetal.inject(9, signal()).unwrap_err().to_string(),
+            "the word has only 5 symbols"
+        );
+        basipetal.inject(2, signal()).unwrap();
+        assert_eq!(Word::from(basipetal.word().clone()).to_string(), "SF[SF]FF");
+        assert_eq!(basipetal.annotated().unwrap().count(), 6);
+    }
+
+    #[test]
+    fn constants_are_declared_in_the_grammar() {
+        let lsys = LSystem::from_str("X; #const +-[] G; X -> F[+X]G-X; F -> FF;").unwrap();
+        let constants: Vec<_> = lsys.constants().iter().map(Symbol::to_string).collect();
+        assert_eq!(constants, ["+", "-", "G"]);
+        assert_eq!(lsys.to_string(), "X; #const +-G; X -> F[+X]G-X; F -> FF;");
+        assert!(lsys.validate().is_empty());
+        let bare = LSystem::from_str("X; const + - [ ] G; X -> F[+X]G-X; F -> FF;").unwrap();
+        assert_eq!(bare, lsys);
+        let rule = LSystem::from_str("const; const < X -> F;").unwrap();
+        assert!(rule.constants().is_empty());
+
+        let error = LSystem::from_str("F; #const +; + -> -;").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "'+' is a constant, but a rule rewrites it"
+        );
+        let mut lsys = LSystem::from_str("F; F -> F+F;").unwrap();
+        lsys.set_constants(['F'.into()]);
+        assert_eq!(
+            lsys.validate(),
+            [Issue::RewritesConstant(RuleRef {
+                table: None,
+                rule: 0
+            })]
+        );
+    }
+
+    #[test]
+    fn grammars_may_be_annotated() {
+        let annotated = "
+            # a plant
+            X; // the axiom
+            #const +-; # turns
+            X -> F[+X]F[-X]+X; // grows
+            F -> FF; #F -> F
+        ";
+        let lsys = LSystem::from_str(annotated).unwrap();
+        let plain = LSystem::from_str("X; #const +-; X -> F[+X]F[-X]+X; F -> FF;").unwrap();
+        assert_eq!(lsys, plain);
+        assert_eq!(lsys.constants().len(), 2);
+
+        let rules = Rule::parse_all("A -> /B; // not /C\n# B -> C;").unwrap();
+        assert_eq!(rules.len(), 1);
+        assert_eq!(Word::from(rules[0].to().clone()).to_string(), "/B");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn systems_are_stored_and_restored() {
+        let grammar = "X(3); #ignore +; X(a) : a > 1 -0.5-> F[+X(a-1)]X(a-1); X(a) -0.5-> FX(a);";
+        let mut lsys = LSystem::from_str(grammar).unwrap();
+        lsys.add_table("other", Rule::parse_all("F -> FF;").unwrap());
+        lsys.set_tracing(true);
+        lsys.nth(2);
+
+        let stored = ron::to_string(&lsys).unwrap();
+        let mut restored: LSystem = ron::from_str(&stored).unwrap();
+        assert_eq!(restored, lsys);
+        assert_eq!(restored.trace(), lsys.trace());
+        assert_eq!(restored.nth(3), lsys.nth(3));
+
+        let word: Word =
+            ron::from_str(&ron::to_string(&Word::parse("F(1)[+🌱]$Leaf").unwrap()).unwrap())
+                .unwrap();
+        assert_eq!(word.to_string(), "F(1)[+🌱]$Leaf");
+        assert!(ron::from_str::<Symbol>("\"FF\"").is_err());
+
+        // what was stored about the word must fit the word
+        let mut lsys = LSystem::from_str("F; F -> FF;").unwrap();
+        lsys.nth(1);
+        lsys.births = Some(vec![0]);
+        let error = ron::from_str::<LSystem>(&ron::to_string(&lsys).unwrap()).unwrap_err();
+        assert!(
+            error.to_string().contains("a birth for every symbol"),
+            "{error}"
+        );
+        lsys.births = None;
+        lsys.table = Some(2);
+        assert!(ron::from_str::<LSystem>(&ron::to_string(&lsys).unwrap()).is_err());
+    }
+
+    #[test]
+    fn parse_errors_outlive_the_input() {
+        fn load(grammar: String) -> Result<LSystem, LsysError> {
+            grammar.parse()
+        }
+        let error = load("F; #const F; F -> FF;".to_string()).unwrap_err();
+        let parsed: LSystem = "F; F -> FF;".parse().unwrap();
+        assert_eq!(parsed, LSystem::from_str("F; F -> FF;").unwrap());
+        let boxed: Box<dyn std::error::Error + 'static> = Box::new(error);
+        assert_eq!(
+            boxed.to_string(),
+            "'F' is a constant, but a rule rewrites it"
+        );
+    }
+
+    #[test]
+    fn grammars_are_loaded_from_files() {
+        let path = std::env::temp_dir().join(format!("lsys-grammar-{}", std::process::id()));
+        std::fs::write(&path, "# a plant\nX;\nX -> F[+X]F[-X]+X;\n").unwrap();
+        let lsys = LSystem::from_file(&path).unwrap();
+        assert_eq!(lsys, LSystem::from_str("X; X -> F[+X]F[-X]+X;").unwrap());
+
+        std::fs::write(&path, "# a plant\nX;\n  X -> F(x;\n").unwrap();
+        let error = LSystem::from_file(&path).unwrap_err();
+        assert!(matches!(
+            error,
+            LoadError::Parse {
+                location: Some((3, 10)),
+                ..
+            }
+        ));
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "{}: expected one of the parameters of the rule's head at line 3, col 10, found `x;`",
+                path.display()
+            )
+        );
+
+        std::fs::remove_file(&path).unwrap();
+        let error = LSystem::from_file(&path).unwrap_err();
+        assert!(matches!(error, LoadError::Io { .. }));
+        assert!(error.to_string().starts_with(&path.display().to_string()));
+    }
+
+    #[test]
+    fn parse_errors_say_where_and_what() {
+        let error = |grammar: &str| LSystem::from_str(grammar).unwrap_err().to_string();
+        assert_eq!(error("X(1;"), "expected `)` at line 1, col 4, found `;`");
+        assert_eq!(error("F\n[+F;"), "expected `]` at line 2, col 4, found `;`");
+        let error = LSystem::from_str("F;\n  F -> F(y);").unwrap_err();
+        assert_eq!(error.offset(), Some(12));
+        assert_eq!(
+            error.to_string(),
+            "expected one of the parameters of the rule's head at line 2, col 10, found `y);`"
+        );
+
+        let error = |rules: &str| Rule::parse_all(rules).unwrap_err().to_string();
+        assert_eq!(
+            error("A -> B;\nC D;"),
+            "expected `->` after the rule's head at line 2, col 3, found `D;`"
+        );
+        assert_eq!(
+            error("A -0.4 B;"),
+            "expected `->` at line 1, col 8, found `B;`"
+        );
+        assert_eq!(
+            Word::parse("F[F").unwrap_err().to_string(),
+            "expected `]` at line 1, col 4, found the end"
+        );
+        assert_eq!(
+            Word::parse("F]F").unwrap_err().to_string(),
+            "unexpected `]` at line 1, col 2"
+        );
+    }
+
+    #[test]
+    fn long_expressions_stay_shallow() {
+        // the tree of a long chain is as deep as the chain, so past MAX_NESTING operators, at the
+        // 128th `+`, it is an error
+        let chain = format!("F(1); F(x) -> F(x{});", "+x".repeat(1 << 20));
+        let error = LSystem::from_str(&chain).unwrap_err();
+        assert_eq!(error.offset(), Some(15 + 2 * MAX_NESTING));
+        assert!(error
+            .to_string()
+            .starts_with("expected operators nested at most 128 deep"));
+        let negations = format!("F(1); F(x) -> F({}x);", "-".repeat(100_000));
+        assert!(matches!(
+            LSystem::from_str(&negations).unwrap_err(),
+            LsysError::ParseError { offset: 16, .. }
+        ));
+
+        // a megabyte of shallow ones parses, derives and drops
+        let module = format!("G(x{})", "+x".repeat(100));
+        let rule = format!("F(1); F(x) -> {};", module.repeat(5000));
+        assert!(rule.len() > 1_000_000);
+        let mut lsys = LSystem::from_str(&rule).unwrap();
+        let word = lsys.nth(1).unwrap();
+        assert_eq!(word.len(), 5000);
+        assert_eq!(
+            word[0],
+            Instruction::Module('G'.into(), Params(vec![101.0]))
+        );
+        drop(lsys);
+    }
+
+    #[test]
+    fn parse_errors_are_told_apart() {
+        fn shareable<E: std::error::Error + Send + Sync + 'static>(_: &E) {}
+        let error = LSystem::from_str("F[+F;").unwrap_err();
+        shareable(&error);
+        assert!(matches!(
+            error,
+            LsysError::UnbalancedBracket { offset: 4, .. }
+        ));
+        assert!(matches!(
+            Word::parse("F]").unwrap_err(),
+            LsysError::UnbalancedBracket { offset: 1, .. }
+        ));
+        assert!(matches!(
+            LSystem::from_str(&format!("{};", "[".repeat(200))).unwrap_err(),
+            LsysError::TooDeep { offset: 128 }
+        ));
+        assert!(matches!(
+            LSystem::from_str("X(1,)").unwrap_err(),
+            LsysError::ParseError { offset: 4, .. }
+        ));
+        assert_eq!(
+            LSystem::from_str(" ; F -> FF;").unwrap_err(),
+            LsysError::EmptyAxiom
+        );
+        assert_eq!(
+            LSystem::from_str("F; #const +; + -> -;").unwrap_err(),
+            LsysError::RewritesConstant('+'.into())
+        );
+        assert!(matches!(
+            LSystem::from_str("F; F -0.6-> F; F -0.7-> FF;").unwrap_err(),
+            LsysError::Weights(_)
+        ));
+    }
+
+    #[test]
+    fn leftovers_are_errors_unless_lenient() {
+        let error = LSystem::from_str("F; A -> B; C D;").unwrap_err();
+        assert_eq!(error.line_column("F; A -> B; C D;"), Some((1, 14)));
+        let error = LSystem::from_str("F; A -> B;\nA -> B]C;").unwrap_err();
+        assert!(matches!(
+            error,
+            LsysError::UnbalancedBracket { offset: 17, .. }
+        ));
+        assert!(LSystem::from_str("F; A -> B; ->").is_err());
+
+        let lenient = LSystem::from_str_lenient("F; A -> B; C D;").unwrap();
+        assert_eq!(lenient, LSystem::from_str("F; A -> B;").unwrap());
+    }
+
+    #[test]
+    fn zero_weight_is_never_selected() {
+        let mut lsys = LSystem::from_str("AAAAAAAAAAAAAAAA; A -> B; A -> C;").unwrap();
+        lsys.rules_mut()[0].set_weight(0.0);
+        lsys.set_selection(RuleSelection::Weighted);
+        assert!(lsys
+            .nth(1)
+            .unwrap()
+            .iter()
+            .all(|i| *i == Instruction::Symbol('C'.into())));
+    }
+
+    #[test]
+    fn blocked_queries_stop_their_branch() {
+        use crate::geometry::Recorder;
+        use crate::graphics::TurtleConfig;
+
+        // the right branch turns back onto the trunk, the left one grows into free space
+        let mut lsys = LSystem::from_str("FFF[-FF?A]F[++++F?A]; A -> FA;").unwrap();
+        let mut env = Environment::new(0.5);
+        let turtle = TurtleConfig::default().delta_ang(std::f32::consts::FRAC_PI_4);
+        turtle
+            .create_turtle()
+            .draw_in(&mut Recorder::new(), lsys.word(), &mut env)
+            .unwrap();
+        assert_eq!(env.queries(), &[false, true]);
+
+        lsys.step_in(&env);
+        let expected = LSystem::from_str("FFF[-FF?FA]F[++++F?A];").unwrap();
+        assert_eq!(lsys.word(), &expected.axiom);
+    }
+
+    #[test]
+    fn responses_become_module_values() {
+        use crate::geometry::Recorder;
+        use crate::graphics::TurtleConfig;
+
+        // the trunk's apex ends up two steps out, the one turned back on it only one
+        let mut lsys = LSystem::from_str("FFP[++++FP]; P(x, y) : x * x + y * y > 3 -> X;").unwrap();
+        let mut env = Environment::new(0.5).position_query('P');
+        let turtle = TurtleConfig::default().delta_ang(std::f32::consts::FRAC_PI_4);
+        turtle
+            .create_turtle()
+            .draw_in(&mut Recorder::new(), lsys.word(), &mut env)
+            .unwrap();
+        assert_eq!(env.responses().len(), 2);
+
+        lsys.step_in(&env);
+        let word = lsys.word();
+        assert_eq!(word[2], Instruction::Symbol('X'.into()));
+        let branch = match &word[3] {
+            Instruction::Branch(branch) => branch,
+            _ => panic!("the branch is gone"),
+        };
+        let values = Params(env.responses()[1].clone());
+        assert_eq!(branch[5], Instruction::Module('P'.into(), values));
+    }
+
+    #[test]
+    fn deep_nesting_is_an_error() {
+        let nested = |depth| format!("{}F{};", "[".repeat(depth), "]".repeat(depth));
+        assert!(LSystem::from_str(&nested(MAX_NESTING)).is_ok());
+        assert!(LSystem::from_str(&nested(MAX_NESTING + 1)).is_err());
+        assert!(LSystem::from_str(&"[".repeat(1_000_000)).is_err());
+    }
+
+    #[test]
+    fn long_rules_parse() {
+        let input = format!("F; F -> {};", "F+G-".repeat(250_000));
+        assert_eq!(
+            LSystem::from_str(&input).unwrap().rules()[0].to().len(),
+            1_000_000
+        );
+    }
+
+    #[test]
+    fn arbitrary_input_never_panics() {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let pieces = [
+            "F", "G", "+", "-", "[", "]", ";", "->", "-", ">", " ", "\t", "\n", "\r", "é", "🌱",
+            "\u{200b}", "\u{301}", "\0", "$", "Ab",
+        ];
+        let mut rng = StdRng::seed_from_u64(221);
+        for _ in 0..20_000 {
+            let len = rng.gen_range(0..40);
+            let input: String = (0..len)
+                .map(|_| *pieces.choose(&mut rng).unwrap())
+                .collect();
+            if let Ok(mut lsys) = LSystem::from_str(&input) {
+                let _ = lsys.nth(2);
+                let _ = lsys.to_string();
+            };
+        }
+    }
+
+    #[test]
+    fn symbols_may_have_names() {
+        let mut lsys = LSystem::from_str(
+            "$Apex; $Apex -> F[+$Leaf(1)]$Apex $Internode; $Leaf(x) -> $Leaf(x+1);",
+        )
+        .unwrap();
+        let word = lsys.nth(2).unwrap();
+        assert_eq!(
+            Word::from(word.clone()).to_string(),
+            "F[+$Leaf(2)]F[+$Leaf(1)]$Apex$Internode$Internode"
+        );
+        assert_eq!(word[0], Instruction::Symbol('F'.into()));
+        assert_eq!(
+            word.last(),
+            Some(&Instruction::Symbol(Symbol::named("Internode")))
+        );
+
+        let printed = "$Apex F$ A; #ignore $Leaf$Bud; $Apex -> $Apex A$Bud;";
+        let lsys = LSystem::from_str(printed).unwrap();
+        assert_eq!(lsys.axiom().len(), 4);
+        assert_eq!(lsys.to_string(), printed);
+        assert_eq!(LSystem::from_str(&lsys.to_string()).unwrap(), lsys);
+
+        let lsys = LSystem::from_str("$F; $ -> $$;").unwrap();
+        assert_eq!(
+            lsys.axiom(),
+            &[
+                Instruction::Symbol('$'.into()),
+                Instruction::Symbol('F'.into())
+            ]
+        );
+        assert_eq!(LSystem::from_str(&lsys.to_string()).unwrap(), lsys);
+    }
+
+    #[test]
+    fn grammars_declare_how_they_are_drawn() {
+        use crate::graphics::TurtleConfig;
+
+        let lsys = LSystem::from_str("angle 25.7;\nstep 4;\nX; X -> F[+X]F[-X]+X;").unwrap();
+        assert_eq!((lsys.angle(), lsys.step_size()), (Some(25.7), Some(4.0)));
+        assert_eq!(
+            lsys.to_string(),
+            "angle 25.7; step 4; X; X -> F[+X]F[-X]+X;"
+        );
+        assert_eq!(LSystem::from_str(&lsys.to_string()).unwrap(), lsys);
+        let turtle = TurtleConfig::default().declared_by(&lsys);
+        assert_eq!(
+            (turtle.delta_ang, turtle.stepsize),
+            (25.7f32.to_radians(), 4.0)
+        );
+
+        let plain = LSystem::from_str("let angle = F; angle step;").unwrap();
+        assert_eq!((plain.angle(), plain.step_size()), (None, None));
+        assert_eq!(plain.axiom().len(), 5);
+        let turtle = TurtleConfig::default().declared_by(&plain);
+        assert_eq!(turtle.stepsize, 1.0);
+
+        for (grammar, error) in [
+            (
+                "angle NaN; F;",
+                "expected a positive, finite angle at line 1, col 7, found `NaN;`",
+            ),
+            (
+                "step inf; F;",
+                "expected a positive, finite step at line 1, col 6, found `inf;`",
+            ),
+            (
+                "step 0; F;",
+                "expected a positive, finite step at line 1, col 6, found `0;`",
+            ),
+            (
+                "angle -5; F;",
+                "expected a positive, finite angle at line 1, col 7, found `-5;`",
+            ),
+            (
+                "angle abc; F;",
+                "expected a number at line 1, col 7, found `abc;`",
+            ),
+            ("angle 25 F;", "expected `;` at line 1, col 10, found `F;`"),
+        ] {
+            assert_eq!(LSystem::from_str(grammar).unwrap_err().to_string(), error);
+        }
+    }
+
+    #[test]
+    fn grammars_may_name_their_sections() {
+        let plain = LSystem::from_str("X; X -> F[+X]F[-X]+X; F -> FF;").unwrap();
+        for sectioned in [
+            "axiom: X\nrules:\n  X -> F[+X]F[-X]+X;\n  F -> FF;",
+            "axiom: X; rules: X -> F[+X]F[-X]+X; F -> FF;",
+            "axiom:X rules:X -> F[+X]F[-X]+X; F -> FF;",
+            "X; rules: X -> F[+X]F[-X]+X; F -> FF;",
+        ] {
+            assert_eq!(
+                LSystem::from_str(sectioned).unwrap(),
+                plain,
+                "{}",
+                sectioned
+            );
+        }
+        let defined = LSystem::from_str("angle 30; let b = [+X]; axiom: Xb rules: X -> X;");
+        assert_eq!(defined.unwrap().axiom().len(), 2);
+
+        let error = LSystem::from_str("axiom: X(1,)\nrules: X -> F;").unwrap_err();
+        assert_eq!(error.offset(), Some(11));
+        assert_eq!(
+            LSystem::from_str("axiom: rules: X -> F;").unwrap_err(),
+            LsysError::EmptyAxiom
+        );
+    }
+
+    #[test]
+    fn contexts_are_written_around_the_head() {
+        let words = |grammar: &str, generations: usize| -> Vec<String> {
+            let lsys = LSystem::from_str(grammar).unwrap();
+            lsys.take(generations)
+                .map(|word| Word::from(word).to_string())
+                .collect()
+        };
+        assert_eq!(words("BAAA; B < A -> B; B -> ;", 3), ["BAAA", "BAA", "BA"]);
+        assert_eq!(words("ABA; A > B -> C;", 2), ["ABA", "CBA"]);
+        assert_eq!(words("F+A; #ignore +; F < A -> X;", 2), ["F+A", "F+X"]);
+        assert_eq!(
+            LSystem::from_str("A; * < A > * -> B;").unwrap(),
+            LSystem::from_str("A; A -> B;").unwrap()
+        );
+
+        let printed = "A(2)B; $Bud C < A(x) > B : x > 1 -> A(x-1); A > BC -> D;";
+        let lsys = LSystem::from_str(printed).unwrap();
+        assert_eq!(lsys.to_string(), printed);
+        let (left, right) = lsys.rules()[0].context();
+        assert_eq!((left.len(), right), (2, &['B'.into()][..]));
+        assert_eq!(lsys.rules()[1].context().1.len(), 2);
+        assert!(LSystem::from_str("A; A > -> B;").is_err());
+
+        // `<` in a successor is a symbol, not the end of a left context
+        let angled = LSystem::from_str("F; F -> F<F; G -> G<;").unwrap();
+        assert!(angled
+            .rules()
+            .iter()
+            .all(|rule| rule.context().0.is_empty()));
+        assert_eq!(angled.to_string(), "F; F -> F<F; G -> G<;");
+        assert_eq!(
+            Rule::parse_all("A < B -> B<A;").unwrap()[0]
+                .context()
+                .0
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn rules_may_use_other_arrows() {
+        let plain = LSystem::from_str("X; X -> F[+X]F[-X]+X; F -> FF;").unwrap();
+        for arrow in ["→", "=>", "="] {
+            let grammar = format!("X; X {0} F[+X]F[-X]+X; F{0}FF;", arrow);
+            assert_eq!(LSystem::from_str(&grammar).unwrap(), plain);
+        }
+        let guarded = LSystem::from_str("A(3); A(x) : x >= 1 => A(x-1)B;").unwrap();
+        assert_eq!(guarded.to_string(), "A(3); A(x) : x >= 1 -> A(x-1)B;");
+        // not a rule rewriting A(x) into `1 -> B` when x holds
+        let error = LSystem::from_str("A(3); A(x) : x = 1 -> B;").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "expected `->` after the rule's head at line 1, col 16, found `=`"
+        );
+    }
+
+    #[test]
+    fn any_whitespace_separates() {
+        let lsys =
+            LSystem::from_str("F G;\r\nF\u{a0}->\u{3000}FG;\r\n\u{2003}G -> G;\r\n").unwrap();
+        assert_eq!(lsys.to_string(), "FG; F -> FG; G -> G;");
+    }
+
+    #[test]
+    fn grapheme_symbols() {
+        let mut lsys = LSystem::from_str("🌱; 🌱 -> 🌿[🌱]e\u{301}; e\u{301} -> 👍🏽;").unwrap();
+        assert_eq!(
+            lsys.rules()[1].from(),
+            &Instruction::Symbol(Symbol::new("e\u{301}"))
+        );
+        assert_eq!(
+            lsys.nth(2).unwrap(),
+            LSystem::from_str("🌿[🌿[🌱]e\u{301}]👍🏽;").unwrap().axiom
+        );
+    }
+
+    #[test]
+    fn words_of_any_letter_are_rewritten() {
+        #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+        enum Organ {
+            Apex,
+            Internode(u8),
+        }
+        impl Letter for Organ {}
+
+        use Instruction::Branch;
+        use Organ::{Apex, Internode};
+        let part = Instruction::Symbol;
+        let mut lsys = LSystem::new(
+            vec![part(Apex)],
+            vec![
+                Rule::new(
+                    part(Apex),
+                    vec![part(Internode(0)), Branch(vec![part(Apex)]), part(Apex)],
+                ),
+                Rule::new(part(Internode(0)), vec![part(Internode(1))]),
+            ],
+        );
+        let mut aging = Rule::new(part(Internode(1)), vec![part(Internode(2))]);
+        aging.set_context(vec![Internode(1)], vec![]);
+        lsys.add_rule(aging);
+
+        assert_eq!(
+            lsys.nth(2).unwrap(),
+            [
+                part(Internode(1)),
+                Branch(vec![
+                    part(Internode(0)),
+                    Branch(vec![part(Apex)]),
+                    part(Apex)
+                ]),
+                part(Internode(0)),
+                Branch(vec![part(Apex)]),
+                part(Apex),
+            ]
+        );
+        // only an internode after an older one ages further
+        let word = lsys.nth(1).unwrap();
+        assert_eq!(
+            (&word[0], &word[2]),
+            (&part(Internode(1)), &part(Internode(2)))
+        );
+    }
+
+    #[test]
+    fn letters_need_not_be_shared_between_threads() {
+        // whatever features are on, a letter that can't be sent to another thread still works
+        #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+        struct Part(std::rc::Rc<str>);
+        impl Letter for Part {}
+
+        let part = |name: &str| Instruction::Symbol(Part(name.into()));
+        let mut lsys = LSystem::new(
+            vec![part("bud")],
+            vec![Rule::new(part("bud"), vec![part("leaf"), part("bud")])],
+        );
+        assert_eq!(
+            lsys.nth(2).unwrap(),
+            [part("leaf"), part("leaf"), part("bud")]
+        );
+    }
+
+    #[test]
+    fn branch_streams_are_independent() {
+        let mut lsys = LSystem::from_str("AAAA[AAAA][AAAA[AAAA]]; A -> B; A -> C;").unwrap();
+        lsys.set_selection(RuleSelection::Uniform);
+        lsys.set_streams(RandomStreams::PerBranch);
+        lsys.set_seed(5);
+
+        let first = lsys.rewrite_subtree(&[4]).unwrap();
+        let nested = lsys.rewrite_subtree(&[5, 4]).unwrap();
+        assert_ne!(first, nested);
+        assert_eq!(lsys.rewrite_subtree(&[0]), None);
+
+        let next = lsys.nth(1).unwrap();
+        assert_eq!(next[4], Instruction::Branch(first));
+        match &next[5] {
+            Instruction::Branch(inner) => {
+                assert_eq!(inner[4], Instruction::Branch(nested.clone()))
+            }
+            _ => panic!("expected a branch"),
+        }
+
+        // the same branch at the same path, with different surroundings, rewrites the same way
+        let mut other = LSystem::from_str("CCCC[BB][AAAA[AAAA]]; A -> B; A -> C;").unwrap();
+        other.set_selection(RuleSelection::Uniform);
+        other.set_streams(RandomStreams::PerBranch);
+        other.set_seed(5);
+        assert_eq!(other.rewrite_subtree(&[5, 4]), Some(nested));
+    }
+
+    #[test]
+    fn pruned_branches_leave_their_siblings_alone() {
+        let mut dense = LSystem::from_str("X; X -> [PA][QA][RA][SA]; A -> AB; A -> AC;").unwrap();
+        dense.set_selection(RuleSelection::Uniform);
+        dense.set_streams(RandomStreams::PerBranch);
+        dense.set_seed(3);
+        let mut sparse = dense.clone();
+        sparse.set_dropout(Some(Pruning::uniform(0.5)));
+
+        let branches = |word: Instructions| -> Vec<String> {
+            let word = Word::from(word).to_string();
+            word.split(['[', ']'])
+                .filter(|b| !b.is_empty())
+                .map(String::from)
+                .collect()
+        };
+        let dense = branches(dense.nth(5).unwrap());
+        let sparse = branches(sparse.nth(5).unwrap());
+        assert_eq!(dense.len(), 4);
+        assert!(!sparse.is_empty() && sparse.len() < dense.len());
+        for branch in &sparse {
+            assert!(dense.contains(branch), "{branch} isn't among {dense:?}");
+        }
+    }
+
+    #[test]
+    fn dropout_follows_the_seed() {
+        let mut lsys = LSystem::from_str("F; F -> F[+F][-F]F;").unwrap();
+        let dense = lsys.clone().nth(3).unwrap();
+        lsys.set_dropout(Some(Pruning::uniform(0.5)));
+        let sparse = lsys.clone().nth(3).unwrap();
+
+        assert!(count_symbols(&sparse) < count_symbols(&dense));
+        assert_eq!(lsys.clone().nth(3).unwrap(), sparse);
+        assert_eq!(lsys.expand_memoized(3), sparse);
+        assert_eq!(lsys.predicted_length(3), None);
+        lsys.set_seed(1);
+        assert_ne!(lsys.clone().nth(3).unwrap(), sparse);
+
+        lsys.set_dropout(Some(Pruning::uniform(1.0)));
+        assert_eq!(Word::from(lsys.nth(2).unwrap()).to_string(), "FFFF");
+    }
+
+    #[test]
+    fn reset_repeats_random_derivations() {
+        let mut lsys = LSystem::from_str("AAAAAAAAAAAAAAAA; A -> B; A -> C;").unwrap();
+        lsys.set_selection(RuleSelection::Uniform);
+        let first = lsys.nth(1).unwrap();
+        lsys.reset();
+        assert_eq!(lsys.nth(1).unwrap(), first);
+    }
+
+    #[test]
+    fn injected_generators_repeat_derivations() {
+        let mut lsys = LSystem::from_str("AAAAAAAAAAAAAAAA; A -> B; A -> C;").unwrap();
+        lsys.set_selection(RuleSelection::Uniform);
+        let mut other = lsys.clone();
+        lsys.seed_from(&mut ChaCha12Rng::seed_from_u64(7));
+        other.seed_from(&mut ChaCha12Rng::seed_from_u64(7));
+        assert_eq!(lsys.seed(), other.seed());
+        assert_eq!(lsys.nth(1), other.nth(1));
+    }
+
+    #[test]
+    fn display_round_trips() {
+        let lsys = LSystem::from_str("++++F; F->G[+F][-F]-GF; G->GG;").unwrap();
+        assert_eq!(lsys.to_string(), "++++F; F -> G[+F][-F]-GF; G -> GG;");
+        assert_eq!(LSystem::from_str(&lsys.to_string()).unwrap(), lsys);
+    }
+
+    #[test]
+    fn mutated_rules_still_parse() {
+        let lsys = LSystem::from_str("F; F->F[+F]F[-F]F;").unwrap();
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..50 {
+            let mutated = lsys.mutate(&mut rng, &MutationConfig::new().edits(3));
+            assert_eq!(LSystem::from_str(&mutated.to_string()).unwrap(), mutated);
+        }
+    }
+
+    #[test]
+    fn crossover_splits_at_branches() {
+        let a = LSystem::from_str("F; F -> FF[+F]F; A -> A;").unwrap();
+        let b = LSystem::from_str("F; F -> G[-G]GG; B -> B;").unwrap();
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut children = HashSet::new();
+        for _ in 0..100 {
+            let child = a.crossover(&b, &mut rng);
+            assert_eq!(LSystem::from_str(&child.to_string()).unwrap(), child);
+            let successor = Word::from(child.rules()[0].to().clone()).to_string();
+            let heads = ["", "FF", "FF[+F]", "FF[+F]F"];
+            let tails = ["G[-G]GG", "[-G]GG", "GG", ""];
+            assert!(heads
+                .iter()
+                .any(|h| tails.iter().any(|t| format!("{}{}", h, t) == successor)));
+            children.insert(child.to_string());
+        }
+        assert!(children.len() > 8);
+        assert!(children.iter().any(|c| c.contains("B -> B")));
+        assert!(children.iter().all(|c| c.contains("A -> A")));
+    }
+
+    #[test]
+    fn mutations_stay_bounded() {
+        let lsys = LSystem::from_str("F; F->F[+F]F;").unwrap();
+        let mut rng = StdRng::seed_from_u64(7);
+        let growing = MutationConfig::new()
+            .edits(200)
+            .delete(0.0)
+            .max_length(10)
+            .max_depth(2);
+        let mutated = lsys.mutate(&mut rng, &growing);
+        let to = mutated.rules()[0].to();
+        assert!(count_symbols(to) <= 10 && nesting(to) <= 2);
+        assert_ne!(&mutated, &lsys);
+
+        let still = MutationConfig::new()
+            .swap(0.0)
+            .insert(0.0)
+            .delete(0.0)
+            .branch(0.0);
+        assert_eq!(lsys.mutate(&mut rng, &still), lsys);
     }
 }
@@ -1,54 +1,290 @@
 use std::error::Error;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use draw::*;
+use lsys::dump::Dump;
+use lsys::ensemble::EnsembleConfig;
+use lsys::explore::ExploreConfig;
+use lsys::geometry::Recorder;
+use lsys::graphics::TurtleConfig;
+use lsys::lsystem::{Instruction, LSystem, RuleSelection, Word};
+use lsys::palette::{Color, Palette};
+use lsys::preset;
+use lsys::random::RandomGrammar;
+use lsys::render::{Border, RenderConfig};
+use lsys::theme::Theme;
 
-mod graphics;
-mod lsystem;
+fn theme(name: &str) -> Result<Theme, Box<dyn Error>> {
+    Theme::by_name(name).ok_or_else(|| {
+        let names: Vec<_> = lsys::theme::ALL.iter().map(|t| t.name).collect();
+        format!(
+            "unknown theme '{}', expected one of {}",
+            name,
+            names.join(", ")
+        )
+        .into()
+    })
+}
+
+// `--theme NAME`: the theme's background and stroke, and its palette for the lines
+fn themed(name: &str, render: RenderConfig) -> Result<(RenderConfig, Vec<Color>), Box<dyn Error>> {
+    let theme = theme(name)?;
+    Ok((render.theme(&theme), theme.palette.to_vec()))
+}
+
+// draws as the grammar declares, turning by `--angle` if given, else 30 degrees where the
+// grammar doesn't say
+fn turtle<'a, 'b, 'c, 'd>(lsystem: &LSystem, angle: Option<f32>) -> TurtleConfig<'a, 'b, 'c, 'd> {
+    let turtle = TurtleConfig::default()
+        .delta_ang(30f32.to_radians())
+        .declared_by(lsystem);
+    match angle {
+        Some(angle) => turtle.delta_ang(angle.to_radians()),
+        None => turtle,
+    }
+}
+
+fn usage() -> Box<dyn Error> {
+    "usage: lsys [explore [--count N] [--seed S] [--edits E] [--iterations I] \
+     [--angle DEGREES] [--draw SYMBOLS] [--theme NAME] [--palette FILE] \
+     [--background HEX] [--padding PX] [--border HEX] [--output FILE] GRAMMAR | \
+     dump [--generations N] [--angle DEGREES] [--draw SYMBOLS] [--theme NAME] \
+     [--words-only] [--renders-only] [--compressed] [--output DIRECTORY] GRAMMAR | \
+     ensemble [--count N] [--seed S] [--iterations I] [--angle DEGREES] [--draw SYMBOLS] \
+     [--theme NAME] [--overlay] [--output FILE] GRAMMAR | \
+     random [--seed S] [--alphabet SYMBOLS] [--rules N] [--length L] [--branching P] \
+     [--iterations I] [--angle DEGREES] [--theme NAME] [--output FILE]]"
+        .into()
+}
+
+fn dump(mut args: impl Iterator<Item = String>) -> Result<(), Box<dyn Error>> {
+    let mut dump = Dump::new();
+    let mut render = RenderConfig::default();
+    let mut colors = vec![];
+    let mut generations = 5;
+    let mut angle = None;
+    let mut draw_forward = String::from("F");
+    let mut output = String::from("generations");
+    let mut grammar = None;
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(usage);
+        match arg.as_str() {
+            "--generations" => generations = value()?.parse()?,
+            "--angle" => angle = Some(value()?.parse()?),
+            "--draw" => draw_forward = value()?,
+            "--theme" => (render, colors) = themed(&value()?, render)?,
+            "--words-only" => dump = dump.renders(false),
+            "--renders-only" => dump = dump.words(false),
+            "--compressed" => dump = dump.compressed(true),
+            "--output" => output = value()?,
+            _ if grammar.is_none() && !arg.starts_with("--") => grammar = Some(arg),
+            _ => return Err(usage()),
+        }
+    }
+
+    let grammar = grammar.ok_or_else(usage)?;
+    let lsystem = LSystem::from_str(&grammar)?;
+    let turtle = turtle(&lsystem, angle)
+        .draw_forward(&draw_forward)
+        .colors(colors);
+    let written = dump.write(lsystem, generations, &turtle, &render, &output)?;
+    println!("wrote {} files to {}", written.len(), output);
+
+    Ok(())
+}
+
+fn ensemble(mut args: impl Iterator<Item = String>) -> Result<(), Box<dyn Error>> {
+    let mut config = EnsembleConfig::default();
+    let mut render = RenderConfig::default();
+    let mut colors = vec![];
+    let mut angle = None;
+    let mut draw_forward = String::from("F");
+    let mut overlay = false;
+    let mut output = None;
+    let mut grammar = None;
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(usage);
+        match arg.as_str() {
+            "--count" => config = config.count(value()?.parse()?),
+            "--seed" => config = config.seed(value()?.parse()?),
+            "--iterations" => config = config.iterations(value()?.parse()?),
+            "--angle" => angle = Some(value()?.parse()?),
+            "--draw" => draw_forward = value()?,
+            "--theme" => (render, colors) = themed(&value()?, render)?,
+            "--overlay" => overlay = true,
+            "--output" => output = Some(value()?),
+            _ if grammar.is_none() && !arg.starts_with("--") => grammar = Some(arg),
+            _ => return Err(usage()),
+        }
+    }
+
+    let grammar = grammar.ok_or_else(usage)?;
+    let mut lsystem = LSystem::from_str(&grammar)?;
+    if lsystem.selection() == RuleSelection::First {
+        lsystem.set_selection(RuleSelection::Uniform);
+    }
+    let turtle = turtle(&lsystem, angle)
+        .draw_forward(&draw_forward)
+        .colors(colors);
+
+    // SVG output can't show opacity, so overlays are written as images
+    if overlay {
+        let output = output.unwrap_or_else(|| String::from("ensemble.ppm"));
+        render
+            .padding(10.0)
+            .fit_raster(&config.overlay(&lsystem, &turtle), 600.0, 600.0)
+            .save_ppm(&output)?;
+    } else {
+        let output = output.unwrap_or_else(|| String::from("ensemble.svg"));
+        config.grid(&lsystem, &turtle, &render).save_svg(&output)?;
+    }
+
+    Ok(())
+}
+
+fn explore(mut args: impl Iterator<Item = String>) -> Result<(), Box<dyn Error>> {
+    let mut config = ExploreConfig::default();
+    let mut render = RenderConfig::default();
+    let mut seed = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let mut angle = None;
+    let mut draw_forward = String::from("F");
+    let mut colors = vec![];
+    let mut output = String::from("explore.svg");
+    let mut grammar = None;
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(usage);
+        match arg.as_str() {
+            "--count" => config = config.count(value()?.parse()?),
+            "--seed" => seed = value()?.parse()?,
+            "--edits" => config = config.edits(value()?.parse()?),
+            "--iterations" => config = config.iterations(value()?.parse()?),
+            "--angle" => angle = Some(value()?.parse()?),
+            "--draw" => draw_forward = value()?,
+            "--theme" => (render, colors) = themed(&value()?, render)?,
+            "--palette" => colors = Palette::from_file(value()?)?.colors().to_vec(),
+            "--background" => render = render.background(Some(Color::from_hex(&value()?)?)),
+            "--padding" => render = render.padding(value()?.parse()?),
+            "--border" => {
+                let color = Color::from_hex(&value()?)?;
+                render = render.border(Some(Border { color, width: 1 }));
+            }
+            "--output" => output = value()?,
+            _ if grammar.is_none() && !arg.starts_with("--") => grammar = Some(arg),
+            _ => return Err(usage()),
+        }
+    }
+
+    let grammar = grammar.ok_or_else(usage)?;
+    let base = LSystem::from_str(&grammar)?;
+    let config = config.seed(seed);
+    let turtle = turtle(&base, angle)
+        .draw_forward(&draw_forward)
+        .colors(colors);
+    let variants = config.variants(&base, turtle.angle());
+
+    println!("seed {}", seed);
+    for (i, variant) in variants.iter().enumerate() {
+        println!(
+            "{}: angle {:.2} {}",
+            i,
+            variant.delta_ang.to_degrees(),
+            variant.lsystem
+        );
+    }
+
+    config
+        .thumbnail_grid(&variants, &turtle, &render)
+        .save_svg(&output)?;
 
-use graphics::{Graphics, TurtleConfig};
-use lsystem::LSystem;
+    Ok(())
+}
 
-const WIDTH: f32 = 300.0;
-const HEIGHT: f32 = 300.0;
+// makes up a grammar, prints it and draws it with every symbol of its alphabet drawing forward
+fn random(mut args: impl Iterator<Item = String>) -> Result<(), Box<dyn Error>> {
+    const MAX_SYMBOLS: usize = 1_000_000;
 
-impl Graphics<()> for Canvas {
-    fn draw_line(&mut self, c_0: (f32, f32), c_1: (f32, f32)) -> Result<(), ()> {
-        let x_off = WIDTH / 2.0;
-        let y_off = HEIGHT / 2.0;
+    let mut config = RandomGrammar::new();
+    let mut render = RenderConfig::default();
+    let mut seed = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let mut alphabet = String::from("FX");
+    let mut iterations = 5;
+    let mut angle = 25.0f32;
+    let mut colors = vec![];
+    let mut output = String::from("random.svg");
 
-        let line = Drawing::new()
-            .with_shape(Shape::Line {
-                start: Point::new(c_0.0 + x_off, c_0.1 + y_off),
-                points: vec![shape::LinePoint::Straight {
-                    point: Point::new(c_1.0 + x_off, c_1.1 + y_off),
-                }],
-            })
-            .with_style(Style::stroked(1, Color::black()));
-        self.display_list.add(line);
-        Ok(())
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(usage);
+        match arg.as_str() {
+            "--seed" => seed = value()?.parse()?,
+            "--alphabet" => alphabet = value()?,
+            "--rules" => config = config.rules(value()?.parse()?),
+            "--length" => config = config.max_length(value()?.parse()?),
+            "--branching" => config = config.branching(value()?.parse()?),
+            "--iterations" => iterations = value()?.parse()?,
+            "--angle" => angle = value()?.parse()?,
+            "--theme" => (render, colors) = themed(&value()?, render)?,
+            "--output" => output = value()?,
+            _ => return Err(usage()),
+        }
     }
+
+    let symbols = Word::parse(&alphabet)?
+        .into_instructions()
+        .into_iter()
+        .map(|instr| match instr {
+            Instruction::Symbol(symbol) => Ok(symbol),
+            _ => Err(format!("'{}' is not a plain symbol", instr)),
+        })
+        .collect::<Result<_, _>>()?;
+    let mut lsystem = config.alphabet(symbols).seed(seed).generate();
+    println!("seed {}", seed);
+    println!("{}", lsystem);
+
+    let word = lsystem.nth_bounded(iterations, MAX_SYMBOLS)?;
+    let turtle = TurtleConfig::default()
+        .delta_ang(angle.to_radians())
+        .draw_forward(&alphabet)
+        .colors(colors);
+    let mut recorder = Recorder::new();
+    turtle.create_turtle().draw(&mut recorder, word).unwrap();
+    render
+        .padding(10.0)
+        .fitted(&recorder, 1.0)
+        .save_svg(&output)?;
+
+    Ok(())
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let mut lsys = LSystem::from_str("++++F; F->G[+F][-F]-GF; G->GG;")?;
-    // let mut lsys = LSystem::from_str("F F->F+G G->F-G")?;
+    let mut args = std::env::args().skip(1);
+    if let Some(mode) = args.next() {
+        return match mode.as_str() {
+            "explore" => explore(args),
+            "dump" => dump(args),
+            "ensemble" => ensemble(args),
+            "random" => random(args),
+            _ => Err(usage()),
+        };
+    }
+
+    let lsys = preset::TREE.lsystem();
 
     // let iters = 12;
     let iters = 7;
 
-    let word = lsys.nth(iters).unwrap();
-
-    let turtle = TurtleConfig::default()
-        .stepsize(75.0 * (2f32.powf(-(iters as f32))))
-        // .stepsize(2.0)
-        .delta_ang(std::f32::consts::PI / 6.0)
-        .draw_forward("FG");
+    let word = lsys.expand_memoized(iters);
 
-    let mut canvas = Canvas::new(WIDTH as u32, HEIGHT as u32);
+    let turtle = preset::TREE
+        .turtle()
+        .stepsize(75.0 * (2f32.powf(-(iters as f32))));
 
-    turtle.create_turtle().draw(&mut canvas, word).unwrap();
+    let mut recorder = Recorder::new();
+    turtle.create_turtle().draw(&mut recorder, word).unwrap();
 
-    render::save(&canvas, "thing.svg", SvgRenderer::new()).expect("Failed to save");
+    let surface = RenderConfig::default().padding(10.0).fitted(&recorder, 1.0);
+    surface.save_svg("thing.svg").expect("Failed to save");
 
     Ok(())
 }
@@ -0,0 +1,87 @@
+//! Drawing into a nannou sketch, behind the `nannou` feature.
+
+use ::nannou::prelude::{Draw, Vec2};
+
+use crate::graphics::{Graphics, LineStyle};
+use crate::palette::Color;
+
+/// Draws lines with a nannou `Draw`, usually the one of the current frame. Turtle coordinates
+/// are placed like on a `Surface`, with y pointing down as in the other outputs, relative to
+/// nannou's origin at the center of the window.
+pub struct NannouGraphics<'d> {
+    draw: &'d Draw,
+    stroke: Color,
+    weight: f32,
+    offset: (f32, f32),
+    scale: f32,
+}
+
+impl<'d> NannouGraphics<'d> {
+    pub fn new(draw: &'d Draw) -> Self {
+        Self {
+            draw,
+            stroke: Color::new(0, 0, 0),
+            weight: 1.0,
+            offset: (0.0, 0.0),
+            scale: 1.0,
+        }
+    }
+
+    /// The color of lines drawn without a color of their own.
+    pub fn stroke(self, stroke: Color) -> Self {
+        Self { stroke, ..self }
+    }
+
+    pub fn weight(self, weight: f32) -> Self {
+        Self { weight, ..self }
+    }
+
+    /// Subsequent lines are drawn at `offset + point * scale`.
+    pub fn place(&mut self, offset: (f32, f32), scale: f32) {
+        self.offset = offset;
+        self.scale = scale;
+    }
+
+    fn map(&self, (x, y): (f32, f32)) -> Vec2 {
+        Vec2::new(
+            self.offset.0 + x * self.scale,
+            -(self.offset.1 + y * self.scale),
+        )
+    }
+}
+
+impl Graphics<()> for NannouGraphics<'_> {
+    fn draw_line(&mut self, c_0: (f32, f32), c_1: (f32, f32)) -> Result<(), ()> {
+        self.draw_styled_line(c_0, c_1, LineStyle::default())
+    }
+
+    fn draw_colored_line(
+        &mut self,
+        c_0: (f32, f32),
+        c_1: (f32, f32),
+        color: Color,
+    ) -> Result<(), ()> {
+        let style = LineStyle {
+            color: Some(color),
+            ..LineStyle::default()
+        };
+        self.draw_styled_line(c_0, c_1, style)
+    }
+
+    fn draw_styled_line(
+        &mut self,
+        c_0: (f32, f32),
+        c_1: (f32, f32),
+        style: LineStyle,
+    ) -> Result<(), ()> {
+        let color = style.color.unwrap_or(self.stroke);
+        let alpha = (style.alpha.clamp(0.0, 1.0) * 255.0).round() as u8;
+        self.draw
+            .line()
+            .start(self.map(c_0))
+            .end(self.map(c_1))
+            .weight(self.weight)
+            .srgba_u8(color.r, color.g, color.b, alpha);
+        Ok(())
+    }
+}
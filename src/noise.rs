@@ -0,0 +1,82 @@
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+/// Seeded two dimensional Perlin noise: smooth values in about -1..1 that change over a
+/// distance of about 1 and are zero at whole coordinates.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Perlin {
+    permutation: Vec<u8>,
+}
+
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+// one of eight directions, dotted with the offset from its lattice point
+fn gradient(hash: u8, x: f32, y: f32) -> f32 {
+    match hash & 7 {
+        0 => x + y,
+        1 => x - y,
+        2 => -x + y,
+        3 => -x - y,
+        4 => x,
+        5 => -x,
+        6 => y,
+        _ => -y,
+    }
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Self {
+        let mut permutation: Vec<u8> = (0..=255).collect();
+        permutation.shuffle(&mut StdRng::seed_from_u64(seed));
+        Self { permutation }
+    }
+
+    fn hash(&self, x: i64, y: i64) -> u8 {
+        let p = |i: i64| self.permutation[i.rem_euclid(256) as usize];
+        p(p(x) as i64 + y)
+    }
+
+    pub fn at(&self, x: f32, y: f32) -> f32 {
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (ix, iy) = (x0 as i64, y0 as i64);
+
+        let corner = |dx: i64, dy: i64| {
+            gradient(self.hash(ix + dx, iy + dy), fx - dx as f32, fy - dy as f32)
+        };
+        let (u, v) = (fade(fx), fade(fy));
+        lerp(
+            lerp(corner(0, 0), corner(1, 0), u),
+            lerp(corner(0, 1), corner(1, 1), u),
+            v,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn noise_is_smooth_and_seeded() {
+        let noise = Perlin::new(3);
+        assert_eq!(noise, Perlin::new(3));
+        assert_ne!(noise, Perlin::new(4));
+        assert_eq!(noise.at(5.0, -2.0), 0.0);
+
+        let mut varies = false;
+        for i in 0..1000 {
+            let (x, y) = (i as f32 * 0.01, i as f32 * 0.007);
+            let value = noise.at(x, y);
+            assert!(value.abs() <= 1.0);
+            assert!((noise.at(x + 0.001, y) - value).abs() < 0.01);
+            varies |= value.abs() > 0.2;
+        }
+        assert!(varies);
+    }
+}
@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    /// Parses `#rrggbb`, `rrggbb`, `#rgb` or `rgb`.
+    pub fn from_hex(hex: &str) -> Result<Self, Box<dyn Error>> {
+        let digits = hex.strip_prefix('#').unwrap_or(hex);
+        let channel = |s: &str| u8::from_str_radix(s, 16);
+
+        let parsed = match digits.len() {
+            6 if digits.is_ascii() => (
+                channel(&digits[0..2]),
+                channel(&digits[2..4]),
+                channel(&digits[4..6]),
+            ),
+            3 if digits.is_ascii() => (
+                channel(&digits[0..1].repeat(2)),
+                channel(&digits[1..2].repeat(2)),
+                channel(&digits[2..3].repeat(2)),
+            ),
+            _ => return Err(format!("'{}' is not a hex color", hex).into()),
+        };
+
+        match parsed {
+            (Ok(r), Ok(g), Ok(b)) => Ok(Self::new(r, g, b)),
+            _ => Err(format!("'{}' is not a hex color", hex).into()),
+        }
+    }
+
+    /// The color `t` of the way from `self` to `other`.
+    pub fn lerp(self, other: Color, t: f32) -> Self {
+        let channel = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+        Self::new(
+            channel(self.r, other.r),
+            channel(self.g, other.g),
+            channel(self.b, other.b),
+        )
+    }
+}
+
+/// Colors at positions between 0 and 1, blended linearly in between.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gradient {
+    stops: Vec<(f32, Color)>,
+}
+
+impl Gradient {
+    /// Stops may come in any order. Positions outside 0..=1 are clamped.
+    pub fn new(mut stops: Vec<(f32, Color)>) -> Self {
+        for stop in &mut stops {
+            stop.0 = stop.0.clamp(0.0, 1.0);
+        }
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self { stops }
+    }
+
+    /// `colors` spread evenly from 0 to 1.
+    pub fn even(colors: &[Color]) -> Self {
+        let last = colors.len().saturating_sub(1).max(1) as f32;
+        Self::new(
+            colors
+                .iter()
+                .enumerate()
+                .map(|(i, &c)| (i as f32 / last, c))
+                .collect(),
+        )
+    }
+
+    pub fn at(&self, t: f32) -> Color {
+        let after = self.stops.iter().position(|&(p, _)| p > t);
+        match after {
+            _ if self.stops.is_empty() => Color::new(0, 0, 0),
+            Some(0) => self.stops[0].1,
+            None => self.stops[self.stops.len() - 1].1,
+            Some(i) => {
+                let ((p0, c0), (p1, c1)) = (self.stops[i - 1], self.stops[i]);
+                c0.lerp(c1, (t - p0) / (p1 - p0))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette {
+    name: String,
+    colors: Vec<Color>,
+}
+
+impl Palette {
+    pub fn new(name: &str, colors: Vec<Color>) -> Self {
+        Self {
+            name: name.to_string(),
+            colors,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn colors(&self) -> &[Color] {
+        &self.colors
+    }
+
+    /// Colors separated by whitespace or commas, one palette per input. Lines starting with `;`
+    /// or `//` are comments.
+    pub fn from_hex_list(name: &str, input: &str) -> Result<Self, Box<dyn Error>> {
+        let colors = input
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.starts_with(';') && !line.starts_with("//"))
+            .flat_map(|line| line.split(|c: char| c == ',' || c.is_whitespace()))
+            .filter(|token| !token.is_empty())
+            .map(Color::from_hex)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Self::non_empty(name, colors)
+    }
+
+    /// The GIMP/Inkscape `.gpl` format. A `Name:` header overrides `name`.
+    pub fn from_gpl(name: &str, input: &str) -> Result<Self, Box<dyn Error>> {
+        let mut lines = input.lines();
+        if lines.next().map(str::trim) != Some("GIMP Palette") {
+            return Err("missing 'GIMP Palette' header".into());
+        }
+
+        let mut name = name.to_string();
+        let mut colors = vec![];
+        for line in lines.map(str::trim) {
+            if let Some(header) = line.strip_prefix("Name:") {
+                name = header.trim().to_string();
+            } else if line.is_empty() || line.starts_with('#') || line.starts_with("Columns:") {
+                continue;
+            } else {
+                let channels = line
+                    .split_whitespace()
+                    .take(3)
+                    .map(str::parse)
+                    .collect::<Result<Vec<u8>, _>>()
+                    .map_err(|_| format!("'{}' is not a palette entry", line))?;
+
+                match channels[..] {
+                    [r, g, b] => colors.push(Color::new(r, g, b)),
+                    _ => return Err(format!("'{}' is not a palette entry", line).into()),
+                }
+            }
+        }
+
+        Self::non_empty(&name, colors)
+    }
+
+    /// A coolors.co palette URL such as `https://coolors.co/264653-2a9d8f-e9c46a`.
+    pub fn from_coolors_url(name: &str, url: &str) -> Result<Self, Box<dyn Error>> {
+        let last = url.trim().trim_end_matches('/').rsplit('/').next();
+        let colors = last
+            .filter(|_| url.contains("coolors.co/"))
+            .ok_or_else(|| format!("'{}' is not a coolors url", url))?
+            .split('-')
+            .map(Color::from_hex)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Self::non_empty(name, colors)
+    }
+
+    /// Detects the format from the content; the palette is named after the file stem unless the
+    /// file names itself.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref();
+        let input = std::fs::read_to_string(path)?;
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        let trimmed = input.trim();
+        if trimmed.starts_with("GIMP Palette") {
+            Self::from_gpl(&name, trimmed)
+        } else if trimmed.contains("coolors.co/") {
+            Self::from_coolors_url(&name, trimmed)
+        } else {
+            Self::from_hex_list(&name, trimmed)
+        }
+    }
+
+    fn non_empty(name: &str, colors: Vec<Color>) -> Result<Self, Box<dyn Error>> {
+        if colors.is_empty() {
+            Err(format!("palette '{}' has no colors", name).into())
+        } else {
+            Ok(Self::new(name, colors))
+        }
+    }
+}
+
+/// Palettes looked up by name.
+#[derive(Debug, Clone, Default)]
+pub struct Palettes {
+    palettes: HashMap<String, Palette>,
+}
+
+impl Palettes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, palette: Palette) {
+        self.palettes.insert(palette.name.clone(), palette);
+    }
+
+    pub fn load(&mut self, path: impl AsRef<Path>) -> Result<&Palette, Box<dyn Error>> {
+        let palette = Palette::from_file(path)?;
+        let name = palette.name.clone();
+        self.insert(palette);
+        Ok(&self.palettes[&name])
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Palette> {
+        self.palettes.get(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gradient_blends_between_stops() {
+        let gradient = Gradient::new(vec![
+            (1.0, Color::new(0, 0, 255)),
+            (0.0, Color::new(255, 0, 0)),
+            (0.5, Color::new(0, 255, 0)),
+        ]);
+        assert_eq!(gradient.at(-1.0), Color::new(255, 0, 0));
+        assert_eq!(gradient.at(0.25), Color::new(128, 128, 0));
+        assert_eq!(gradient.at(0.5), Color::new(0, 255, 0));
+        assert_eq!(gradient.at(2.0), Color::new(0, 0, 255));
+        assert_eq!(
+            Gradient::even(&[Color::new(0, 0, 0)]).at(0.7),
+            Color::new(0, 0, 0)
+        );
+    }
+
+    #[test]
+    fn hex_colors() {
+        assert_eq!(Color::from_hex("#ff8000").unwrap(), Color::new(255, 128, 0));
+        assert_eq!(Color::from_hex("f80").unwrap(), Color::new(255, 136, 0));
+        assert!(Color::from_hex("#ff80").is_err());
+        assert!(Color::from_hex("#gg0000").is_err());
+    }
+
+    #[test]
+    fn hex_list() {
+        let palette =
+            Palette::from_hex_list("p", "; comment\n#000000, #ffffff\n  123456\n").unwrap();
+        assert_eq!(
+            palette.colors(),
+            &[
+                Color::new(0, 0, 0),
+                Color::new(255, 255, 255),
+                Color::new(0x12, 0x34, 0x56)
+            ]
+        );
+    }
+
+    #[test]
+    fn gpl() {
+        let input =
+            "GIMP Palette\nName: Leaves\nColumns: 2\n#\n 34 139  34\tForest\n124 252 0 Lawn\n";
+        let palette = Palette::from_gpl("file", input).unwrap();
+        assert_eq!(palette.name(), "Leaves");
+        assert_eq!(
+            palette.colors(),
+            &[Color::new(34, 139, 34), Color::new(124, 252, 0)]
+        );
+    }
+
+    #[test]
+    fn coolors() {
+        let palette =
+            Palette::from_coolors_url("c", "https://coolors.co/264653-2a9d8f-e9c46a").unwrap();
+        assert_eq!(palette.colors().len(), 3);
+        assert_eq!(palette.colors()[0], Color::new(0x26, 0x46, 0x53));
+        assert!(Palette::from_coolors_url("c", "https://example.com/264653").is_err());
+    }
+
+    #[test]
+    fn empty_palette_is_an_error() {
+        assert!(Palette::from_hex_list("p", "; nothing here\n").is_err());
+    }
+}
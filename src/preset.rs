@@ -0,0 +1,71 @@
+use crate::graphics::TurtleConfig;
+use crate::lsystem::LSystem;
+
+/// A well-known grammar together with the turtle settings it is meant to be drawn with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Preset {
+    pub name: &'static str,
+    pub grammar: &'static str,
+    pub angle: f32,
+    pub draw_forward: &'static str,
+}
+
+pub const DRAGON: Preset = Preset {
+    name: "dragon",
+    grammar: "FX; X -> X+YF+; Y -> -FX-Y;",
+    angle: 90.0,
+    draw_forward: "F",
+};
+
+pub const KOCH: Preset = Preset {
+    name: "koch",
+    grammar: "F; F -> F+F--F+F;",
+    angle: 60.0,
+    draw_forward: "F",
+};
+
+pub const SIERPINSKI: Preset = Preset {
+    name: "sierpinski",
+    grammar: "F-G-G; F -> F-G+F+G-F; G -> GG;",
+    angle: 120.0,
+    draw_forward: "FG",
+};
+
+pub const TREE: Preset = Preset {
+    name: "tree",
+    grammar: "++++F; F -> G[+F][-F]-GF; G -> GG;",
+    angle: 30.0,
+    draw_forward: "FG",
+};
+
+pub const ALL: &[Preset] = &[DRAGON, KOCH, SIERPINSKI, TREE];
+
+impl Preset {
+    pub fn by_name(name: &str) -> Option<Self> {
+        ALL.iter().copied().find(|preset| preset.name == name)
+    }
+
+    pub fn lsystem(&self) -> LSystem {
+        // presets are checked by the tests below
+        LSystem::from_str(self.grammar).unwrap()
+    }
+
+    pub fn turtle(&self) -> TurtleConfig<'static, 'static, 'static, 'static> {
+        TurtleConfig::default()
+            .delta_ang(self.angle.to_radians())
+            .draw_forward(self.draw_forward)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_parse() {
+        for preset in ALL {
+            assert!(LSystem::from_str(preset.grammar).is_ok(), "{}", preset.name);
+            assert_eq!(Preset::by_name(preset.name), Some(*preset));
+        }
+    }
+}
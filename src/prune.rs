@@ -0,0 +1,136 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::lsystem::{count_branches, Instruction, Instructions};
+
+/// Randomly removes bracketed branches from a derived word. Each branch at nesting depth `d`
+/// (top level branches have depth 1) is removed with the `d`-th probability; deeper branches use
+/// the last one. `LSystem::set_dropout` prunes successors as they are derived instead.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Pruning {
+    probabilities: Vec<f64>,
+    seed: u64,
+}
+
+impl Pruning {
+    pub fn new(probabilities: Vec<f64>) -> Self {
+        Self {
+            probabilities,
+            seed: 0,
+        }
+    }
+
+    /// The same probability at every depth.
+    pub fn uniform(probability: f64) -> Self {
+        Self::new(vec![probability])
+    }
+
+    pub fn seed(self, seed: u64) -> Self {
+        Self { seed, ..self }
+    }
+
+    fn probability(&self, depth: usize) -> f64 {
+        let index = depth
+            .saturating_sub(1)
+            .min(self.probabilities.len().saturating_sub(1));
+        self.probabilities
+            .get(index)
+            .copied()
+            .unwrap_or(0.0)
+            .clamp(0.0, 1.0)
+    }
+
+    pub fn apply<S: Clone>(&self, word: &[Instruction<S>]) -> Instructions<S> {
+        self.apply_with(word, &mut StdRng::seed_from_u64(self.seed))
+    }
+
+    /// Like `apply`, drawing from `rng` instead of the configured seed.
+    pub fn apply_with<S: Clone, R: Rng>(
+        &self,
+        word: &[Instruction<S>],
+        rng: &mut R,
+    ) -> Instructions<S> {
+        self.prune(word, 1, rng, &mut vec![])
+    }
+
+    // also whether each branch of `word`, in reading order, was kept
+    pub(crate) fn apply_marked<S: Clone, R: Rng>(
+        &self,
+        word: &[Instruction<S>],
+        rng: &mut R,
+    ) -> (Instructions<S>, Vec<bool>) {
+        let mut kept = vec![];
+        (self.prune(word, 1, rng, &mut kept), kept)
+    }
+
+    fn prune<S: Clone, R: Rng>(
+        &self,
+        word: &[Instruction<S>],
+        depth: usize,
+        rng: &mut R,
+        kept: &mut Vec<bool>,
+    ) -> Instructions<S> {
+        word.iter()
+            .filter_map(|instr| match instr {
+                Instruction::Symbol(_) | Instruction::Module(..) => Some(instr.clone()),
+                Instruction::Branch(inner) => {
+                    if rng.gen_bool(self.probability(depth)) {
+                        kept.extend(std::iter::repeat_n(false, 1 + count_branches(inner)));
+                        None
+                    } else {
+                        kept.push(true);
+                        Some(Instruction::Branch(self.prune(inner, depth + 1, rng, kept)))
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lsystem::LSystem;
+
+    fn word() -> Instructions {
+        LSystem::from_str("F; F -> F[+F][-F]F;")
+            .unwrap()
+            .nth(3)
+            .unwrap()
+    }
+
+    fn branches(word: &[Instruction]) -> usize {
+        word.iter()
+            .map(|instr| match instr {
+                Instruction::Branch(inner) => 1 + branches(inner),
+                Instruction::Symbol(_) | Instruction::Module(..) => 0,
+            })
+            .sum()
+    }
+
+    #[test]
+    fn certain_pruning() {
+        let word = word();
+        assert_eq!(Pruning::uniform(0.0).apply(&word), word);
+        assert_eq!(branches(&Pruning::uniform(1.0).apply(&word)), 0);
+    }
+
+    #[test]
+    fn only_deep_branches() {
+        let pruned = Pruning::new(vec![0.0, 1.0]).apply(&word());
+        assert!(branches(&pruned) > 0);
+        for instr in &pruned {
+            if let Instruction::Branch(inner) = instr {
+                assert_eq!(branches(inner), 0);
+            }
+        }
+    }
+
+    #[test]
+    fn seeded() {
+        let word = word();
+        let pruning = Pruning::uniform(0.5).seed(3);
+        assert_eq!(pruning.apply(&word), pruning.apply(&word));
+        assert_ne!(pruning.apply(&word), word);
+    }
+}
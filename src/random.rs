@@ -0,0 +1,178 @@
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use crate::lsystem::{Instruction, Instructions, LSystem, Rule, RuleSelection, Symbol};
+
+/// Makes up grammars: every symbol of the alphabet gets a few rules with random successors of
+/// its symbols, turns and branches one deep, and a random axiom without branches. The grammars
+/// are always well formed and print as grammars that parse again. The same seed always makes
+/// the same grammar.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RandomGrammar {
+    alphabet: Vec<Symbol>,
+    rules: usize,
+    max_length: usize,
+    branching: f64,
+    turning: f64,
+    seed: u64,
+}
+
+impl Default for RandomGrammar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RandomGrammar {
+    pub fn new() -> Self {
+        Self {
+            alphabet: vec!['F'.into(), 'X'.into()],
+            rules: 1,
+            max_length: 6,
+            branching: 0.2,
+            turning: 0.3,
+            seed: 0,
+        }
+    }
+
+    /// The symbols rules rewrite and successors are made of, besides `+` and `-`. An empty
+    /// alphabet makes grammars of `F` alone.
+    pub fn alphabet(self, alphabet: Vec<Symbol>) -> Self {
+        Self { alphabet, ..self }
+    }
+
+    /// How many rules each symbol gets; with more than one, rules are picked at random, all
+    /// weighing the same.
+    pub fn rules(self, rules: usize) -> Self {
+        Self {
+            rules: rules.max(1),
+            ..self
+        }
+    }
+
+    /// The most instructions in a successor or a branch, a branch counting as one.
+    pub fn max_length(self, max_length: usize) -> Self {
+        Self {
+            max_length: max_length.max(1),
+            ..self
+        }
+    }
+
+    /// The chance that an instruction of a successor is a branch.
+    pub fn branching(self, branching: f64) -> Self {
+        Self {
+            branching: branching.clamp(0.0, 1.0),
+            ..self
+        }
+    }
+
+    /// The chance that an instruction is a turn, `+` or `-`.
+    pub fn turning(self, turning: f64) -> Self {
+        Self {
+            turning: turning.clamp(0.0, 1.0),
+            ..self
+        }
+    }
+
+    pub fn seed(self, seed: u64) -> Self {
+        Self { seed, ..self }
+    }
+
+    pub fn generate(&self) -> LSystem {
+        self.generate_with(&mut StdRng::seed_from_u64(self.seed))
+    }
+
+    /// Like `generate`, drawing from `rng` instead of the configured seed.
+    pub fn generate_with<R: Rng>(&self, rng: &mut R) -> LSystem {
+        let alphabet = match self.alphabet.is_empty() {
+            true => vec![Symbol::from('F')],
+            false => self.alphabet.clone(),
+        };
+
+        let mut rules = vec![];
+        for symbol in &alphabet {
+            for _ in 0..self.rules {
+                let from = Instruction::Symbol(symbol.clone());
+                rules.push(Rule::new(from, self.sequence(&alphabet, true, rng)));
+            }
+        }
+
+        let axiom = self.sequence(&alphabet, false, rng);
+        let mut lsystem = LSystem::new(axiom, rules);
+        if self.rules > 1 {
+            lsystem.set_selection(RuleSelection::Weighted);
+        }
+        lsystem
+    }
+
+    // holds at least one symbol of the alphabet, so nothing is only ever turning
+    fn sequence<R: Rng>(&self, alphabet: &[Symbol], branches: bool, rng: &mut R) -> Instructions {
+        let length = rng.gen_range(1..=self.max_length);
+        let letter = rng.gen_range(0..length);
+        (0..length)
+            .map(|i| {
+                if i == letter {
+                    Instruction::Symbol(alphabet.choose(rng).unwrap().clone())
+                } else if branches && rng.gen_bool(self.branching) {
+                    Instruction::Branch(self.sequence(alphabet, false, rng))
+                } else if rng.gen_bool(self.turning) {
+                    Instruction::Symbol(Symbol::from(*['+', '-'].choose(rng).unwrap()))
+                } else {
+                    Instruction::Symbol(alphabet.choose(rng).unwrap().clone())
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn depth(instrs: &[Instruction]) -> usize {
+        instrs
+            .iter()
+            .map(|instr| match instr {
+                Instruction::Branch(inner) => 1 + depth(inner),
+                Instruction::Symbol(_) | Instruction::Module(..) => 0,
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    #[test]
+    fn grammars_are_well_formed() {
+        let config = RandomGrammar::new()
+            .alphabet(vec!['A'.into(), 'B'.into()])
+            .rules(2)
+            .branching(0.5);
+        for seed in 0..20 {
+            let lsystem = config.clone().seed(seed).generate();
+            assert_eq!(LSystem::from_str(&lsystem.to_string()).unwrap(), lsystem);
+            assert_eq!(lsystem.rules().len(), 4);
+            assert_eq!(lsystem.selection(), RuleSelection::Weighted);
+            for rule in lsystem.rules() {
+                assert!(!rule.to().is_empty() && rule.to().len() <= 6);
+                assert!(depth(rule.to()) <= 1);
+                assert!(rule
+                    .to()
+                    .iter()
+                    .any(|i| i.symbol().is_some_and(|s| s.is_in("AB"))));
+            }
+        }
+    }
+
+    #[test]
+    fn seeds_repeat() {
+        let config = RandomGrammar::new().seed(5);
+        assert_eq!(config.generate(), config.generate());
+        let others: Vec<_> = (0..8)
+            .map(|seed| config.clone().seed(seed).generate())
+            .collect();
+        assert!(others.iter().any(|other| *other != config.generate()));
+        let plain = RandomGrammar::new().alphabet(vec![]).generate();
+        assert!(plain
+            .axiom()
+            .iter()
+            .all(|i| i.symbol().is_some_and(|s| s.is_in("F+-"))));
+    }
+}
@@ -0,0 +1,195 @@
+use std::io::Write;
+
+use crate::graphics::{Graphics, LineStyle};
+use crate::palette::Color;
+use crate::render::{Blend, Border};
+
+/// A pixel image that lines are blended into, created by `RenderConfig::raster`. Coordinates are
+/// placed like on a `Surface`.
+#[derive(Debug, Clone)]
+pub struct Raster {
+    width: usize,
+    height: usize,
+    // unclamped channels, so additive blending can saturate gradually
+    pixels: Vec<[f32; 3]>,
+    stroke: Color,
+    padding: f32,
+    blend: Blend,
+    offset: (f32, f32),
+    scale: f32,
+}
+
+impl Raster {
+    pub(crate) fn new(width: usize, height: usize, background: Color, stroke: Color) -> Self {
+        let background = [background.r, background.g, background.b].map(f32::from);
+        Self {
+            width,
+            height,
+            pixels: vec![background; width * height],
+            stroke,
+            padding: 0.0,
+            blend: Blend::Over,
+            offset: (0.0, 0.0),
+            scale: 1.0,
+        }
+    }
+
+    pub(crate) fn setup(&mut self, padding: f32, blend: Blend) {
+        self.padding = padding;
+        self.blend = blend;
+    }
+
+    pub(crate) fn frame(&mut self, border: Border) {
+        let color = [border.color.r, border.color.g, border.color.b].map(f32::from);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let edge = x.min(y).min(self.width - 1 - x).min(self.height - 1 - y);
+                if edge < border.width as usize {
+                    self.pixels[y * self.width + x] = color;
+                }
+            }
+        }
+    }
+
+    /// Subsequent lines are drawn at `offset + point * scale` within the drawable area.
+    pub fn place(&mut self, offset: (f32, f32), scale: f32) {
+        self.offset = offset;
+        self.scale = scale;
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Color {
+        let [r, g, b] = self.pixels[y * self.width + x].map(|c| c.round().clamp(0.0, 255.0) as u8);
+        Color::new(r, g, b)
+    }
+
+    /// The image as a binary PPM.
+    pub fn to_ppm(&self) -> Vec<u8> {
+        let mut ppm = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let color = self.pixel(x, y);
+                ppm.extend([color.r, color.g, color.b]);
+            }
+        }
+        ppm
+    }
+
+    pub fn save_ppm(&self, path: &str) -> std::io::Result<()> {
+        std::fs::File::create(path)?.write_all(&self.to_ppm())
+    }
+
+    fn map(&self, (x, y): (f32, f32)) -> (f32, f32) {
+        (
+            self.padding + self.offset.0 + x * self.scale,
+            self.padding + self.offset.1 + y * self.scale,
+        )
+    }
+
+    fn plot(&mut self, (x, y): (f32, f32), color: Color, alpha: f32) {
+        let (x, y) = (x.floor(), y.floor());
+        if x < 0.0 || y < 0.0 || x >= self.width as f32 || y >= self.height as f32 {
+            return;
+        }
+        let pixel = &mut self.pixels[y as usize * self.width + x as usize];
+        for (below, color) in pixel.iter_mut().zip([color.r, color.g, color.b]) {
+            *below = self.blend.apply(*below, color as f32, alpha);
+        }
+    }
+}
+
+impl Graphics<()> for Raster {
+    fn draw_line(&mut self, c_0: (f32, f32), c_1: (f32, f32)) -> Result<(), ()> {
+        self.draw_styled_line(c_0, c_1, LineStyle::default())
+    }
+
+    fn draw_colored_line(
+        &mut self,
+        c_0: (f32, f32),
+        c_1: (f32, f32),
+        color: Color,
+    ) -> Result<(), ()> {
+        let style = LineStyle {
+            color: Some(color),
+            ..LineStyle::default()
+        };
+        self.draw_styled_line(c_0, c_1, style)
+    }
+
+    // one pixel per step along the longer axis, leaving out the end so that a line continuing
+    // from this one doesn't blend the shared pixel twice
+    fn draw_styled_line(
+        &mut self,
+        c_0: (f32, f32),
+        c_1: (f32, f32),
+        style: LineStyle,
+    ) -> Result<(), ()> {
+        let color = style.color.unwrap_or(self.stroke);
+        let (start, end) = (self.map(c_0), self.map(c_1));
+        let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+        let steps = dx.abs().max(dy.abs()).round().max(1.0) as usize;
+        for i in 0..steps {
+            let t = i as f32 / steps as f32;
+            self.plot((start.0 + dx * t, start.1 + dy * t), color, style.alpha);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::RenderConfig;
+
+    fn half(color: Color) -> LineStyle {
+        LineStyle {
+            color: Some(color),
+            alpha: 0.5,
+        }
+    }
+
+    #[test]
+    fn translucent_lines_build_up() {
+        let black = Color::new(0, 0, 0);
+        for blend in [Blend::Over, Blend::Multiply] {
+            let mut raster = RenderConfig::new().blend(blend).raster(4.0, 4.0);
+            for _ in 0..2 {
+                raster
+                    .draw_styled_line((0.0, 1.5), (4.0, 1.5), half(black))
+                    .unwrap();
+            }
+            assert_eq!(raster.pixel(2, 1), Color::new(64, 64, 64));
+            assert_eq!(raster.pixel(2, 2), Color::new(255, 255, 255));
+        }
+    }
+
+    #[test]
+    fn additive_lines_saturate() {
+        let mut raster = RenderConfig::new()
+            .background(Some(Color::new(0, 0, 0)))
+            .blend(Blend::Add)
+            .raster(4.0, 4.0);
+        let glow = Color::new(200, 100, 0);
+        for _ in 0..3 {
+            raster
+                .draw_styled_line((1.5, 0.0), (1.5, 4.0), half(glow))
+                .unwrap();
+        }
+        assert_eq!(raster.pixel(1, 3), Color::new(255, 150, 0));
+    }
+
+    #[test]
+    fn ppm_has_a_header_and_every_pixel() {
+        let raster = RenderConfig::new().padding(1.0).raster(2.0, 3.0);
+        let ppm = raster.to_ppm();
+        assert!(ppm.starts_with(b"P6\n4 5\n255\n"));
+        assert_eq!(ppm.len(), 11 + 4 * 5 * 3);
+    }
+}
@@ -0,0 +1,302 @@
+use draw::{shape::LinePoint, Canvas, Drawing, Point, Shape, Style, SvgRenderer, RGB};
+
+use crate::geometry::Recorder;
+use crate::graphics::Graphics;
+use crate::palette::Color;
+use crate::raster::Raster;
+use crate::theme::Theme;
+
+fn rgb(color: Color) -> RGB {
+    RGB::new(color.r, color.g, color.b)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Border {
+    pub color: Color,
+    pub width: u32,
+}
+
+/// How a translucent line combines with what is already drawn. Only raster targets blend; SVG
+/// output draws every line opaque.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Blend {
+    /// Paints over what is below.
+    #[default]
+    Over,
+    /// Adds light, so overlapping lines on a dark background glow.
+    Add,
+    /// Filters what is below, so overlapping lines on a light background darken.
+    Multiply,
+}
+
+impl Blend {
+    pub(crate) fn apply(self, below: f32, color: f32, alpha: f32) -> f32 {
+        match self {
+            Blend::Over => below + (color - below) * alpha,
+            Blend::Add => below + color * alpha,
+            Blend::Multiply => below * (1.0 - alpha + alpha * color / 255.0),
+        }
+    }
+}
+
+/// How the canvas around a figure looks, shared by everything that creates a surface.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderConfig {
+    background: Option<Color>,
+    stroke: Color,
+    padding: f32,
+    border: Option<Border>,
+    blend: Blend,
+}
+
+impl Default for RenderConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RenderConfig {
+    pub fn new() -> Self {
+        Self {
+            background: None,
+            stroke: Color::new(0, 0, 0),
+            padding: 0.0,
+            border: None,
+            blend: Blend::Over,
+        }
+    }
+
+    /// `None` leaves the background transparent.
+    pub fn background(self, background: Option<Color>) -> Self {
+        Self { background, ..self }
+    }
+
+    /// The color of lines drawn without a color of their own.
+    pub fn stroke(self, stroke: Color) -> Self {
+        Self { stroke, ..self }
+    }
+
+    /// The theme's background and stroke.
+    pub fn theme(self, theme: &Theme) -> Self {
+        Self {
+            background: Some(theme.background),
+            stroke: theme.stroke,
+            ..self
+        }
+    }
+
+    pub fn padding(self, padding: f32) -> Self {
+        Self {
+            padding: padding.max(0.0),
+            ..self
+        }
+    }
+
+    pub fn border(self, border: Option<Border>) -> Self {
+        Self { border, ..self }
+    }
+
+    pub fn blend(self, blend: Blend) -> Self {
+        Self { blend, ..self }
+    }
+
+    /// A raster image whose drawable area is `width` by `height` pixels, like `surface`. A
+    /// transparent background is white.
+    pub fn raster(&self, width: f32, height: f32) -> Raster {
+        let total_width = (width + 2.0 * self.padding).ceil() as usize;
+        let total_height = (height + 2.0 * self.padding).ceil() as usize;
+        let background = self.background.unwrap_or(Color::new(255, 255, 255));
+        let mut raster = Raster::new(total_width, total_height, background, self.stroke);
+        if let Some(border) = self.border {
+            raster.frame(border);
+        }
+        raster.setup(self.padding, self.blend);
+        raster
+    }
+
+    /// A surface just large enough for everything in `recorder` drawn at `scale`, plus padding,
+    /// with the recording already drawn on it.
+    pub fn fitted(&self, recorder: &Recorder, scale: f32) -> Surface {
+        let bounds = match recorder.bounds() {
+            Some(bounds) => bounds,
+            None => return self.surface(0.0, 0.0),
+        };
+        let mut surface = self.surface(bounds.width() * scale, bounds.height() * scale);
+        surface.place((-bounds.min.0 * scale, -bounds.min.1 * scale), scale);
+        recorder.replay(&mut surface).unwrap();
+        surface
+    }
+
+    /// A `width` by `height` surface with everything in `recorder` scaled to fit it, keeping its
+    /// aspect ratio.
+    pub fn fit_into(&self, recorder: &Recorder, width: f32, height: f32) -> Surface {
+        let mut surface = self.surface(width, height);
+        if let Some((offset, scale)) = fit(recorder, width, height) {
+            surface.place(offset, scale);
+            recorder.replay(&mut surface).unwrap();
+        }
+        surface
+    }
+
+    /// Like `fit_into`, onto a raster image.
+    pub fn fit_raster(&self, recorder: &Recorder, width: f32, height: f32) -> Raster {
+        let mut raster = self.raster(width, height);
+        if let Some((offset, scale)) = fit(recorder, width, height) {
+            raster.place(offset, scale);
+            recorder.replay(&mut raster).unwrap();
+        }
+        raster
+    }
+
+    /// A surface whose drawable area is `width` by `height`; padding is added around it.
+    pub fn surface(&self, width: f32, height: f32) -> Surface {
+        let total_width = width + 2.0 * self.padding;
+        let total_height = height + 2.0 * self.padding;
+        let mut canvas = Canvas::new(total_width.ceil() as u32, total_height.ceil() as u32);
+        let frame = Shape::Rectangle {
+            width: canvas.width,
+            height: canvas.height,
+        };
+
+        if let Some(background) = self.background {
+            let fill = Drawing::new()
+                .with_shape(frame.clone())
+                .with_style(Style::filled(rgb(background)));
+            canvas.display_list.add(fill);
+        }
+
+        if let Some(border) = self.border {
+            let outline = Drawing::new()
+                .with_shape(frame)
+                .with_style(Style::stroked(border.width, rgb(border.color)));
+            canvas.display_list.add(outline);
+        }
+
+        Surface {
+            canvas,
+            padding: self.padding,
+            stroke: self.stroke,
+            offset: (0.0, 0.0),
+            scale: 1.0,
+        }
+    }
+}
+
+// where and how large to draw a recording to fit it centered into `width` by `height`
+pub(crate) fn fit(recorder: &Recorder, width: f32, height: f32) -> Option<((f32, f32), f32)> {
+    let bounds = recorder.bounds()?;
+    let scale = f32::min(
+        width / bounds.width().max(f32::EPSILON),
+        height / bounds.height().max(f32::EPSILON),
+    );
+    let (cx, cy) = bounds.center();
+    Some(((width / 2.0 - cx * scale, height / 2.0 - cy * scale), scale))
+}
+
+/// A canvas prepared by a `RenderConfig`. Turtle coordinates are mapped into the drawable area
+/// through an offset and a scale.
+pub struct Surface {
+    canvas: Canvas,
+    padding: f32,
+    stroke: Color,
+    offset: (f32, f32),
+    scale: f32,
+}
+
+impl Surface {
+    /// Subsequent lines are drawn at `offset + point * scale` within the drawable area.
+    pub fn place(&mut self, offset: (f32, f32), scale: f32) {
+        self.offset = offset;
+        self.scale = scale;
+    }
+
+    fn map(&self, (x, y): (f32, f32)) -> Point {
+        Point::new(
+            self.padding + self.offset.0 + x * self.scale,
+            self.padding + self.offset.1 + y * self.scale,
+        )
+    }
+
+    pub fn canvas(&self) -> &Canvas {
+        &self.canvas
+    }
+
+    pub fn into_canvas(self) -> Canvas {
+        self.canvas
+    }
+
+    pub fn save_svg(&self, path: &str) -> std::io::Result<()> {
+        draw::render::save(&self.canvas, path, SvgRenderer::new())
+    }
+}
+
+impl Graphics<()> for Surface {
+    fn draw_line(&mut self, c_0: (f32, f32), c_1: (f32, f32)) -> Result<(), ()> {
+        self.draw_colored_line(c_0, c_1, self.stroke)
+    }
+
+    fn draw_colored_line(
+        &mut self,
+        c_0: (f32, f32),
+        c_1: (f32, f32),
+        color: Color,
+    ) -> Result<(), ()> {
+        let line = Drawing::new()
+            .with_shape(Shape::Line {
+                start: self.map(c_0),
+                points: vec![LinePoint::Straight {
+                    point: self.map(c_1),
+                }],
+            })
+            .with_style(Style::stroked(1, rgb(color)));
+        self.canvas.display_list.add(line);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn padding_grows_the_canvas() {
+        let surface = RenderConfig::new().padding(10.0).surface(100.0, 50.0);
+        assert_eq!((surface.canvas().width, surface.canvas().height), (120, 70));
+        assert!(surface.canvas().drawings().is_empty());
+    }
+
+    #[test]
+    fn background_and_border_come_first() {
+        let mut surface = RenderConfig::new()
+            .background(Some(Color::new(255, 255, 255)))
+            .border(Some(Border {
+                color: Color::new(0, 0, 0),
+                width: 2,
+            }))
+            .surface(10.0, 10.0);
+        surface.draw_line((0.0, 0.0), (1.0, 1.0)).unwrap();
+
+        let drawings = surface.canvas().drawings();
+        assert_eq!(drawings.len(), 3);
+        assert!(drawings[0].style.fill.is_some());
+        assert_eq!(drawings[1].style.stroke.as_ref().unwrap().width, 2);
+    }
+
+    #[test]
+    fn fitted_surface_covers_the_figure() {
+        let mut recorder = Recorder::new();
+        recorder.draw_line((-10.0, 5.0), (30.0, -15.0)).unwrap();
+        let surface = RenderConfig::new().padding(4.0).fitted(&recorder, 2.0);
+
+        assert_eq!((surface.canvas().width, surface.canvas().height), (88, 48));
+        assert_eq!(surface.map((-10.0, -15.0)), Point::new(4.0, 4.0));
+        assert_eq!(surface.canvas().drawings().len(), 1);
+    }
+
+    #[test]
+    fn lines_are_placed_inside_padding() {
+        let mut surface = RenderConfig::new().padding(5.0).surface(10.0, 10.0);
+        surface.place((2.0, 3.0), 2.0);
+        assert_eq!(surface.map((1.0, 1.0)), Point::new(9.0, 10.0));
+    }
+}
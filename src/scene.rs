@@ -0,0 +1,353 @@
+use crate::geometry::{Bounds, Recorder, Segment};
+use crate::graphics::{Graphics, LineStyle, TurtleConfig};
+use crate::lsystem::Instructions;
+use crate::palette::Color;
+use crate::render::{RenderConfig, Surface};
+
+/// Where a figure goes in a scene: scaled first, then rotated about the turtle's start, then
+/// moved to `position`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    position: (f32, f32),
+    rotation: f32,
+    scale: f32,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Transform {
+    pub fn new() -> Self {
+        Self {
+            position: (0.0, 0.0),
+            rotation: 0.0,
+            scale: 1.0,
+        }
+    }
+
+    pub fn position(self, position: (f32, f32)) -> Self {
+        Self { position, ..self }
+    }
+
+    /// In radians.
+    pub fn rotation(self, rotation: f32) -> Self {
+        Self { rotation, ..self }
+    }
+
+    pub fn scale(self, scale: f32) -> Self {
+        Self { scale, ..self }
+    }
+
+    pub fn apply(&self, (x, y): (f32, f32)) -> (f32, f32) {
+        let (sin, cos) = self.rotation.sin_cos();
+        let (x, y) = (x * self.scale, y * self.scale);
+        (
+            self.position.0 + x * cos - y * sin,
+            self.position.1 + x * sin + y * cos,
+        )
+    }
+
+    /// The point that `apply` maps to `point`.
+    pub fn invert(&self, point: (f32, f32)) -> (f32, f32) {
+        let (sin, cos) = self.rotation.sin_cos();
+        let (x, y) = (point.0 - self.position.0, point.1 - self.position.1);
+        (
+            (x * cos + y * sin) / self.scale,
+            (y * cos - x * sin) / self.scale,
+        )
+    }
+
+    /// `inner` followed by `self`.
+    pub fn compose(&self, inner: &Transform) -> Transform {
+        Transform {
+            position: self.apply(inner.position),
+            rotation: self.rotation + inner.rotation,
+            scale: self.scale * inner.scale,
+        }
+    }
+}
+
+/// One branch of a drawing. Its segments and the transforms of its children are in coordinates
+/// local to it, so turning a node moves everything that grows from it.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Node {
+    pub transform: Transform,
+    pub segments: Vec<Segment>,
+    pub children: Vec<Node>,
+}
+
+impl Node {
+    /// Every segment in the tree in its parent's coordinates, parents before children.
+    pub fn flatten(&self) -> Vec<Segment> {
+        let mut segments = vec![];
+        self.flatten_into(&Transform::new(), &mut segments);
+        segments
+    }
+
+    fn flatten_into(&self, parent: &Transform, segments: &mut Vec<Segment>) {
+        let world = parent.compose(&self.transform);
+        segments.extend(self.segments.iter().map(|s| Segment {
+            start: world.apply(s.start),
+            end: world.apply(s.end),
+            ..*s
+        }));
+        for child in &self.children {
+            child.flatten_into(&world, segments);
+        }
+    }
+}
+
+/// A `Graphics` target that keeps lines in a tree of nodes mirroring the branches of the word.
+#[derive(Debug, Clone)]
+pub struct NodeRecorder {
+    // every open node, with where it is in world coordinates
+    stack: Vec<(Node, Transform)>,
+}
+
+impl Default for NodeRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NodeRecorder {
+    pub fn new() -> Self {
+        Self {
+            stack: vec![(Node::default(), Transform::new())],
+        }
+    }
+
+    /// The root node. Branches left open are closed first.
+    pub fn into_root(mut self) -> Node {
+        while self.stack.len() > 1 {
+            self.close();
+        }
+        self.stack.pop().unwrap().0
+    }
+
+    fn close(&mut self) {
+        let (node, _) = self.stack.pop().unwrap();
+        self.stack.last_mut().unwrap().0.children.push(node);
+    }
+
+    fn push(&mut self, c_0: (f32, f32), c_1: (f32, f32), style: LineStyle) {
+        let (node, world) = self.stack.last_mut().unwrap();
+        node.segments.push(Segment {
+            start: world.invert(c_0),
+            end: world.invert(c_1),
+            color: style.color,
+            alpha: style.alpha,
+        });
+    }
+}
+
+impl Graphics<()> for NodeRecorder {
+    fn draw_line(&mut self, c_0: (f32, f32), c_1: (f32, f32)) -> Result<(), ()> {
+        self.draw_styled_line(c_0, c_1, LineStyle::default())
+    }
+
+    fn draw_colored_line(
+        &mut self,
+        c_0: (f32, f32),
+        c_1: (f32, f32),
+        color: Color,
+    ) -> Result<(), ()> {
+        let style = LineStyle {
+            color: Some(color),
+            ..LineStyle::default()
+        };
+        self.draw_styled_line(c_0, c_1, style)
+    }
+
+    fn draw_styled_line(
+        &mut self,
+        c_0: (f32, f32),
+        c_1: (f32, f32),
+        style: LineStyle,
+    ) -> Result<(), ()> {
+        self.push(c_0, c_1, style);
+        Ok(())
+    }
+
+    fn begin_branch(&mut self, origin: (f32, f32), angle: f32) -> Result<(), ()> {
+        let (_, parent) = self.stack.last().unwrap();
+        let transform = Transform::new()
+            .position(parent.invert(origin))
+            .rotation(angle - parent.rotation);
+        let world = parent.compose(&transform);
+        self.stack.push((
+            Node {
+                transform,
+                ..Node::default()
+            },
+            world,
+        ));
+        Ok(())
+    }
+
+    fn end_branch(&mut self) -> Result<(), ()> {
+        if self.stack.len() > 1 {
+            self.close();
+        }
+        Ok(())
+    }
+}
+
+struct Placed<'s> {
+    recorder: &'s mut Recorder,
+    transform: Transform,
+}
+
+impl Graphics<()> for Placed<'_> {
+    fn draw_line(&mut self, c_0: (f32, f32), c_1: (f32, f32)) -> Result<(), ()> {
+        let t = self.transform;
+        self.recorder.draw_line(t.apply(c_0), t.apply(c_1))
+    }
+
+    fn draw_colored_line(
+        &mut self,
+        c_0: (f32, f32),
+        c_1: (f32, f32),
+        color: Color,
+    ) -> Result<(), ()> {
+        let t = self.transform;
+        self.recorder
+            .draw_colored_line(t.apply(c_0), t.apply(c_1), color)
+    }
+
+    fn draw_styled_line(
+        &mut self,
+        c_0: (f32, f32),
+        c_1: (f32, f32),
+        style: LineStyle,
+    ) -> Result<(), ()> {
+        let t = self.transform;
+        self.recorder
+            .draw_styled_line(t.apply(c_0), t.apply(c_1), style)
+    }
+}
+
+/// Several figures, each with its own turtle and transform, sharing one coordinate system.
+#[derive(Debug, Default, Clone)]
+pub struct Scene {
+    recorder: Recorder,
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, word: Instructions, turtle: &TurtleConfig, transform: Transform) {
+        let mut placed = Placed {
+            recorder: &mut self.recorder,
+            transform,
+        };
+        turtle.create_turtle().draw(&mut placed, word).unwrap();
+    }
+
+    /// A plain line in scene coordinates, such as the ground under a row of plants.
+    pub fn line(&mut self, start: (f32, f32), end: (f32, f32)) {
+        self.recorder.draw_line(start, end).unwrap();
+    }
+
+    pub fn recorder(&self) -> &Recorder {
+        &self.recorder
+    }
+
+    pub fn bounds(&self) -> Option<Bounds> {
+        self.recorder.bounds()
+    }
+
+    /// The whole scene scaled to fit a `width` by `height` surface, keeping its aspect ratio.
+    pub fn render(&self, render: &RenderConfig, width: f32, height: f32) -> Surface {
+        render.fit_into(&self.recorder, width, height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lsystem::LSystem;
+    use std::f32::consts::FRAC_PI_2;
+
+    fn close(a: (f32, f32), b: (f32, f32)) -> bool {
+        (a.0 - b.0).abs() < 1e-5 && (a.1 - b.1).abs() < 1e-5
+    }
+
+    #[test]
+    fn transform_scales_rotates_then_moves() {
+        let t = Transform::new()
+            .scale(2.0)
+            .rotation(FRAC_PI_2)
+            .position((10.0, 0.0));
+        assert!(close(t.apply((1.0, 0.0)), (10.0, 2.0)));
+    }
+
+    #[test]
+    fn invert_undoes_apply() {
+        let t = Transform::new()
+            .scale(3.0)
+            .rotation(1.0)
+            .position((2.0, -4.0));
+        assert!(close(t.invert(t.apply((0.5, 7.0))), (0.5, 7.0)));
+    }
+
+    #[test]
+    fn nodes_mirror_branches() {
+        let word = LSystem::from_str("F[+F[-F]F][-F]F;")
+            .unwrap()
+            .word()
+            .clone();
+        let turtle = TurtleConfig::default().delta_ang(0.5);
+
+        let mut flat = Recorder::new();
+        turtle
+            .create_turtle()
+            .draw(&mut flat, word.clone())
+            .unwrap();
+        let mut nodes = NodeRecorder::new();
+        turtle.create_turtle().draw(&mut nodes, word).unwrap();
+        let root = nodes.into_root();
+
+        assert_eq!(root.segments.len(), 2);
+        assert_eq!(root.children.len(), 2);
+        assert_eq!(root.children[0].children.len(), 1);
+        assert!(close(root.children[0].transform.position, (1.0, 0.0)));
+        assert!(close(root.children[0].segments[0].start, (0.0, 0.0)));
+
+        let mut expected = flat.segments().to_vec();
+        let mut actual = root.flatten();
+        let key = |s: &Segment| (s.end.0 * 1e3) as i64 * 1_000_000 + (s.end.1 * 1e3) as i64;
+        expected.sort_by_key(key);
+        actual.sort_by_key(key);
+        assert_eq!(expected.len(), actual.len());
+        for (e, a) in expected.iter().zip(&actual) {
+            assert!(close(e.start, a.start) && close(e.end, a.end));
+        }
+    }
+
+    #[test]
+    fn figures_share_one_coordinate_system() {
+        let word = LSystem::from_str("F;").unwrap().word().clone();
+        let turtle = TurtleConfig::default();
+
+        let mut scene = Scene::new();
+        scene.line((-1.0, 0.0), (11.0, 0.0));
+        for x in [0.0, 5.0, 10.0] {
+            let transform = Transform::new().position((x, 0.0)).rotation(-FRAC_PI_2);
+            scene.add(word.clone(), &turtle, transform);
+        }
+
+        let segments = scene.recorder().segments();
+        assert_eq!(segments.len(), 4);
+        assert!(close(segments[3].end, (10.0, -1.0)));
+
+        let bounds = scene.bounds().unwrap();
+        assert!(close(bounds.min, (-1.0, -1.0)));
+        assert!(close(bounds.max, (11.0, 0.0)));
+    }
+}
@@ -0,0 +1,208 @@
+use std::io::Write;
+
+use crate::geometry::Recorder;
+use crate::render::fit;
+
+/// Turns recorded lines into a signed distance field: every pixel holds its distance to the
+/// nearest stroke, negative inside one. Shaders can draw such a texture crisply at any scale,
+/// and offset its edge for outlines and glows.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SdfConfig {
+    width: usize,
+    height: usize,
+    spread: f32,
+    radius: f32,
+}
+
+impl Default for SdfConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SdfConfig {
+    pub fn new() -> Self {
+        Self {
+            width: 256,
+            height: 256,
+            spread: 8.0,
+            radius: 1.0,
+        }
+    }
+
+    pub fn resolution(self, width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            ..self
+        }
+    }
+
+    /// How far from a stroke distances are still told apart, in pixels; past it they are
+    /// clamped. The figure is kept this far from the edges.
+    pub fn spread(self, spread: f32) -> Self {
+        Self {
+            spread: spread.max(f32::EPSILON),
+            ..self
+        }
+    }
+
+    /// Half the width of every stroke, in pixels.
+    pub fn radius(self, radius: f32) -> Self {
+        Self {
+            radius: radius.max(0.0),
+            ..self
+        }
+    }
+
+    /// Fits the figure into the field like `RenderConfig::fit_into` does.
+    pub fn field(&self, recorder: &Recorder) -> DistanceField {
+        let mut field = DistanceField {
+            width: self.width,
+            height: self.height,
+            spread: self.spread,
+            distances: vec![self.spread; self.width * self.height],
+            offset: (0.0, 0.0),
+            scale: 1.0,
+        };
+
+        let inner = (
+            self.width as f32 - 2.0 * self.spread,
+            self.height as f32 - 2.0 * self.spread,
+        );
+        let (offset, scale) = match fit(recorder, inner.0.max(0.0), inner.1.max(0.0)) {
+            Some(placement) => placement,
+            None => return field,
+        };
+        field.offset = (offset.0 + self.spread, offset.1 + self.spread);
+        field.scale = scale;
+
+        // only pixels closer than the spread can change, so each line visits just those
+        let reach = self.spread + self.radius;
+        for segment in recorder.segments() {
+            let (a, b) = (field.map(segment.start), field.map(segment.end));
+            let x0 = (a.0.min(b.0) - reach).floor().max(0.0) as usize;
+            let y0 = (a.1.min(b.1) - reach).floor().max(0.0) as usize;
+            let x1 = ((a.0.max(b.0) + reach).ceil() as usize).min(self.width);
+            let y1 = ((a.1.max(b.1) + reach).ceil() as usize).min(self.height);
+            for y in y0..y1 {
+                for x in x0..x1 {
+                    let center = (x as f32 + 0.5, y as f32 + 0.5);
+                    let distance = to_segment(center, a, b) - self.radius;
+                    let value = &mut field.distances[y * self.width + x];
+                    *value = value.min(distance.max(-self.spread));
+                }
+            }
+        }
+
+        field
+    }
+}
+
+fn to_segment(p: (f32, f32), a: (f32, f32), b: (f32, f32)) -> f32 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let length = dx * dx + dy * dy;
+    let t = match length > 0.0 {
+        true => (((p.0 - a.0) * dx + (p.1 - a.1) * dy) / length).clamp(0.0, 1.0),
+        false => 0.0,
+    };
+    f32::hypot(p.0 - (a.0 + t * dx), p.1 - (a.1 + t * dy))
+}
+
+/// Distances in pixels, between minus and plus the spread, created by `SdfConfig::field`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DistanceField {
+    width: usize,
+    height: usize,
+    spread: f32,
+    distances: Vec<f32>,
+    offset: (f32, f32),
+    scale: f32,
+}
+
+impl DistanceField {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn distance(&self, x: usize, y: usize) -> f32 {
+        self.distances[y * self.width + x]
+    }
+
+    fn map(&self, (x, y): (f32, f32)) -> (f32, f32) {
+        (
+            self.offset.0 + x * self.scale,
+            self.offset.1 + y * self.scale,
+        )
+    }
+
+    /// The distance at a point in turtle coordinates, for collision queries; `None` outside the
+    /// field.
+    pub fn at(&self, point: (f32, f32)) -> Option<f32> {
+        let (x, y) = self.map(point);
+        if x < 0.0 || y < 0.0 || x >= self.width as f32 || y >= self.height as f32 {
+            return None;
+        }
+        Some(self.distance(x as usize, y as usize))
+    }
+
+    /// One byte per pixel, with the edge of the strokes at 128, 0 a spread outside them and 255
+    /// a spread inside: the usual encoding of distance field textures.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.distances
+            .iter()
+            .map(|d| (128.0 - d / self.spread * 127.0).round().clamp(0.0, 255.0) as u8)
+            .collect()
+    }
+
+    /// The field as a binary greyscale PGM, encoded like `to_bytes`.
+    pub fn to_pgm(&self) -> Vec<u8> {
+        let mut pgm = format!("P5\n{} {}\n255\n", self.width, self.height).into_bytes();
+        pgm.extend(self.to_bytes());
+        pgm
+    }
+
+    pub fn save_pgm(&self, path: &str) -> std::io::Result<()> {
+        std::fs::File::create(path)?.write_all(&self.to_pgm())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphics::Graphics;
+
+    #[test]
+    fn distances_grow_away_from_strokes() {
+        let mut recorder = Recorder::new();
+        recorder.draw_line((0.0, 0.0), (10.0, 0.0)).unwrap();
+        recorder.draw_line((10.0, 0.0), (10.0, 10.0)).unwrap();
+        let field = SdfConfig::new()
+            .resolution(40, 40)
+            .spread(10.0)
+            .radius(1.0)
+            .field(&recorder);
+
+        // the figure spans the inner 20 pixels, two pixels per unit
+        assert!(field.at((5.0, 0.0)).unwrap() < 0.0);
+        assert!((field.at((5.0, 2.0)).unwrap() - 3.0).abs() < 1.0);
+        assert!(field.at((5.0, 2.0)) < field.at((5.0, 4.0)));
+        assert_eq!(field.at((0.0, 10.0)), Some(10.0));
+        assert_eq!(field.at((-20.0, 0.0)), None);
+
+        let bytes = field.to_bytes();
+        assert_eq!(bytes.len(), 1600);
+        assert_eq!(bytes[0], 1);
+        assert!(bytes.iter().any(|&b| b > 128));
+    }
+
+    #[test]
+    fn empty_recordings_are_far_from_everything() {
+        let field = SdfConfig::new().resolution(4, 2).field(&Recorder::new());
+        assert!(field.to_bytes().iter().all(|&b| b == 1));
+    }
+}
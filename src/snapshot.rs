@@ -0,0 +1,130 @@
+use crate::geometry::{Recorder, Segment};
+use crate::preset::Preset;
+
+/// Coordinates are rounded to multiples of this before hashing, so tiny floating point
+/// differences between platforms don't change the hash.
+pub const QUANTUM: f32 = 1e-3;
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+struct Fnv {
+    hash: u64,
+    exact: bool,
+}
+
+impl Fnv {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.hash ^= u64::from(*byte);
+            self.hash = self.hash.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn write_coord(&mut self, value: f32) {
+        if self.exact {
+            // adding 0.0 turns -0.0 into 0.0, so they hash the same
+            self.write(&(value + 0.0).to_bits().to_le_bytes());
+            return;
+        }
+        // integers have no negative zero, so -0.0 and 0.0 hash the same
+        let quantized = (value / QUANTUM).round() as i64;
+        self.write(&quantized.to_le_bytes());
+    }
+}
+
+/// A hash of the geometry that only depends on the quantized segment coordinates and colors in
+/// drawing order. It is the same on every platform and Rust version.
+pub fn geometry_hash(segments: &[Segment]) -> u64 {
+    hash(segments, false)
+}
+
+fn hash(segments: &[Segment], exact: bool) -> u64 {
+    let mut hasher = Fnv {
+        hash: FNV_OFFSET,
+        exact,
+    };
+    for segment in segments {
+        hasher.write_coord(segment.start.0);
+        hasher.write_coord(segment.start.1);
+        hasher.write_coord(segment.end.0);
+        hasher.write_coord(segment.end.1);
+        match segment.color {
+            Some(color) => hasher.write(&[1, color.r, color.g, color.b]),
+            None => hasher.write(&[0]),
+        }
+        // opaque lines hash as they did before segments had an alpha
+        if segment.alpha != 1.0 {
+            hasher.write_coord(segment.alpha);
+        }
+    }
+    hasher.hash
+}
+
+/// Derives `preset` `iterations` times, draws it with unit steps and hashes the result.
+pub fn render_hash(preset: Preset, iterations: usize) -> u64 {
+    let word = preset.lsystem().nth(iterations).unwrap();
+    let mut recorder = Recorder::new();
+    preset
+        .turtle()
+        .create_turtle()
+        .draw(&mut recorder, word)
+        .unwrap();
+    geometry_hash(recorder.segments())
+}
+
+/// Like `render_hash`, but drawn in fixed-point mode and hashed without quantizing, since the
+/// geometry itself is then the same on every platform and not just close enough to quantize
+/// the same.
+pub fn fixed_render_hash(preset: Preset, iterations: usize) -> u64 {
+    let word = preset.lsystem().nth(iterations).unwrap();
+    let mut recorder = Recorder::new();
+    preset
+        .turtle()
+        .fixed_point(true)
+        .create_turtle()
+        .draw(&mut recorder, word)
+        .unwrap();
+    hash(recorder.segments(), true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preset;
+
+    #[test]
+    fn hash_is_stable() {
+        assert_eq!(render_hash(preset::KOCH, 3), render_hash(preset::KOCH, 3));
+        assert_ne!(render_hash(preset::KOCH, 3), render_hash(preset::KOCH, 4));
+        assert_ne!(render_hash(preset::KOCH, 3), render_hash(preset::DRAGON, 3));
+    }
+
+    #[test]
+    fn tiny_differences_are_ignored() {
+        let segment = |x| Segment {
+            start: (0.0, -0.0),
+            end: (x, 1.0),
+            color: None,
+            alpha: 1.0,
+        };
+        assert_eq!(
+            geometry_hash(&[segment(1.0)]),
+            geometry_hash(&[segment(1.0 + 1e-6)])
+        );
+        assert_ne!(
+            geometry_hash(&[segment(1.0)]),
+            geometry_hash(&[segment(1.01)])
+        );
+    }
+
+    #[test]
+    fn known_hash() {
+        assert_eq!(render_hash(preset::DRAGON, 6), 12883793503084443730);
+    }
+
+    #[test]
+    fn known_fixed_hash() {
+        assert_eq!(fixed_render_hash(preset::DRAGON, 6), 13842343057266492384);
+    }
+}
@@ -0,0 +1,148 @@
+use std::collections::BTreeMap;
+
+use crate::lsystem::Instruction;
+use crate::symbol::Symbol;
+use crate::trace::RuleApplication;
+
+/// Counts describing a word, for looking at how a grammar grows without drawing it. Created by
+/// `Word::stats`, or `WordStats::of` for any derived word.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WordStats {
+    /// How often each symbol occurs, modules counted as their symbol.
+    pub counts: BTreeMap<Symbol, usize>,
+    /// How many symbols are nested in as many branches as the index, the top level first.
+    pub depths: Vec<usize>,
+    /// How many symbols the word has, brackets left out.
+    pub length: usize,
+    /// How many branches the word has.
+    pub branches: usize,
+}
+
+impl WordStats {
+    pub fn of(instrs: &[Instruction]) -> Self {
+        let mut stats = Self::default();
+        stats.add(instrs, 0);
+        stats
+    }
+
+    fn add(&mut self, instrs: &[Instruction], depth: usize) {
+        for instr in instrs {
+            match instr {
+                Instruction::Symbol(s) | Instruction::Module(s, _) => {
+                    *self.counts.entry(s.clone()).or_default() += 1;
+                    if self.depths.len() <= depth {
+                        self.depths.resize(depth + 1, 0);
+                    }
+                    self.depths[depth] += 1;
+                    self.length += 1;
+                }
+                Instruction::Branch(inner) => {
+                    self.branches += 1;
+                    self.add(inner, depth + 1);
+                }
+            }
+        }
+    }
+
+    /// The deepest symbol's number of enclosing branches, 0 for an empty word.
+    pub fn max_depth(&self) -> usize {
+        self.depths.len().saturating_sub(1)
+    }
+}
+
+/// How many times every rule fired since counting was turned on with `LSystem::set_counting`,
+/// e.g. to find the rules that dominate a grammar's growth.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DerivationStats {
+    /// By the index of the rule among the grammar's own rules, one count for each.
+    pub fired: Vec<usize>,
+    /// The same for the rules of each table that was in use, by its name.
+    pub tables: BTreeMap<String, Vec<usize>>,
+    /// How many rewrites were counted.
+    pub rewrites: usize,
+}
+
+impl DerivationStats {
+    // none fired yet of the grammar's `rules`
+    pub(crate) fn new(rules: usize) -> Self {
+        Self {
+            fired: vec![0; rules],
+            ..Self::default()
+        }
+    }
+
+    /// How many times rules fired, in all tables.
+    pub fn total(&self) -> usize {
+        let tables = self.tables.values().flatten();
+        self.fired.iter().chain(tables).sum()
+    }
+
+    /// The grammar's own rule that fired the most, the first among equals.
+    pub fn dominant(&self) -> Option<usize> {
+        let most = *self.fired.iter().max().filter(|&&most| most > 0)?;
+        self.fired.iter().position(|&fired| fired == most)
+    }
+
+    // a rewrite with `rules`, the rules of `table` or else the grammar's own
+    pub(crate) fn record(
+        &mut self,
+        table: Option<&str>,
+        rules: usize,
+        applications: &[RuleApplication],
+    ) {
+        let fired = match table {
+            Some(table) => self.tables.entry(table.to_string()).or_default(),
+            None => &mut self.fired,
+        };
+        if fired.len() < rules {
+            fired.resize(rules, 0);
+        }
+        for application in applications {
+            fired[application.rule] += 1;
+        }
+        self.rewrites += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lsystem::{LSystem, Rule, Word};
+
+    #[test]
+    fn symbols_are_counted_by_depth() {
+        let stats = Word::parse("F[+F[-G]]F(2)").unwrap().stats();
+        assert_eq!(stats.length, 6);
+        assert_eq!(stats.branches, 2);
+        assert_eq!(stats.counts[&'F'.into()], 3);
+        assert_eq!(stats.counts[&'G'.into()], 1);
+        assert_eq!(stats.depths, [2, 2, 2]);
+        assert_eq!(stats.max_depth(), 2);
+        assert_eq!(WordStats::of(&[]), WordStats::default());
+    }
+
+    #[test]
+    fn rules_are_counted_as_they_fire() {
+        let mut lsys = LSystem::from_str("A; A -> AB; B -> A; C -> C;").unwrap();
+        lsys.set_counting(true);
+        lsys.nth(4);
+        let stats = lsys.derivation_stats().unwrap();
+        // A, AB, ABA, ABAAB and ABAABABA are rewritten
+        assert_eq!(stats.fired, [12, 7, 0]);
+        assert_eq!(stats.rewrites, 5);
+        assert_eq!(stats.dominant(), Some(0));
+
+        lsys.add_table("other", Rule::parse_all("B -> C;").unwrap());
+        lsys.set_table(Some("other")).unwrap();
+        lsys.next();
+        let stats = lsys.derivation_stats().unwrap();
+        assert_eq!(stats.tables["other"], [5]);
+        assert_eq!(stats.total(), 24);
+
+        lsys.reset();
+        assert_eq!(lsys.derivation_stats(), Some(&DerivationStats::new(3)));
+        lsys.set_counting(false);
+        assert!(lsys.derivation_stats().is_none());
+    }
+}
@@ -0,0 +1,169 @@
+use std::fmt;
+use std::hash::Hash;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+/// One symbol of a word: a single extended grapheme cluster, so `🌱`, `é` written with a
+/// combining accent, or `👍🏽` each count as one symbol, or a name like `Apex`, written `$Apex`
+/// in grammars.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(Repr);
+
+// most symbols are a single char, which is kept inline; a cluster is only ever used for more
+// than one char so every grapheme has exactly one representation
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum Repr {
+    Char(char),
+    Cluster(Box<str>),
+    Name(Box<str>),
+}
+
+impl Symbol {
+    /// `grapheme` is expected to be a single grapheme cluster, as the parser produces.
+    pub fn new(grapheme: &str) -> Self {
+        let mut chars = grapheme.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Self(Repr::Char(c)),
+            _ => Self(Repr::Cluster(grapheme.into())),
+        }
+    }
+
+    /// `name` is expected to be an identifier of two letters or more, like `Apex` or `leaf_2`;
+    /// a name of one letter is that letter's symbol, as `$F` is `$` and `F` in a grammar.
+    pub fn named(name: &str) -> Self {
+        match name.len() {
+            1 => Self::new(name),
+            _ => Self(Repr::Name(name.into())),
+        }
+    }
+
+    pub fn as_char(&self) -> Option<char> {
+        match self.0 {
+            Repr::Char(c) => Some(c),
+            Repr::Cluster(_) | Repr::Name(_) => None,
+        }
+    }
+
+    pub fn as_name(&self) -> Option<&str> {
+        match &self.0 {
+            Repr::Name(name) => Some(name),
+            Repr::Char(_) | Repr::Cluster(_) => None,
+        }
+    }
+
+    pub fn matches(&self, grapheme: &str) -> bool {
+        match &self.0 {
+            Repr::Char(c) => {
+                let mut chars = grapheme.chars();
+                chars.next() == Some(*c) && chars.next().is_none()
+            }
+            Repr::Cluster(cluster) => **cluster == *grapheme,
+            Repr::Name(_) => false,
+        }
+    }
+
+    /// Whether this is one of the graphemes of `set`, e.g. a turtle's `"FG"`.
+    pub fn is_in(&self, set: &str) -> bool {
+        match self.0 {
+            Repr::Char(c) if set.is_ascii() => set.contains(c),
+            _ => set.graphemes(true).any(|grapheme| self.matches(grapheme)),
+        }
+    }
+}
+
+/// What the words of an `LSystem` are made of: `Symbol` for grammars that are parsed, or any
+/// type of one's own, like an enum of plant organs, for systems built in code. Only `Symbol`s
+/// are drawn, cut branches with `%` and answer an environment's queries.
+pub trait Letter: Clone + Eq + Hash + fmt::Debug {
+    fn as_symbol(&self) -> Option<&Symbol> {
+        None
+    }
+}
+
+impl Letter for Symbol {
+    fn as_symbol(&self) -> Option<&Symbol> {
+        Some(self)
+    }
+}
+
+impl From<char> for Symbol {
+    fn from(c: char) -> Self {
+        Self(Repr::Char(c))
+    }
+}
+
+impl PartialEq<char> for Symbol {
+    fn eq(&self, other: &char) -> bool {
+        self.as_char() == Some(*other)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.0 {
+            Repr::Char(c) => write!(f, "{}", c),
+            Repr::Cluster(cluster) => write!(f, "{}", cluster),
+            Repr::Name(name) => write!(f, "${}", name),
+        }
+    }
+}
+
+// written as the grapheme it is, so stored systems stay readable
+#[cfg(feature = "serde")]
+impl serde::Serialize for Symbol {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Symbol {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let grapheme = String::deserialize(deserializer)?;
+        match grapheme.strip_prefix('$') {
+            Some(name) if name.len() > 1 && is_identifier(name) => Ok(Symbol::named(name)),
+            _ if grapheme.graphemes(true).count() == 1 => Ok(Symbol::new(&grapheme)),
+            _ => Err(serde::de::Error::custom(format!(
+                "'{}' is neither a single grapheme nor a name",
+                grapheme
+            ))),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+fn is_identifier(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_chars_are_chars() {
+        assert_eq!(Symbol::new("F"), Symbol::from('F'));
+        assert_eq!(Symbol::new("🌱"), '🌱');
+        assert_eq!(Symbol::new("e\u{301}").as_char(), None);
+    }
+
+    #[test]
+    fn names_are_told_from_graphemes() {
+        let apex = Symbol::named("Apex");
+        assert_eq!(apex.as_name(), Some("Apex"));
+        assert_eq!(apex.to_string(), "$Apex");
+        assert!(!apex.is_in("Apex"));
+        assert_eq!(Symbol::named("A"), 'A');
+    }
+
+    #[test]
+    fn membership_is_per_grapheme() {
+        let accented = Symbol::new("e\u{301}");
+        assert!(accented.is_in("Fe\u{301}"));
+        assert!(!Symbol::from('e').is_in("Fe\u{301}"));
+        assert!(Symbol::from('G').is_in("FG"));
+        assert!(Symbol::new("👍🏽").is_in("F👍🏽"));
+        assert!(!Symbol::from('👍').is_in("F👍🏽"));
+    }
+}
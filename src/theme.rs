@@ -0,0 +1,89 @@
+use crate::palette::{Color, Palette};
+
+/// A background, a stroke for lines without a color of their own, and a palette for the ones
+/// with, chosen to go together.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Theme {
+    pub name: &'static str,
+    pub background: Color,
+    pub stroke: Color,
+    pub palette: &'static [Color],
+}
+
+pub const LIGHT: Theme = Theme {
+    name: "light",
+    background: Color::new(255, 255, 255),
+    stroke: Color::new(0, 0, 0),
+    palette: &[
+        Color::new(38, 70, 83),
+        Color::new(42, 157, 143),
+        Color::new(233, 196, 106),
+        Color::new(231, 111, 81),
+    ],
+};
+
+pub const DARK: Theme = Theme {
+    name: "dark",
+    background: Color::new(18, 18, 24),
+    stroke: Color::new(230, 230, 230),
+    palette: &[
+        Color::new(129, 236, 236),
+        Color::new(116, 185, 255),
+        Color::new(162, 155, 254),
+        Color::new(253, 121, 168),
+    ],
+};
+
+pub const BLUEPRINT: Theme = Theme {
+    name: "blueprint",
+    background: Color::new(16, 58, 112),
+    stroke: Color::new(235, 244, 255),
+    palette: &[
+        Color::new(235, 244, 255),
+        Color::new(160, 200, 240),
+        Color::new(255, 255, 160),
+    ],
+};
+
+pub const SEPIA: Theme = Theme {
+    name: "sepia",
+    background: Color::new(244, 236, 216),
+    stroke: Color::new(92, 64, 40),
+    palette: &[
+        Color::new(92, 64, 40),
+        Color::new(140, 100, 60),
+        Color::new(180, 130, 80),
+        Color::new(120, 110, 70),
+    ],
+};
+
+pub const ALL: &[Theme] = &[LIGHT, DARK, BLUEPRINT, SEPIA];
+
+impl Theme {
+    pub fn by_name(name: &str) -> Option<Self> {
+        ALL.iter().copied().find(|theme| theme.name == name)
+    }
+
+    pub fn palette(&self) -> Palette {
+        Palette::new(self.name, self.palette.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn themes_are_readable() {
+        let luma = |c: Color| 0.299 * c.r as f32 + 0.587 * c.g as f32 + 0.114 * c.b as f32;
+        for theme in ALL {
+            assert_eq!(Theme::by_name(theme.name), Some(*theme));
+            assert!(!theme.palette.is_empty());
+            let background = luma(theme.background);
+            assert!((background - luma(theme.stroke)).abs() > 100.0);
+            for &color in theme.palette {
+                assert!((background - luma(color)).abs() > 50.0, "{}", theme.name);
+            }
+        }
+    }
+}
@@ -0,0 +1,280 @@
+use crate::lsystem::{count_symbols, Instruction, Instructions};
+use crate::symbol::Symbol;
+
+/// Which rule rewrote which symbol, for `LSystem::set_tracing`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RuleApplication {
+    /// The symbol's place among the symbols of the word rewritten, counted in reading order
+    /// through branches, brackets left out.
+    pub position: usize,
+    /// The rule's place in the table of rules in use, the grammar's own unless `table` says
+    /// otherwise.
+    pub rule: usize,
+}
+
+/// One rewrite of a traced system.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TraceStep {
+    /// The generation of the word rewritten.
+    pub generation: usize,
+    /// The rule table in use, `None` for the grammar's own rules.
+    pub table: Option<String>,
+    /// In order of position; symbols no rule matched are left out.
+    pub applications: Vec<RuleApplication>,
+}
+
+/// Every rewrite since tracing was turned on, e.g. to find out why a grammar grows the way it
+/// does, or to animate a derivation rule by rule.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DerivationTrace {
+    pub(crate) steps: Vec<TraceStep>,
+}
+
+impl DerivationTrace {
+    pub fn steps(&self) -> &[TraceStep] {
+        &self.steps
+    }
+}
+
+/// What a rule made of one symbol, for `LSystem::replay`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Production<S = Symbol> {
+    /// The symbol's place in the word rewritten, counted like `RuleApplication::position`.
+    pub position: usize,
+    /// The place of the first symbol produced in the new word, counted the same way.
+    pub produced_at: usize,
+    pub rule: usize,
+    pub from: Instruction<S>,
+    /// What the rule produced, after any dropout.
+    pub to: Instructions<S>,
+}
+
+/// One rewrite of a replayed system: the word before and after it, and every production in
+/// reading order, the symbols between them having been kept as they were.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayStep<S = Symbol> {
+    /// The generation of the word rewritten.
+    pub generation: usize,
+    pub before: Instructions<S>,
+    pub after: Instructions<S>,
+    pub productions: Vec<Production<S>>,
+}
+
+// counts positions while a word is rewritten, and carries the generations symbols were
+// produced in over to the new word
+pub(crate) struct Tracer<'c, S = Symbol> {
+    pub(crate) position: usize,
+    pub(crate) applications: Vec<RuleApplication>,
+    pub(crate) births: Option<Vec<usize>>,
+    pub(crate) born: Vec<usize>,
+    pub(crate) generation: usize,
+    pub(crate) productions: Option<Vec<Production<S>>>,
+    // symbols of the new word so far
+    pub(crate) produced: usize,
+    // how often every symbol of the word was rewritten past the maximum depth, and the same for
+    // the new word
+    pub(crate) rewrites: Option<Vec<usize>>,
+    pub(crate) counted: Vec<usize>,
+    // the keys of the word's branches in reading order, with per branch streams, the place of
+    // the next one, and the keys of the new word's branches
+    pub(crate) keys: Option<Vec<u64>>,
+    pub(crate) branch: usize,
+    pub(crate) keyed: Vec<u64>,
+    // asked every so many instructions whether to go on, and what it said last
+    pub(crate) should_continue: Option<&'c mut dyn FnMut() -> bool>,
+    pub(crate) visited: usize,
+    pub(crate) cancelled: bool,
+}
+
+impl<S> Default for Tracer<'_, S> {
+    fn default() -> Self {
+        Self {
+            position: 0,
+            applications: vec![],
+            births: None,
+            born: vec![],
+            generation: 0,
+            productions: None,
+            produced: 0,
+            rewrites: None,
+            counted: vec![],
+            keys: None,
+            branch: 0,
+            keyed: vec![],
+            should_continue: None,
+            visited: 0,
+            cancelled: false,
+        }
+    }
+}
+
+impl<S: Clone> Tracer<'_, S> {
+    // whether to give up on the word before the next instruction, asking every `CHUNK` of them
+    pub(crate) fn cancelled(&mut self) -> bool {
+        const CHUNK: usize = 1024;
+
+        self.visited += 1;
+        if let Some(should_continue) = &mut self.should_continue {
+            if !self.cancelled && self.visited.is_multiple_of(CHUNK) {
+                self.cancelled = !should_continue();
+            }
+        }
+        self.cancelled
+    }
+
+    // the next `count` symbols are left as they are
+    pub(crate) fn keep(&mut self, count: usize) {
+        if let Some(births) = &self.births {
+            let kept = &births[self.position..self.position + count];
+            self.born.extend_from_slice(kept);
+        }
+        if let Some(rewrites) = &self.rewrites {
+            let kept = &rewrites[self.position..self.position + count];
+            self.counted.extend_from_slice(kept);
+        }
+        self.position += count;
+        self.produced += count;
+    }
+
+    // the next branch is rewritten in place, under the key it had
+    pub(crate) fn enter_branch(&mut self) -> Option<u64> {
+        let key = *self.keys.as_ref()?.get(self.branch)?;
+        self.branch += 1;
+        self.keyed.push(key);
+        Some(key)
+    }
+
+    // the next `count` branches are left as they are
+    pub(crate) fn keep_branches(&mut self, count: usize) {
+        if let Some(keys) = &self.keys {
+            let kept = keys
+                .get(self.branch..self.branch + count)
+                .unwrap_or_default();
+            self.keyed.extend_from_slice(kept);
+        }
+        self.branch += count;
+    }
+
+    // the next `count` branches are cut off
+    pub(crate) fn drop_branches(&mut self, count: usize) {
+        self.branch += count;
+    }
+
+    // the branches a rule just produced
+    pub(crate) fn produce_branches(&mut self, keys: impl Iterator<Item = u64>) {
+        if self.keys.is_some() {
+            self.keyed.extend(keys);
+        }
+    }
+
+    // how often the next symbol was rewritten past the maximum depth
+    pub(crate) fn rewrites(&self) -> usize {
+        self.rewrites
+            .as_ref()
+            .map_or(0, |rewrites| rewrites[self.position])
+    }
+
+    // the next symbol, `from`, is rewritten by `rule` into `to`, `deep` past the maximum depth
+    pub(crate) fn rewrite(
+        &mut self,
+        rule: usize,
+        from: &Instruction<S>,
+        to: &[Instruction<S>],
+        deep: bool,
+    ) {
+        self.applications.push(RuleApplication {
+            position: self.position,
+            rule,
+        });
+        let symbols = count_symbols(to);
+        if self.births.is_some() {
+            self.born
+                .extend(std::iter::repeat_n(self.generation, symbols));
+        }
+        if self.rewrites.is_some() {
+            let rewrites = if deep { self.rewrites() + 1 } else { 0 };
+            self.counted.extend(std::iter::repeat_n(rewrites, symbols));
+        }
+        if let Some(productions) = &mut self.productions {
+            productions.push(Production {
+                position: self.position,
+                produced_at: self.produced,
+                rule,
+                from: from.clone(),
+                to: to.to_vec(),
+            });
+        }
+        self.position += 1;
+        self.produced += symbols;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lsystem::{Instruction, LSystem, Rule};
+
+    #[test]
+    fn rewrites_are_recorded() {
+        let mut lsys = LSystem::from_str("A[B]A; A -> AB; B -> A; C -> C;").unwrap();
+        lsys.set_tracing(true);
+        lsys.nth(1);
+
+        let trace = lsys.trace().unwrap();
+        let applied: Vec<Vec<(usize, usize)>> = trace
+            .steps()
+            .iter()
+            .map(|step| {
+                step.applications
+                    .iter()
+                    .map(|a| (a.position, a.rule))
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            applied,
+            [
+                vec![(0, 0), (1, 1), (2, 0)],
+                vec![(0, 0), (1, 1), (2, 0), (3, 0), (4, 1)]
+            ]
+        );
+        assert_eq!(trace.steps()[1].generation, 1);
+
+        lsys.add_table("other", Rule::parse_all("B -> C;").unwrap());
+        lsys.set_table(Some("other")).unwrap();
+        lsys.next();
+        let last = lsys.trace().unwrap().steps().last().unwrap();
+        assert_eq!(last.table.as_deref(), Some("other"));
+
+        lsys.set_tracing(false);
+        assert!(lsys.trace().is_none());
+    }
+
+    #[test]
+    fn replays_show_what_each_rule_produced() {
+        let lsys = LSystem::from_str("A[B]C; A -> AB; B -> A;").unwrap();
+        let steps: Vec<_> = lsys.clone().replay().take(3).collect();
+        let mut rewritten = lsys.clone();
+        for (generation, step) in steps.iter().enumerate() {
+            assert_eq!(step.generation, generation);
+            assert_eq!(Some(step.before.clone()), rewritten.next());
+            assert_eq!(&step.after, rewritten.word());
+        }
+
+        let first: Vec<_> = steps[0]
+            .productions
+            .iter()
+            .map(|p| (p.position, p.produced_at, p.rule, p.to.len()))
+            .collect();
+        assert_eq!(first, [(0, 0, 0, 2), (1, 2, 1, 1)]);
+        assert_eq!(
+            steps[0].productions[1].from,
+            Instruction::Symbol('B'.into())
+        );
+        // AB[A]C becomes A B A [A B] C
+        let second: Vec<_> = steps[1].productions.iter().map(|p| p.produced_at).collect();
+        assert_eq!(second, [0, 2, 3]);
+    }
+}
@@ -0,0 +1,260 @@
+use std::collections::HashMap;
+
+use crate::geometry::Bounds;
+use crate::graphics::{Graphics, Step, TurtleConfig};
+use crate::lsystem::{Instruction, Instructions, LSystem, Symbol};
+
+#[derive(Debug, Clone, Copy)]
+struct State {
+    x: f32,
+    y: f32,
+    angle: f32,
+}
+
+impl State {
+    // `local` is a displacement and turn relative to this state's position and heading
+    fn then(self, local: State) -> State {
+        let (sin, cos) = self.angle.sin_cos();
+        State {
+            x: self.x + local.x * cos - local.y * sin,
+            y: self.y + local.x * sin + local.y * cos,
+            angle: self.angle + local.angle,
+        }
+    }
+}
+
+const START: State = State {
+    x: 0.0,
+    y: 0.0,
+    angle: 0.0,
+};
+
+/// Draws a system `depth` generations on from its current word, but only expands the parts of
+/// it that can reach `viewport`; everything else is skipped over without being derived. Deep
+/// zooms into self-similar figures then cost about as much as the detail that is visible.
+///
+/// Only steps, turns and branches are interpreted, each symbol always rewrites the same way, and
+/// the step length is the turtle's at every depth, so the turtle's step should already be scaled
+/// for `depth`. Modules act as their plain symbols and parametric rules are ignored.
+pub struct Zoom<'t, 'a, 'b, 'c, 'd> {
+    turtle: &'t TurtleConfig<'a, 'b, 'c, 'd>,
+    successors: HashMap<Symbol, Instructions>,
+    viewport: Bounds,
+    // how far from its start a symbol expanded n more times can draw, and where it leaves the
+    // turtle, by symbol and n
+    reach: HashMap<(Symbol, usize), f32>,
+    moves: HashMap<(Symbol, usize), State>,
+}
+
+impl<'t, 'a, 'b, 'c, 'd> Zoom<'t, 'a, 'b, 'c, 'd> {
+    pub fn new(
+        lsystem: &LSystem,
+        turtle: &'t TurtleConfig<'a, 'b, 'c, 'd>,
+        viewport: Bounds,
+    ) -> Self {
+        Self {
+            turtle,
+            successors: lsystem.successors(),
+            viewport,
+            reach: HashMap::new(),
+            moves: HashMap::new(),
+        }
+    }
+
+    pub fn draw<G, R>(
+        &mut self,
+        graphics: &mut G,
+        word: &[Instruction],
+        depth: usize,
+    ) -> Result<(), R>
+    where
+        G: Graphics<R>,
+    {
+        let mut state = START;
+        self.sequence(graphics, word, depth, &mut state)
+    }
+
+    fn sequence<G, R>(
+        &mut self,
+        graphics: &mut G,
+        instructions: &[Instruction],
+        depth: usize,
+        state: &mut State,
+    ) -> Result<(), R>
+    where
+        G: Graphics<R>,
+    {
+        for instruction in instructions {
+            match instruction {
+                Instruction::Symbol(s) | Instruction::Module(s, _) => {
+                    self.symbol(graphics, s, depth, state)?
+                }
+                Instruction::Branch(inner) => {
+                    let mut branch = *state;
+                    self.sequence(graphics, inner, depth, &mut branch)?
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn symbol<G, R>(
+        &mut self,
+        graphics: &mut G,
+        symbol: &Symbol,
+        depth: usize,
+        state: &mut State,
+    ) -> Result<(), R>
+    where
+        G: Graphics<R>,
+    {
+        let successor = match self.successors.get(symbol) {
+            Some(successor) if depth > 0 => successor.clone(),
+            _ => return self.primitive(graphics, symbol, state),
+        };
+
+        let reach = self.reach(symbol, depth);
+        if self.visible(*state, reach) {
+            self.sequence(graphics, &successor, depth - 1, state)
+        } else {
+            *state = state.then(self.moves(symbol, depth));
+            Ok(())
+        }
+    }
+
+    fn primitive<G, R>(&self, graphics: &mut G, symbol: &Symbol, state: &mut State) -> Result<(), R>
+    where
+        G: Graphics<R>,
+    {
+        let before = *state;
+        *state = state.then(self.primitive_move(symbol));
+        match self.turtle.classify(symbol) {
+            Some(Step::DrawForward | Step::DrawBackward) => {
+                graphics.draw_line((before.x, before.y), (state.x, state.y))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn primitive_move(&self, symbol: &Symbol) -> State {
+        let step = self.turtle.stepsize;
+        let (x, angle) = match self.turtle.classify(symbol) {
+            _ if *symbol == '+' => (0.0, -self.turtle.delta_ang),
+            _ if *symbol == '-' => (0.0, self.turtle.delta_ang),
+            Some(Step::Forward | Step::DrawForward) => (step, 0.0),
+            Some(Step::Backward | Step::DrawBackward) => (-step, 0.0),
+            None => (0.0, 0.0),
+        };
+        State { x, y: 0.0, angle }
+    }
+
+    fn visible(&self, state: State, reach: f32) -> bool {
+        let Bounds { min, max } = self.viewport;
+        let dx = (min.0 - state.x).max(0.0).max(state.x - max.0);
+        let dy = (min.1 - state.y).max(0.0).max(state.y - max.1);
+        dx * dx + dy * dy <= reach * reach
+    }
+
+    // every piece starts within the reach of the pieces before it, so the sum bounds them all
+    fn reach(&mut self, symbol: &Symbol, depth: usize) -> f32 {
+        if let Some(&reach) = self.reach.get(&(symbol.clone(), depth)) {
+            return reach;
+        }
+        let reach = match self.successors.get(symbol) {
+            Some(successor) if depth > 0 => {
+                let successor = successor.clone();
+                self.sequence_reach(&successor, depth - 1)
+            }
+            _ => {
+                let step = self.primitive_move(symbol);
+                f32::hypot(step.x, step.y)
+            }
+        };
+        self.reach.insert((symbol.clone(), depth), reach);
+        reach
+    }
+
+    fn sequence_reach(&mut self, instructions: &[Instruction], depth: usize) -> f32 {
+        instructions
+            .iter()
+            .map(|instruction| match instruction {
+                Instruction::Symbol(s) | Instruction::Module(s, _) => self.reach(s, depth),
+                Instruction::Branch(inner) => self.sequence_reach(inner, depth),
+            })
+            .sum()
+    }
+
+    fn moves(&mut self, symbol: &Symbol, depth: usize) -> State {
+        if let Some(&moves) = self.moves.get(&(symbol.clone(), depth)) {
+            return moves;
+        }
+        let moves = match self.successors.get(symbol) {
+            Some(successor) if depth > 0 => {
+                let successor = successor.clone();
+                let mut state = START;
+                for instruction in &successor {
+                    if let Instruction::Symbol(s) | Instruction::Module(s, _) = instruction {
+                        state = state.then(self.moves(s, depth - 1));
+                    }
+                }
+                state
+            }
+            _ => self.primitive_move(symbol),
+        };
+        self.moves.insert((symbol.clone(), depth), moves);
+        moves
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Recorder;
+
+    fn inside(viewport: &Bounds, (x, y): (f32, f32)) -> bool {
+        x >= viewport.min.0 && x <= viewport.max.0 && y >= viewport.min.1 && y <= viewport.max.1
+    }
+
+    #[test]
+    fn zoom_draws_what_is_visible() {
+        let koch = LSystem::from_str("F; F -> F+F--F+F;").unwrap();
+        let turtle = TurtleConfig::default()
+            .delta_ang(std::f32::consts::FRAC_PI_3)
+            .stepsize(1.0 / 3f32.powi(6));
+
+        let mut full = Recorder::new();
+        turtle
+            .create_turtle()
+            .draw(&mut full, koch.clone().nth(6).unwrap())
+            .unwrap();
+
+        let viewport = Bounds {
+            min: (0.3, -0.05),
+            max: (0.4, 0.05),
+        };
+        let mut zoomed = Recorder::new();
+        Zoom::new(&koch, &turtle, viewport)
+            .draw(&mut zoomed, koch.word(), 6)
+            .unwrap();
+
+        assert!(zoomed.segments().len() * 10 < full.segments().len());
+        let visible = |r: &Recorder| {
+            r.segments()
+                .iter()
+                .filter(|s| inside(&viewport, s.start) && inside(&viewport, s.end))
+                .count()
+        };
+        assert!(visible(&full) > 0);
+        assert_eq!(visible(&zoomed), visible(&full));
+
+        let everything = Bounds {
+            min: (-1.0, -1.0),
+            max: (2.0, 1.0),
+        };
+        let mut all = Recorder::new();
+        Zoom::new(&koch, &turtle, everything)
+            .draw(&mut all, koch.word(), 6)
+            .unwrap();
+        assert_eq!(all.segments().len(), full.segments().len());
+    }
+}